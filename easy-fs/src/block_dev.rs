@@ -5,4 +5,10 @@ use core::any::Any;
 pub trait BlockDevice : Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    /// Handle an interrupt of the device, for devices completing requests asynchronously
+    fn handle_irq(&self) {}
+    /// Number of blocks of the device, if it can tell
+    fn num_blocks(&self) -> Option<usize> {
+        None
+    }
 }
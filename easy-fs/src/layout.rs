@@ -78,6 +78,8 @@ impl SuperBlock {
 pub enum DiskInodeType {
     File,
     Directory,
+    /// Named pipe, its data only lives in memory
+    Fifo,
 }
 
 /// A indirect block
@@ -130,6 +132,10 @@ impl DiskInode {
     pub fn is_file(&self) -> bool {
         self.type_ == DiskInodeType::File
     }
+    /// Whether this inode is a named pipe
+    pub fn is_fifo(&self) -> bool {
+        self.type_ == DiskInodeType::Fifo
+    }
     /// Get the number of data blocks corresponding to size
     pub fn data_blocks(&self) -> u32 {
         Self::_data_blocks(self.size)
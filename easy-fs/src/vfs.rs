@@ -34,6 +34,8 @@ impl Inode {
         self.read_disk_inode(|disk_inode| {
             if disk_inode.is_dir() {
                 DiskInodeType::Directory
+            } else if disk_inode.is_fifo() {
+                DiskInodeType::Fifo
             } else {
                 DiskInodeType::File
             }
@@ -213,6 +215,17 @@ impl Inode {
     }
     /// Create inode under current inode by name
     pub fn create(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::File)
+    }
+    /// Create a named pipe under current inode by name
+    pub fn create_fifo(&self, name: &str) -> Option<Arc<Inode>> {
+        self.create_inode(name, DiskInodeType::Fifo)
+    }
+    /// Whether this inode is a named pipe
+    pub fn is_fifo(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_fifo())
+    }
+    fn create_inode(&self, name: &str, type_: DiskInodeType) -> Option<Arc<Inode>> {
         let mut fs = self.fs.lock();
         if self
             .modify_disk_inode(|root_inode| {
@@ -233,7 +246,7 @@ impl Inode {
         get_block_cache(new_inode_block_id as usize, Arc::clone(&self.block_device))
             .lock()
             .modify(new_inode_block_offset, |new_inode: &mut DiskInode| {
-                new_inode.initialize(DiskInodeType::File);
+                new_inode.initialize(type_);
             });
         self.modify_disk_inode(|root_inode| {
             // append file in the dirent
@@ -153,6 +153,7 @@ impl File for OSInode {
         let mode = match inner.inode.get_mode() {
             DiskInodeType::Directory => StatMode::DIR,
             DiskInodeType::File => StatMode::FILE,
+            DiskInodeType::Fifo => StatMode::FIFO,
         };
         let dentry = ROOT_INODE.get_dentry(ino as u32);
         let nlink = ROOT_INODE.find(dentry.name()).unwrap().get_nlink();
@@ -49,6 +49,8 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// named pipe
+        const FIFO  = 0o010000;
     }
 }
 
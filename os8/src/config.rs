@@ -7,8 +7,25 @@ pub const MEMORY_END: usize = 0x88000000;
 pub const PAGE_SIZE: usize = 0x1000;
 pub const PAGE_SIZE_BITS: usize = 0xc;
 pub const MAX_SYSCALL_NUM: usize = 500;
+/// Descriptors a process may have open at once
+pub const MAX_FD: usize = 128;
+/// Stride scheduling: a thread of priority `p` advances by `BIG_STRIDE / p` each time slice
+pub const BIG_STRIDE: usize = 1 << 32;
+
+/// Shared memory segments are attached from here on unless told otherwise,
+/// far from the user stacks above the program image
+pub const SHM_BASE: usize = 0x10_0000_0000;
+/// End of the lower half of the Sv39 address space, where user mappings go;
+/// the trampoline and the trap contexts are at the top of the upper half
+pub const USER_SPACE_END: usize = 1 << 38;
 
 pub const TRAMPOLINE: usize = usize::MAX - PAGE_SIZE + 1;
 pub const TRAP_CONTEXT: usize = TRAMPOLINE - PAGE_SIZE;
 pub const CLOCK_FREQ: usize = 12500000;
-pub const MMIO: &[(usize, usize)] = &[(0x10001000, 0x1000)];
+/// Device registers mapped in the kernel space: the PLIC, the UART and the
+/// virtio block device
+pub const MMIO: &[(usize, usize)] = &[
+    (0x0c00_0000, 0x21_0000),
+    (0x1000_0000, 0x1000),
+    (0x10001000, 0x1000),
+];
@@ -1,14 +1,14 @@
-//! SBI console driver, for text output
+//! Kernel text output, written straight to the UART
 
-use crate::sbi::console_putchar;
+use crate::drivers::UART;
 use core::fmt::{self, Write};
 
 struct Stdout;
 
 impl Write for Stdout {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
+        for byte in s.bytes() {
+            UART.write(byte);
         }
         Ok(())
     }
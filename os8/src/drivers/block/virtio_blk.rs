@@ -1,42 +1,116 @@
-
-use virtio_drivers::{VirtIOBlk, VirtIOHeader};
+use super::BlockDevice;
 use crate::mm::{
-    PhysAddr,
-    VirtAddr,
-    frame_alloc,
-    frame_dealloc,
-    PhysPageNum,
-    FrameTracker,
-    PageTable,
-    StepByOne,
-    kernel_token,
+    frame_alloc, frame_dealloc, kernel_token, FrameTracker, PageTable, PhysAddr, PhysPageNum,
+    StepByOne, VirtAddr,
 };
-use super::BlockDevice;
 use crate::sync::UPSafeCell;
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use easy_fs::BLOCK_SZ;
 use lazy_static::*;
+use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader};
 
 #[allow(unused)]
 const VIRTIO0: usize = 0x10001000;
+/// Offset of the configuration space of a virtio-mmio device, which starts
+/// with the capacity for virtio-blk
+const VIRTIO_CONFIG: usize = 0x100;
+
+/// Requests issued to the device at once, each takes 3 of its 16 descriptors
+const MAX_IN_FLIGHT: usize = 5;
+/// Writes queued beyond this make the writer wait for its own
+const MAX_QUEUED_WRITES: usize = 32;
+
+/// A virtio-blk device completing requests by interrupts
+///
+/// Requests wait in an elevator queue in front of the device. Writes are
+/// copied into the queue and complete in the background, reads put the task
+/// to sleep until the completion interrupt.
+pub struct VirtIOBlock(UPSafeCell<VirtIOBlockInner>);
+
+struct VirtIOBlockInner {
+    virtio_blk: VirtIOBlk<'static>,
+    /// Requests not issued yet, at most one per block
+    pending: BTreeMap<usize, RequestRef>,
+    /// Requests issued to the device, by the token it completes them with
+    in_flight: BTreeMap<u16, RequestRef>,
+    /// Block of the last issued request, the elevator sweeps up from there
+    head: usize,
+    /// Writes pending or in flight
+    queued_writes: usize,
+}
 
-pub struct VirtIOBlock(UPSafeCell<VirtIOBlk<'static>>);
+/// A request for one block, with a buffer of its own for the device
+struct Request {
+    block_id: usize,
+    write: bool,
+    buf: [u8; BLOCK_SZ],
+    resp: BlkResp,
+    done: bool,
+    /// Threads sleeping until it is done
+    waiters: Vec<Arc<TaskControlBlock>>,
+}
+
+type RequestRef = Arc<UPSafeCell<Request>>;
 
 lazy_static! {
-    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { 
-        UPSafeCell::new(Vec::new())
-    };
+    static ref QUEUE_FRAMES: UPSafeCell<Vec<FrameTracker>> = unsafe { UPSafeCell::new(Vec::new()) };
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0.exclusive_access()
-        .read_block(block_id, buf)
-        .expect("Error when reading VirtIOBlk");
+        let request = {
+            let mut inner = self.0.exclusive_access();
+            match inner.find(block_id) {
+                // the latest data of the block is on its way to the device
+                Some(request) if request.exclusive_access().write => {
+                    buf.copy_from_slice(&request.exclusive_access().buf);
+                    return;
+                }
+                // someone is reading it already
+                Some(request) => request,
+                None => inner.queue(block_id, false, None),
+            }
+        };
+        self.wait(&request);
+        buf.copy_from_slice(&request.exclusive_access().buf);
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0.exclusive_access()
-        .write_block(block_id, buf)
-        .expect("Error when writing VirtIOBlk");
+        let request = {
+            let mut inner = self.0.exclusive_access();
+            let request = match inner.pending.get(&block_id) {
+                Some(request) => {
+                    // merge into the request not issued yet, readers
+                    // waiting for it get the new data
+                    let mut req = request.exclusive_access();
+                    req.buf.copy_from_slice(buf);
+                    if !req.write {
+                        req.write = true;
+                        drop(req);
+                        inner.queued_writes += 1;
+                    }
+                    return;
+                }
+                None => inner.queue(block_id, true, Some(buf)),
+            };
+            if inner.queued_writes <= MAX_QUEUED_WRITES {
+                return;
+            }
+            request
+        };
+        self.wait(&request);
+    }
+    fn handle_irq(&self) {
+        let mut inner = self.0.exclusive_access();
+        inner.virtio_blk.ack_interrupt();
+        inner.complete();
+    }
+    fn num_blocks(&self) -> Option<usize> {
+        // in 512-byte sectors, which are blocks
+        let capacity = unsafe { ((VIRTIO0 + VIRTIO_CONFIG) as *const u64).read_volatile() };
+        Some(capacity as usize)
     }
 }
 
@@ -44,19 +118,154 @@ impl VirtIOBlock {
     #[allow(unused)]
     pub fn new() -> Self {
         unsafe {
-            Self(UPSafeCell::new(VirtIOBlk::new(
-                &mut *(VIRTIO0 as *mut VirtIOHeader)
-            ).unwrap()))
+            let virtio_blk = VirtIOBlk::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap();
+            Self(UPSafeCell::new(VirtIOBlockInner {
+                virtio_blk,
+                pending: BTreeMap::new(),
+                in_flight: BTreeMap::new(),
+                head: 0,
+                queued_writes: 0,
+            }))
+        }
+    }
+
+    /// Sleep until `request` is done
+    ///
+    /// Without a task to put to sleep, i.e. while booting, the device is
+    /// polled instead.
+    fn wait(&self, request: &RequestRef) {
+        loop {
+            let mut req = request.exclusive_access();
+            if req.done {
+                return;
+            }
+            match current_task() {
+                Some(task) => {
+                    req.waiters.push(task);
+                    drop(req);
+                    block_current_and_run_next();
+                }
+                None => {
+                    drop(req);
+                    self.0.exclusive_access().complete();
+                }
+            }
         }
     }
 }
 
+impl VirtIOBlockInner {
+    /// The latest request for `block_id` not done yet
+    fn find(&self, block_id: usize) -> Option<RequestRef> {
+        self.pending.get(&block_id).cloned().or_else(|| {
+            self.in_flight
+                .values()
+                .find(|request| request.exclusive_access().block_id == block_id)
+                .cloned()
+        })
+    }
+
+    fn queue(&mut self, block_id: usize, write: bool, data: Option<&[u8]>) -> RequestRef {
+        let mut buf = [0u8; BLOCK_SZ];
+        if let Some(data) = data {
+            buf.copy_from_slice(data);
+        }
+        let request = Arc::new(unsafe {
+            UPSafeCell::new(Request {
+                block_id,
+                write,
+                buf,
+                resp: BlkResp::default(),
+                done: false,
+                waiters: Vec::new(),
+            })
+        });
+        if write {
+            self.queued_writes += 1;
+        }
+        self.pending.insert(block_id, request.clone());
+        self.dispatch();
+        request
+    }
+
+    /// Issue pending requests while the device has room for them
+    ///
+    /// They are taken in ascending block order from the head, wrapping around
+    /// at the end. Blocks with a request in flight wait for it to complete,
+    /// since the device may complete requests in any order.
+    fn dispatch(&mut self) {
+        while self.in_flight.len() < MAX_IN_FLIGHT {
+            let busy: Vec<usize> = self
+                .in_flight
+                .values()
+                .map(|request| request.exclusive_access().block_id)
+                .collect();
+            let next = self
+                .pending
+                .range(self.head..)
+                .chain(self.pending.range(..self.head))
+                .map(|(&block_id, _)| block_id)
+                .find(|block_id| !busy.contains(block_id));
+            let block_id = match next {
+                Some(block_id) => block_id,
+                None => return,
+            };
+            let request = self.pending.remove(&block_id).unwrap();
+            let token = {
+                let mut req = request.exclusive_access();
+                let req = &mut *req;
+                // the buffers live in the request, which is kept in
+                // `in_flight` until the device is done with them
+                unsafe {
+                    if req.write {
+                        self.virtio_blk
+                            .write_block_nb(block_id, &req.buf, &mut req.resp)
+                    } else {
+                        self.virtio_blk
+                            .read_block_nb(block_id, &mut req.buf, &mut req.resp)
+                    }
+                }
+                .expect("Error when issuing a VirtIOBlk request")
+            };
+            self.head = block_id;
+            self.in_flight.insert(token, request);
+        }
+    }
+
+    /// Finish the requests completed by the device and issue the next ones
+    fn complete(&mut self) {
+        while let Ok(token) = self.virtio_blk.pop_used() {
+            let request = match self.in_flight.remove(&token) {
+                Some(request) => request,
+                None => continue,
+            };
+            let mut req = request.exclusive_access();
+            assert_eq!(
+                req.resp.status(),
+                RespStatus::Ok,
+                "Error when {} VirtIOBlk",
+                if req.write { "writing" } else { "reading" }
+            );
+            if req.write {
+                self.queued_writes -= 1;
+            }
+            req.done = true;
+            for task in req.waiters.drain(..) {
+                add_task(task);
+            }
+        }
+        self.dispatch();
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn virtio_dma_alloc(pages: usize) -> PhysAddr {
     let mut ppn_base = PhysPageNum(0);
     for i in 0..pages {
         let frame = frame_alloc().unwrap();
-        if i == 0 { ppn_base = frame.ppn; }
+        if i == 0 {
+            ppn_base = frame.ppn;
+        }
         assert_eq!(frame.ppn.0, ppn_base.0 + i);
         QUEUE_FRAMES.exclusive_access().push(frame);
     }
@@ -80,5 +289,7 @@ pub extern "C" fn virtio_phys_to_virt(paddr: PhysAddr) -> VirtAddr {
 
 #[no_mangle]
 pub extern "C" fn virtio_virt_to_phys(vaddr: VirtAddr) -> PhysAddr {
-    PageTable::from_token(kernel_token()).translate_va(vaddr).unwrap()
+    PageTable::from_token(kernel_token())
+        .translate_va(vaddr)
+        .unwrap()
 }
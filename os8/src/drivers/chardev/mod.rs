@@ -0,0 +1,9 @@
+mod ns16550a;
+
+use ns16550a::Ns16550a;
+
+/// UART of QEMU virt
+pub const VIRT_UART: usize = 0x1000_0000;
+
+/// The console, used without any lock since it keeps no state of its own
+pub static UART: Ns16550a = Ns16550a::new(VIRT_UART);
@@ -0,0 +1,74 @@
+//! NS16550A UART, as emulated by QEMU virt
+//!
+//! The baud rate is left as the SBI firmware set it up.
+
+/// Receive buffer, or transmit holding register when written
+const RBR_THR: usize = 0;
+/// Interrupt enable register
+const IER: usize = 1;
+/// FIFO control register
+const FCR: usize = 2;
+/// Line control register
+const LCR: usize = 3;
+/// Modem control register
+const MCR: usize = 4;
+/// Line status register
+const LSR: usize = 5;
+
+/// IER: interrupt when data was received
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+/// FCR: enable and clear both FIFOs
+const FCR_ENABLE_CLEAR: u8 = 0b111;
+/// LCR: 8 data bits, no parity, one stop bit
+const LCR_8N1: u8 = 0b11;
+/// MCR: data terminal ready, request to send, and OUT2 which gates the interrupt line
+const MCR_DTR_RTS_OUT2: u8 = 0b1011;
+/// LSR: a received byte is waiting
+const LSR_DATA_READY: u8 = 1 << 0;
+/// LSR: the transmit holding register takes a byte
+const LSR_THR_EMPTY: u8 = 1 << 5;
+
+pub struct Ns16550a {
+    base: usize,
+}
+
+impl Ns16550a {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { ((self.base + reg) as *const u8).read_volatile() }
+    }
+
+    fn write_reg(&self, reg: usize, value: u8) {
+        unsafe { ((self.base + reg) as *mut u8).write_volatile(value) }
+    }
+
+    /// Interrupt on every received byte
+    pub fn init(&self) {
+        self.write_reg(IER, 0);
+        self.write_reg(FCR, FCR_ENABLE_CLEAR);
+        self.write_reg(LCR, LCR_8N1);
+        self.write_reg(MCR, MCR_DTR_RTS_OUT2);
+        self.write_reg(IER, IER_RX_AVAILABLE);
+    }
+
+    /// Take a received byte, which also acknowledges the interrupt once
+    /// none is left
+    pub fn read(&self) -> Option<u8> {
+        if self.read_reg(LSR) & LSR_DATA_READY != 0 {
+            Some(self.read_reg(RBR_THR))
+        } else {
+            None
+        }
+    }
+
+    /// Send `byte`, waiting for room in the transmitter
+    pub fn write(&self, byte: u8) {
+        while self.read_reg(LSR) & LSR_THR_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+        self.write_reg(RBR_THR, byte);
+    }
+}
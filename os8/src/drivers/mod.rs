@@ -1,3 +1,40 @@
 mod block;
+mod chardev;
+mod plic;
 
 pub use block::BLOCK_DEVICE;
+pub use chardev::UART;
+
+use crate::fs::console_receive;
+use plic::{Plic, UART_IRQ, VIRTIO0_IRQ, VIRT_PLIC};
+use riscv::register::sie;
+
+static PLIC: Plic = Plic::new(VIRT_PLIC);
+
+/// Set up the console and let it and the disk interrupt the supervisor
+pub fn init() {
+    UART.init();
+    PLIC.set_threshold(0);
+    for irq in [UART_IRQ, VIRTIO0_IRQ] {
+        PLIC.set_priority(irq, 1);
+        PLIC.enable(irq);
+    }
+    unsafe {
+        sie::set_sext();
+    }
+}
+
+/// Handle the supervisor external interrupts raised by the devices
+///
+/// Interrupts are only taken in user mode, so the scheduler also calls this
+/// to catch the ones raised while the kernel runs.
+pub fn irq_handler() {
+    while let Some(irq) = PLIC.claim() {
+        match irq {
+            UART_IRQ => console_receive(),
+            VIRTIO0_IRQ => BLOCK_DEVICE.handle_irq(),
+            _ => panic!("unexpected interrupt {}", irq),
+        }
+        PLIC.complete(irq);
+    }
+}
@@ -0,0 +1,65 @@
+//! Platform-level interrupt controller, routing device interrupts to harts
+//!
+//! Only the supervisor mode context of hart 0 is used.
+
+/// PLIC of QEMU virt
+pub const VIRT_PLIC: usize = 0x0c00_0000;
+
+/// Interrupt source of the UART on QEMU virt
+pub const UART_IRQ: u32 = 10;
+
+/// Interrupt source of the first virtio device on QEMU virt, the disk
+pub const VIRTIO0_IRQ: u32 = 1;
+
+/// Context of the supervisor mode of hart 0
+const SUPERVISOR_CONTEXT: usize = 1;
+
+pub struct Plic {
+    base: usize,
+}
+
+impl Plic {
+    pub const fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn reg(&self, offset: usize) -> *mut u32 {
+        (self.base + offset) as *mut u32
+    }
+
+    /// Priority of source `irq`, 0 never interrupts
+    pub fn set_priority(&self, irq: u32, priority: u32) {
+        unsafe { self.reg(irq as usize * 4).write_volatile(priority) }
+    }
+
+    /// Let source `irq` interrupt the supervisor
+    pub fn enable(&self, irq: u32) {
+        let reg = self.reg(0x2000 + SUPERVISOR_CONTEXT * 0x80 + irq as usize / 32 * 4);
+        unsafe { reg.write_volatile(reg.read_volatile() | 1 << (irq % 32)) }
+    }
+
+    /// Only interrupts of a priority above `threshold` reach the supervisor
+    pub fn set_threshold(&self, threshold: u32) {
+        unsafe {
+            self.reg(0x20_0000 + SUPERVISOR_CONTEXT * 0x1000)
+                .write_volatile(threshold)
+        }
+    }
+
+    /// Take the pending interrupt of the highest priority, if any
+    pub fn claim(&self) -> Option<u32> {
+        let irq = unsafe {
+            self.reg(0x20_0004 + SUPERVISOR_CONTEXT * 0x1000)
+                .read_volatile()
+        };
+        (irq != 0).then_some(irq)
+    }
+
+    /// Tell the source of `irq` it was handled, so that it may interrupt again
+    pub fn complete(&self, irq: u32) {
+        unsafe {
+            self.reg(0x20_0004 + SUPERVISOR_CONTEXT * 0x1000)
+                .write_volatile(irq)
+        }
+    }
+}
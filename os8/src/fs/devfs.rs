@@ -0,0 +1,311 @@
+//! devfs, the device nodes of `/dev`
+//!
+//! Its nodes open the devices as files of their own instead of inodes:
+//! `null`, `zero`, `urandom`, the console as `tty` and the disk as `vda`.
+
+use super::tty::{tty_ioctl, tty_read, tty_readable, tty_register_poller, tty_write};
+use super::vfs::{alloc_dev, FileSystem, Inode, InodeType, FS_LOCK};
+use super::{File, OpenFlags, PollEvents, PollWaker};
+use crate::drivers::BLOCK_DEVICE;
+use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::timer::get_time;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, Ordering};
+use easy_fs::{block_cache_sync_all, get_block_cache, BLOCK_SZ};
+use lazy_static::*;
+
+/// A device with a node in devfs
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Device {
+    /// Reads nothing and takes whatever is written
+    Null,
+    /// Reads zeros and takes whatever is written
+    Zero,
+    /// Reads pseudo-random bytes and takes whatever is written
+    Urandom,
+    Tty,
+    Disk,
+}
+
+/// The nodes of devfs by name, numbered from 2 on in this order
+const DEVICES: &[(&str, Device)] = &[
+    ("null", Device::Null),
+    ("zero", Device::Zero),
+    ("urandom", Device::Urandom),
+    ("tty", Device::Tty),
+    ("vda", Device::Disk),
+];
+
+lazy_static! {
+    /// State of the xorshift generator behind `/dev/urandom`, which is no
+    /// good for cryptography
+    static ref RANDOM: UPSafeCell<u64> = unsafe { UPSafeCell::new(get_time() as u64 | 1) };
+}
+
+/// The device nodes, which are always there
+pub struct DevFs {
+    root: Arc<DevInode>,
+    dev: u64,
+}
+
+/// The root directory, or the node of a device
+struct DevInode {
+    ino: u64,
+    device: Option<Device>,
+}
+
+impl DevFs {
+    /// Make the nodes, there are no options
+    pub fn mount(_source: &str, _data: &str) -> Result<Arc<dyn FileSystem>, isize> {
+        Ok(Arc::new(Self {
+            root: Arc::new(DevInode {
+                ino: 1,
+                device: None,
+            }),
+            dev: alloc_dev(),
+        }))
+    }
+}
+
+impl FileSystem for DevFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+    fn dev(&self) -> u64 {
+        self.dev
+    }
+}
+
+impl Inode for DevInode {
+    fn type_(&self) -> InodeType {
+        match self.device {
+            None => InodeType::Directory,
+            Some(Device::Disk) => InodeType::BlockDevice,
+            Some(_) => InodeType::CharDevice,
+        }
+    }
+    fn ino(&self) -> u64 {
+        self.ino
+    }
+    // the data is in the devices, which are opened as files of their own
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    fn clear(&self) {}
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        if self.device.is_some() {
+            return None;
+        }
+        let index = DEVICES.iter().position(|(node, _)| *node == name)?;
+        Some(Arc::new(DevInode {
+            ino: index as u64 + 2,
+            device: Some(DEVICES[index].1),
+        }))
+    }
+    fn create(&self, _name: &str, _type_: InodeType) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    fn ls(&self) -> Vec<String> {
+        match self.device {
+            None => DEVICES
+                .iter()
+                .map(|(name, _)| String::from(*name))
+                .collect(),
+            Some(_) => Vec::new(),
+        }
+    }
+    fn link(&self, _name: &str, _ino: u64) -> bool {
+        false
+    }
+    fn unlink(&self, _name: &str) -> bool {
+        false
+    }
+    fn open_device(&self, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        let (readable, writable) = flags.read_write();
+        let file: Arc<dyn File + Send + Sync> = match self.device? {
+            Device::Tty => Arc::new(TtyDevice {
+                readable,
+                writable,
+                nonblock: AtomicBool::new(false),
+            }),
+            Device::Disk => Arc::new(DiskDevice {
+                readable,
+                writable,
+                offset: unsafe { UPSafeCell::new(0) },
+            }),
+            device => Arc::new(MemDevice {
+                device,
+                readable,
+                writable,
+            }),
+        };
+        Some(file)
+    }
+}
+
+/// `/dev/null`, `/dev/zero` or `/dev/urandom`, which never block
+struct MemDevice {
+    device: Device,
+    readable: bool,
+    writable: bool,
+}
+
+/// Next pseudo-random number of `/dev/urandom`
+fn random() -> u64 {
+    let mut state = RANDOM.exclusive_access();
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+impl File for MemDevice {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        for slice in buf.buffers.iter_mut() {
+            match self.device {
+                Device::Null => return 0,
+                Device::Urandom => {
+                    for chunk in slice.chunks_mut(8) {
+                        let bytes = random().to_le_bytes();
+                        chunk.copy_from_slice(&bytes[..chunk.len()]);
+                    }
+                }
+                _ => slice.fill(0),
+            }
+        }
+        buf.len()
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        buf.len()
+    }
+}
+
+/// `/dev/tty`, the console the standard streams are on
+struct TtyDevice {
+    readable: bool,
+    writable: bool,
+    nonblock: AtomicBool,
+}
+
+impl File for TtyDevice {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        match tty_read(buf, self.nonblocking()) {
+            Ok(read_size) => read_size,
+            Err(err) => err as usize,
+        }
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        tty_write(buf)
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        if self.readable && tty_readable() {
+            ready |= PollEvents::IN;
+        }
+        if self.writable {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    fn register_poller(&self, waker: &Arc<PollWaker>) {
+        tty_register_poller(waker);
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+}
+
+/// `/dev/vda`, the whole disk as one file
+///
+/// It goes through the block cache of easy-fs, so that it agrees with the
+/// files on the disk.
+struct DiskDevice {
+    readable: bool,
+    writable: bool,
+    offset: UPSafeCell<usize>,
+}
+
+/// Size of the disk in bytes
+fn disk_size() -> usize {
+    BLOCK_DEVICE.num_blocks().unwrap_or(0) * BLOCK_SZ
+}
+
+impl File for DiskDevice {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, mut buf: UserBuffer) -> usize {
+        let _fs = FS_LOCK.lock();
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        let size = disk_size();
+        for slice in buf.buffers.iter_mut() {
+            let mut copied = 0;
+            while copied < slice.len() && *offset < size {
+                let pos = *offset % BLOCK_SZ;
+                let len = (BLOCK_SZ - pos)
+                    .min(slice.len() - copied)
+                    .min(size - *offset);
+                get_block_cache(*offset / BLOCK_SZ, BLOCK_DEVICE.clone())
+                    .lock()
+                    .read(0, |block: &[u8; BLOCK_SZ]| {
+                        slice[copied..copied + len].copy_from_slice(&block[pos..pos + len]);
+                    });
+                copied += len;
+                *offset += len;
+            }
+        }
+        *offset - start
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let _fs = FS_LOCK.lock();
+        let mut offset = self.offset.exclusive_access();
+        let start = *offset;
+        let size = disk_size();
+        for slice in buf.buffers.iter() {
+            let mut copied = 0;
+            while copied < slice.len() && *offset < size {
+                let pos = *offset % BLOCK_SZ;
+                let len = (BLOCK_SZ - pos)
+                    .min(slice.len() - copied)
+                    .min(size - *offset);
+                get_block_cache(*offset / BLOCK_SZ, BLOCK_DEVICE.clone())
+                    .lock()
+                    .modify(0, |block: &mut [u8; BLOCK_SZ]| {
+                        block[pos..pos + len].copy_from_slice(&slice[copied..copied + len]);
+                    });
+                copied += len;
+                *offset += len;
+            }
+        }
+        block_cache_sync_all();
+        *offset - start
+    }
+}
@@ -0,0 +1,96 @@
+//! easy-fs on the virtio disk as the root filesystem
+
+use super::vfs::{alloc_dev, FileSystem, Inode, InodeType};
+use crate::drivers::BLOCK_DEVICE;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use easy_fs::{DirEntry, DiskInodeType, EasyFileSystem};
+
+/// easy-fs, whose root directory is the only one
+pub struct EasyFs {
+    root: Arc<easy_fs::Inode>,
+    dev: u64,
+}
+
+impl EasyFs {
+    /// Open the easy-fs image on the disk
+    pub fn open() -> Self {
+        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        Self {
+            root: Arc::new(EasyFileSystem::root_inode(&efs)),
+            dev: alloc_dev(),
+        }
+    }
+}
+
+impl FileSystem for EasyFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+    fn dev(&self) -> u64 {
+        self.dev
+    }
+}
+
+impl Inode for easy_fs::Inode {
+    fn type_(&self) -> InodeType {
+        match self.get_mode() {
+            DiskInodeType::File => InodeType::File,
+            DiskInodeType::Directory => InodeType::Directory,
+            DiskInodeType::Fifo => InodeType::Fifo,
+        }
+    }
+    fn ino(&self) -> u64 {
+        self.get_ino_from_pos()
+    }
+    fn nlink(&self) -> u32 {
+        self.get_nlink()
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        easy_fs::Inode::read_at(self, offset, buf)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        easy_fs::Inode::write_at(self, offset, buf)
+    }
+    fn clear(&self) {
+        easy_fs::Inode::clear(self)
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        easy_fs::Inode::find(self, name).map(|inode| inode as Arc<dyn Inode>)
+    }
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        let inode = match type_ {
+            InodeType::File => easy_fs::Inode::create(self, name),
+            InodeType::Fifo => self.create_fifo(name),
+            // there is only the root directory, and devices are in devfs
+            InodeType::Directory | InodeType::CharDevice | InodeType::BlockDevice => None,
+        };
+        inode.map(|inode| inode as Arc<dyn Inode>)
+    }
+    fn ls(&self) -> Vec<String> {
+        easy_fs::Inode::ls(self)
+    }
+    fn link(&self, name: &str, ino: u64) -> bool {
+        if self.get_ino(name).is_some() {
+            return false;
+        }
+        self.append_dir(DirEntry::new(name, ino as u32));
+        easy_fs::Inode::find(self, name).unwrap().increase_nlink();
+        true
+    }
+    fn unlink(&self, name: &str) -> bool {
+        let inode = match easy_fs::Inode::find(self, name) {
+            Some(inode) if inode.get_mode() != DiskInodeType::Directory => inode,
+            _ => return false,
+        };
+        // the data goes with the last name, even while the file is open,
+        // and the disk inode stays allocated as easy-fs never frees one
+        if inode.get_nlink() == 1 {
+            inode.clear();
+        }
+        inode.decrease_nlink();
+        self.remove_dir(name);
+        true
+    }
+}
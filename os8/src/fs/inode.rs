@@ -1,14 +1,10 @@
-use easy_fs::{
-    EasyFileSystem,
-    Inode,
-};
-use crate::drivers::BLOCK_DEVICE;
+use super::vfs::{lookup, lookup_parent, FileSystem, Inode, InodeType, FS_LOCK};
 use crate::sync::UPSafeCell;
 use alloc::sync::Arc;
-use lazy_static::*;
 use bitflags::*;
 use alloc::vec::Vec;
-use super::File;
+use super::{File, Stat, StatMode};
+use crate::syscall::errno::{EEXIST, EISDIR, ENOENT, ENOSPC, EPERM, EXDEV};
 use crate::mm::UserBuffer;
 
 /// A wrapper around a filesystem inode
@@ -17,12 +13,14 @@ pub struct OSInode {
     readable: bool,
     writable: bool,
     inner: UPSafeCell<OSInodeInner>,
+    /// Keeps the filesystem from being unmounted
+    fs: Arc<dyn FileSystem>,
 }
 
 /// The OS inode inner in 'UPSafeCell'
 pub struct OSInodeInner {
     offset: usize,
-    inode: Arc<Inode>,
+    inode: Arc<dyn Inode>,
 }
 
 impl OSInode {
@@ -30,7 +28,8 @@ impl OSInode {
     pub fn new(
         readable: bool,
         writable: bool,
-        inode: Arc<Inode>,
+        fs: Arc<dyn FileSystem>,
+        inode: Arc<dyn Inode>,
     ) -> Self {
         Self {
             readable,
@@ -39,10 +38,12 @@ impl OSInode {
                 offset: 0,
                 inode,
             })},
+            fs,
         }
     }
     /// Read all data inside a inode into vector
     pub fn read_all(&self) -> Vec<u8> {
+        let _fs = FS_LOCK.lock();
         let mut inner = self.inner.exclusive_access();
         let mut buffer = [0u8; 512];
         let mut v: Vec<u8> = Vec::new();
@@ -58,18 +59,12 @@ impl OSInode {
     }
 }
 
-lazy_static! {
-    /// The root of all inodes, or '/' in short
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
-}
-
 /// List all files in the filesystems
 pub fn list_apps() {
+    let _fs = FS_LOCK.lock();
     println!("/**** APPS ****");
-    for app in ROOT_INODE.ls() {
+    let (_, root) = lookup("/").unwrap();
+    for app in root.ls() {
         println!("{}", app);
     }
     println!("**************/");
@@ -83,6 +78,10 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        /// Reads and writes fail with `EAGAIN` instead of waiting
+        const NONBLOCK = 1 << 11;
+        /// The descriptor is closed by exec
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -101,48 +100,95 @@ impl OpenFlags {
     }
 }
 
+/// Device and inode number of `path` if it is a named pipe, which
+/// [`open_fifo`] opens
+///
+/// [`open_fifo`]: super::open_fifo
+pub fn fifo_ino(path: &str) -> Option<(u64, u64)> {
+    let _fs = FS_LOCK.lock();
+    lookup(path)
+        .filter(|(_, inode)| inode.type_() == InodeType::Fifo)
+        .map(|(fs, inode)| (fs.dev(), inode.ino()))
+}
+
+/// The device `path` is the node of, opened with `flags`
+pub fn open_device(path: &str, flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+    let _fs = FS_LOCK.lock();
+    let (_, inode) = lookup(path)?;
+    inode.open_device(flags)
+}
+
+/// Create a named pipe, failing if `path` exists already
+pub fn mkfifo(path: &str) -> bool {
+    let _fs = FS_LOCK.lock();
+    lookup_parent(path)
+        .and_then(|((_, dir), name)| dir.create(&name, InodeType::Fifo))
+        .is_some()
+}
+
+/// Give the file at `old_path` the name `new_path` as well
+pub fn link(old_path: &str, new_path: &str) -> Result<(), isize> {
+    let _fs = FS_LOCK.lock();
+    let (fs, inode) = lookup(old_path).ok_or(ENOENT)?;
+    if inode.type_() == InodeType::Directory {
+        return Err(EPERM);
+    }
+    let ((dir_fs, dir), name) = lookup_parent(new_path).ok_or(ENOENT)?;
+    if dir_fs.dev() != fs.dev() {
+        return Err(EXDEV);
+    }
+    if dir.link(&name, inode.ino()) {
+        Ok(())
+    } else {
+        Err(EEXIST)
+    }
+}
+
+/// Remove the name `path`, the file goes with its last one
+pub fn unlink(path: &str) -> Result<(), isize> {
+    let _fs = FS_LOCK.lock();
+    let ((_, dir), name) = lookup_parent(path).ok_or(ENOENT)?;
+    match dir.find(&name) {
+        None => Err(ENOENT),
+        Some(inode) if inode.type_() == InodeType::Directory => Err(EISDIR),
+        Some(_) if dir.unlink(&name) => Ok(()),
+        Some(_) => Err(EPERM),
+    }
+}
+
 /// Open a file by path
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
+pub fn open_file(path: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
     let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(
-                readable,
-                writable,
-                inode,
-            )))
-        } else {
+    let _fs = FS_LOCK.lock();
+    let (fs, inode) = match lookup(path) {
+        Some((fs, inode)) => {
+            if inode.type_() == InodeType::Directory && writable {
+                return None;
+            }
+            if inode.type_() == InodeType::File
+                && flags.intersects(OpenFlags::CREATE | OpenFlags::TRUNC)
+            {
+                // clear size
+                inode.clear();
+            }
+            (fs, inode)
+        }
+        None if flags.contains(OpenFlags::CREATE) => {
             // create file
-            ROOT_INODE.create(name)
-                .map(|inode| {
-                    Arc::new(OSInode::new(
-                        readable,
-                        writable,
-                        inode,
-                    ))
-                })
+            let ((fs, dir), name) = lookup_parent(path)?;
+            let inode = dir.create(&name, InodeType::File)?;
+            (fs, inode)
         }
-    } else {
-        ROOT_INODE.find(name)
-            .map(|inode| {
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(OSInode::new(
-                    readable,
-                    writable,
-                    inode
-                ))
-            })
-    }
+        None => return None,
+    };
+    Some(Arc::new(OSInode::new(readable, writable, fs, inode)))
 }
 
 impl File for OSInode {
     fn readable(&self) -> bool { self.readable }
     fn writable(&self) -> bool { self.writable }
     fn read(&self, mut buf: UserBuffer) -> usize {
+        let _fs = FS_LOCK.lock();
         let mut inner = self.inner.exclusive_access();
         let mut total_read_size = 0usize;
         for slice in buf.buffers.iter_mut() {
@@ -156,14 +202,33 @@ impl File for OSInode {
         total_read_size
     }
     fn write(&self, buf: UserBuffer) -> usize {
+        let _fs = FS_LOCK.lock();
         let mut inner = self.inner.exclusive_access();
         let mut total_write_size = 0usize;
         for slice in buf.buffers.iter() {
             let write_size = inner.inode.write_at(inner.offset, *slice);
-            assert_eq!(write_size, slice.len());
             inner.offset += write_size;
             total_write_size += write_size;
+            if write_size < slice.len() {
+                // the filesystem is full
+                break;
+            }
+        }
+        if total_write_size == 0 && buf.len() > 0 {
+            return ENOSPC as usize;
         }
         total_write_size
     }
+    fn stat(&self) -> Option<Stat> {
+        let _fs = FS_LOCK.lock();
+        let inode = &self.inner.exclusive_access().inode;
+        let mode = match inode.type_() {
+            InodeType::File => StatMode::FILE,
+            InodeType::Directory => StatMode::DIR,
+            InodeType::Fifo => StatMode::FIFO,
+            InodeType::CharDevice => StatMode::CHR,
+            InodeType::BlockDevice => StatMode::BLK,
+        };
+        Some(Stat::new(self.fs.dev(), inode.ino(), mode, inode.nlink()))
+    }
 }
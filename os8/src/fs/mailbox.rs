@@ -0,0 +1,61 @@
+//! The mailbox of a process opened as a file, to wait for messages along
+//! with other files in `ppoll`
+
+use super::{File, PollEvents, PollWaker};
+use crate::mm::UserBuffer;
+use crate::sync::Mailbox;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Reads take the messages of the mailbox one at a time
+pub struct MailboxFile {
+    mailbox: Arc<Mailbox>,
+    nonblock: AtomicBool,
+}
+
+impl MailboxFile {
+    pub fn new(mailbox: Arc<Mailbox>, nonblock: bool) -> Self {
+        Self {
+            mailbox,
+            nonblock: AtomicBool::new(nonblock),
+        }
+    }
+}
+
+impl File for MailboxFile {
+    fn readable(&self) -> bool {
+        true
+    }
+    fn writable(&self) -> bool {
+        false
+    }
+    /// Take the oldest message, the rest of a longer one being lost
+    fn read(&self, buf: UserBuffer) -> usize {
+        let message = match self.mailbox.recv(self.nonblocking()) {
+            Ok(message) => message,
+            Err(err) => return err as usize,
+        };
+        let mut copied = 0;
+        for slice in buf.buffers {
+            let len = slice.len().min(message.len() - copied);
+            slice[..len].copy_from_slice(&message[copied..copied + len]);
+            copied += len;
+        }
+        copied
+    }
+    fn write(&self, _buf: UserBuffer) -> usize {
+        0
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        self.mailbox.poll(events & PollEvents::IN)
+    }
+    fn register_poller(&self, waker: &Arc<PollWaker>) {
+        self.mailbox.register_poller(waker);
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+}
@@ -1,8 +1,17 @@
 mod stdio;
+mod devfs;
+mod easyfs;
 mod inode;
+mod mailbox;
 mod pipe;
+mod poll;
+mod tmpfs;
+mod tty;
+mod vfs;
 
 use crate::mm::UserBuffer;
+use crate::syscall::errno::EINVAL;
+use alloc::sync::Arc;
 
 /// The common abstraction of all IO resources
 pub trait File : Send + Sync {
@@ -10,6 +19,44 @@ pub trait File : Send + Sync {
     fn writable(&self) -> bool;
     fn read(&self, buf: UserBuffer) -> usize;
     fn write(&self, buf: UserBuffer) -> usize;
+    /// Device-specific control, only terminals support it so far
+    fn ioctl(&self, _request: usize, _arg: usize) -> isize {
+        -1
+    }
+    /// File-specific `fcntl` commands, such as resizing a pipe
+    fn fcntl(&self, _cmd: usize, _arg: usize) -> isize {
+        EINVAL
+    }
+    /// Which of `events` would not block right now, plus `ERR` and `HUP`
+    ///
+    /// Files that never block are always ready for what they support.
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let mut ready = PollEvents::empty();
+        if self.readable() {
+            ready |= PollEvents::IN;
+        }
+        if self.writable() {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+    /// Have `waker` woken up the next time the readiness of this file may change
+    fn register_poller(&self, _waker: &Arc<PollWaker>) {}
+    /// Whether reads and writes fail with `EAGAIN` instead of waiting
+    fn nonblocking(&self) -> bool {
+        false
+    }
+    /// Set `O_NONBLOCK`, files that never block ignore it
+    fn set_nonblocking(&self, _nonblock: bool) {}
+    /// Whether this file is the object at `object`, like a pipe end, or is
+    /// open on it, to find the processes able to make progress on it
+    fn is_open_on(&self, object: usize) -> bool {
+        self as *const Self as *const () as usize == object
+    }
+    /// What `fstat` reports, only files of a filesystem have it so far
+    fn stat(&self) -> Option<Stat> {
+        None
+    }
 }
 
 /// The stat of a inode
@@ -28,6 +75,18 @@ pub struct Stat {
     pad: [u64; 7],
 }
 
+impl Stat {
+    pub fn new(dev: u64, ino: u64, mode: StatMode, nlink: u32) -> Self {
+        Self {
+            dev,
+            ino,
+            mode,
+            nlink,
+            pad: [0; 7],
+        }
+    }
+}
+
 bitflags! {
     /// The mode of a inode
     /// whether a directory or a file
@@ -37,9 +96,19 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// named pipe
+        const FIFO  = 0o010000;
+        /// character device
+        const CHR   = 0o020000;
+        /// block device
+        const BLK   = 0o060000;
     }
 }    
 
 pub use stdio::{Stdin, Stdout};
-pub use inode::{OSInode, open_file, OpenFlags, list_apps};
-pub use pipe::{Pipe, make_pipe};
+pub use tty::console_receive;
+pub use inode::{OSInode, open_file, OpenFlags, list_apps, fifo_ino, mkfifo, link, unlink, open_device};
+pub use vfs::{mount, umount};
+pub use mailbox::MailboxFile;
+pub use pipe::{FifoFile, Pipe, make_pipe, open_fifo};
+pub use poll::{PollEvents, PollQueue, PollWaker};
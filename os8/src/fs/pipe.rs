@@ -1,14 +1,44 @@
-use super::File;
-use alloc::sync::{Arc, Weak};
-use crate::sync::UPSafeCell;
+use super::{File, PollEvents, PollQueue, PollWaker};
+use crate::config::PAGE_SIZE;
 use crate::mm::UserBuffer;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EAGAIN, EBUSY, EINTR, EINVAL, ENXIO, EPERM, EPIPE};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::{Arc, Weak};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
-use crate::task::suspend_current_and_run_next;
+use crate::sync::{
+    block_on, current_thread, deadlock_detection_enabled, remove_object, set_holders, unblock,
+    ThreadKey,
+};
+use crate::task::{
+    add_task, block_current_interruptible, current_interrupted, current_process, current_task,
+    processes_with_file, SignalFlags, TaskControlBlock,
+};
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Set the capacity of a pipe, rounded up to whole pages
+pub const F_SETPIPE_SZ: usize = 1031;
+/// Get the capacity of a pipe
+pub const F_GETPIPE_SZ: usize = 1032;
+
+/// Capacity of a new pipe
+const PIPE_DEFAULT_SIZE: usize = PAGE_SIZE;
+/// Largest capacity `F_SETPIPE_SZ` accepts
+const PIPE_MAX_SIZE: usize = 16 * PAGE_SIZE;
+/// Pages all pipes together may have beyond their first one, the buffers
+/// being on the kernel heap
+const PIPE_EXTRA_PAGES_MAX: usize = 64;
+
+/// Pages taken by `F_SETPIPE_SZ` beyond the first one of every pipe
+static PIPE_EXTRA_PAGES: AtomicUsize = AtomicUsize::new(0);
 
 /// One end of a pipe
 pub struct Pipe {
     readable: bool,
     writable: bool,
+    nonblock: AtomicBool,
     buffer: Arc<UPSafeCell<PipeRingBuffer>>,
 }
 
@@ -18,6 +48,7 @@ impl Pipe {
         Self {
             readable: true,
             writable: false,
+            nonblock: AtomicBool::new(false),
             buffer,
         }
     }
@@ -26,154 +57,550 @@ impl Pipe {
         Self {
             readable: false,
             writable: true,
+            nonblock: AtomicBool::new(false),
             buffer,
         }
     }
 }
 
-const RING_BUFFER_SIZE: usize = 32;
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        remove_object(self as *const Self as usize);
+        // the other side may be waiting for an end of file or a broken pipe
+        let mut ring_buffer = self.buffer.exclusive_access();
+        if self.readable {
+            ring_buffer.wake_writers();
+        } else {
+            ring_buffer.wake_readers();
+        }
+    }
+}
+
+/// Threads of the processes having `end` open, the ones waiters on it depend on
+///
+/// Waiters for data wait on the write end, waiters for space on the read end.
+fn pipe_end_holders(end: &Weak<Pipe>) -> Vec<ThreadKey> {
+    processes_with_file(end.as_ptr() as usize)
+        .iter()
+        .flat_map(|process| {
+            let pid = process.getpid();
+            let tids = process.inner_exclusive_access().live_tids();
+            tids.into_iter().map(move |tid| (pid, tid))
+        })
+        .collect()
+}
+
+/// Record that the calling thread is about to wait for the other side of `end`
+fn block_on_pipe_end(end: &Weak<Pipe>) -> Result<(), isize> {
+    let object = end.as_ptr() as usize;
+    if deadlock_detection_enabled() {
+        set_holders(object, pipe_end_holders(end));
+    }
+    block_on(object)
+}
+
+/// The other side of `end` made progress, its waiters may go on
+fn pipe_end_progressed(end: &Weak<Pipe>) {
+    if deadlock_detection_enabled() {
+        set_holders(end.as_ptr() as usize, Vec::new());
+    }
+}
 
-#[derive(Copy, Clone, PartialEq)]
-enum RingBufferStatus {
-    FULL,
-    EMPTY,
-    NORMAL,
+fn wake_all(queue: &mut VecDeque<Arc<TaskControlBlock>>) {
+    for task in queue.drain(..) {
+        add_task(task);
+    }
 }
 
 /// The underlying ring buffer of a pipe
 pub struct PipeRingBuffer {
-    arr: [u8; RING_BUFFER_SIZE],
-    head: usize,
-    tail: usize,
-    status: RingBufferStatus,
+    data: VecDeque<u8>,
+    capacity: usize,
     write_end: Option<Weak<Pipe>>,
+    read_end: Option<Weak<Pipe>>,
+    /// Threads waiting for data or for the write end to close
+    readers: VecDeque<Arc<TaskControlBlock>>,
+    /// Threads waiting for room or for the read end to close
+    writers: VecDeque<Arc<TaskControlBlock>>,
+    /// Threads polling either end
+    pollers: PollQueue,
 }
 
 impl PipeRingBuffer {
     pub fn new() -> Self {
         Self {
-            arr: [0; RING_BUFFER_SIZE],
-            head: 0,
-            tail: 0,
-            status: RingBufferStatus::EMPTY,
+            data: VecDeque::new(),
+            capacity: PIPE_DEFAULT_SIZE,
             write_end: None,
+            read_end: None,
+            readers: VecDeque::new(),
+            writers: VecDeque::new(),
+            pollers: PollQueue::new(),
         }
     }
+    /// Data arrived or the write end closed
+    fn wake_readers(&mut self) {
+        wake_all(&mut self.readers);
+        self.pollers.wake_all();
+    }
+    /// Room was made or the read end closed
+    fn wake_writers(&mut self) {
+        wake_all(&mut self.writers);
+        self.pollers.wake_all();
+    }
     /// Set the write end bound to this buffer
     pub fn set_write_end(&mut self, write_end: &Arc<Pipe>) {
         self.write_end = Some(Arc::downgrade(write_end));
     }
+    /// Set the read end bound to this buffer
+    pub fn set_read_end(&mut self, read_end: &Arc<Pipe>) {
+        self.read_end = Some(Arc::downgrade(read_end));
+    }
     /// Write into the buffer
     pub fn write_byte(&mut self, byte: u8) {
-        self.status = RingBufferStatus::NORMAL;
-        self.arr[self.tail] = byte;
-        self.tail = (self.tail + 1) % RING_BUFFER_SIZE;
-        if self.tail == self.head {
-            self.status = RingBufferStatus::FULL;
-        }
+        self.data.push_back(byte);
     }
     /// Read from the buffer
     pub fn read_byte(&mut self) -> u8 {
-        self.status = RingBufferStatus::NORMAL;
-        let c = self.arr[self.head];
-        self.head = (self.head + 1) % RING_BUFFER_SIZE;
-        if self.head == self.tail {
-            self.status = RingBufferStatus::EMPTY;
-        }
-        c
+        self.data.pop_front().unwrap()
     }
     /// Get the length of remaining data in the buffer
     pub fn available_read(&self) -> usize {
-        if self.status == RingBufferStatus::EMPTY {
-            0
-        } else {
-            if self.tail > self.head {
-                self.tail - self.head
-            } else {
-                self.tail + RING_BUFFER_SIZE - self.head
-            }
-        }
+        self.data.len()
     }
     /// Get the length of remaining space in the buffer
     pub fn available_write(&self) -> usize {
-        if self.status == RingBufferStatus::FULL {
-            0
-        } else {
-            RING_BUFFER_SIZE - self.available_read()
-        }
+        self.capacity.saturating_sub(self.data.len())
     }
     /// Check if all write ends bounded to this buffer are closed
     pub fn all_write_ends_closed(&self) -> bool {
-        self.write_end.as_ref().unwrap().upgrade().is_none()
+        // a named pipe may not have been opened for writing yet
+        self.write_end
+            .as_ref()
+            .map_or(true, |end| end.strong_count() == 0)
+    }
+    /// Check if all read ends bounded to this buffer are closed
+    pub fn all_read_ends_closed(&self) -> bool {
+        self.read_end
+            .as_ref()
+            .map_or(true, |end| end.strong_count() == 0)
+    }
+    /// Resize the buffer to hold `size` bytes, rounded up to whole pages
+    ///
+    /// Fails with `EBUSY` if it holds more data than that, and with `EPERM`
+    /// beyond [`PIPE_MAX_SIZE`] or once the pipes together would have more
+    /// than [`PIPE_EXTRA_PAGES_MAX`] extra pages. Returns the new capacity.
+    pub fn set_capacity(&mut self, size: usize) -> Result<usize, isize> {
+        if size > PIPE_MAX_SIZE {
+            return Err(EPERM);
+        }
+        let capacity = ((size + PAGE_SIZE - 1) / PAGE_SIZE).max(1) * PAGE_SIZE;
+        if capacity < self.data.len() {
+            return Err(EBUSY);
+        }
+        let (pages, old_pages) = (capacity / PAGE_SIZE, self.capacity / PAGE_SIZE);
+        if pages > old_pages {
+            let extra = PIPE_EXTRA_PAGES.load(Ordering::Relaxed) + pages - old_pages;
+            if extra > PIPE_EXTRA_PAGES_MAX {
+                return Err(EPERM);
+            }
+            PIPE_EXTRA_PAGES.store(extra, Ordering::Relaxed);
+        } else {
+            PIPE_EXTRA_PAGES.fetch_sub(old_pages - pages, Ordering::Relaxed);
+        }
+        self.capacity = capacity;
+        self.wake_writers();
+        Ok(capacity)
+    }
+}
+
+impl Drop for PipeRingBuffer {
+    fn drop(&mut self) {
+        PIPE_EXTRA_PAGES.fetch_sub(self.capacity / PAGE_SIZE - 1, Ordering::Relaxed);
+    }
+}
+
+/// A named pipe, whose two ends are shared by everyone opening it
+///
+/// Every open gets a [`FifoFile`] of its own on them though, for its own
+/// `O_NONBLOCK`.
+struct Fifo {
+    buffer: Arc<UPSafeCell<PipeRingBuffer>>,
+    read_end: Weak<Pipe>,
+    write_end: Weak<Pipe>,
+    /// Times each end was opened, to notice a peer that came and went
+    read_opens: usize,
+    write_opens: usize,
+    /// Threads waiting in `open` for the other end
+    waiters: VecDeque<Arc<TaskControlBlock>>,
+}
+
+impl Fifo {
+    fn new() -> Self {
+        Self {
+            buffer: Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) }),
+            read_end: Weak::new(),
+            write_end: Weak::new(),
+            read_opens: 0,
+            write_opens: 0,
+            waiters: VecDeque::new(),
+        }
+    }
+
+    fn open_read_end(&mut self) -> Arc<Pipe> {
+        self.read_opens += 1;
+        self.read_end.upgrade().unwrap_or_else(|| {
+            let end = Arc::new(Pipe::read_end_with_buffer(self.buffer.clone()));
+            self.buffer.exclusive_access().set_read_end(&end);
+            self.read_end = Arc::downgrade(&end);
+            end
+        })
+    }
+
+    fn open_write_end(&mut self) -> Arc<Pipe> {
+        self.write_opens += 1;
+        self.write_end.upgrade().unwrap_or_else(|| {
+            let end = Arc::new(Pipe::write_end_with_buffer(self.buffer.clone()));
+            self.buffer.exclusive_access().set_write_end(&end);
+            self.write_end = Arc::downgrade(&end);
+            end
+        })
+    }
+
+    fn in_use(&self) -> bool {
+        self.read_end.strong_count() > 0 || self.write_end.strong_count() > 0
+    }
+
+    /// Whether the other side of the `writable` end is open, or was opened
+    /// since it had been opened `opens` times
+    fn peer_arrived(&self, writable: bool, opens: usize) -> bool {
+        if writable {
+            self.read_end.strong_count() > 0 || self.read_opens != opens
+        } else {
+            self.write_end.strong_count() > 0 || self.write_opens != opens
+        }
+    }
+}
+
+lazy_static! {
+    /// Named pipes open somewhere, by device and inode number
+    static ref FIFOS: UPSafeCell<BTreeMap<(u64, u64), Fifo>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Open the named pipe `ino`, a device and inode number, for reading,
+/// writing or both
+///
+/// Waits for the other end to be opened too, unless `nonblock` or opened
+/// for both: a read end is then returned right away, while a write end
+/// fails with `ENXIO` if nobody reads.
+pub fn open_fifo(
+    ino: (u64, u64),
+    readable: bool,
+    writable: bool,
+    nonblock: bool,
+) -> Result<Arc<FifoFile>, isize> {
+    let mut fifos = FIFOS.exclusive_access();
+    // the data of a named pipe goes away once nobody has it open
+    fifos.retain(|_, fifo| fifo.in_use());
+    let fifo = fifos.entry(ino).or_insert_with(Fifo::new);
+    if !readable && nonblock && fifo.read_end.strong_count() == 0 {
+        return Err(ENXIO);
+    }
+    let file = Arc::new(FifoFile {
+        read_end: readable.then(|| fifo.open_read_end()),
+        write_end: writable.then(|| fifo.open_write_end()),
+        nonblock: AtomicBool::new(nonblock),
+    });
+    let opens = if writable {
+        fifo.read_opens
+    } else {
+        fifo.write_opens
+    };
+    wake_all(&mut fifo.waiters);
+    drop(fifos);
+    if nonblock || (readable && writable) {
+        return Ok(file);
+    }
+    // holding our end, so that the peer does not wait for us in turn
+    loop {
+        let mut fifos = FIFOS.exclusive_access();
+        let fifo = fifos.get_mut(&ino).unwrap();
+        if fifo.peer_arrived(writable, opens) {
+            return Ok(file);
+        }
+        if current_interrupted() {
+            return Err(EINTR);
+        }
+        fifo.waiters.push_back(current_task().unwrap());
+        drop(fifos);
+        block_current_interruptible();
+    }
+}
+
+/// An open named pipe, on its read end, its write end or both
+pub struct FifoFile {
+    read_end: Option<Arc<Pipe>>,
+    write_end: Option<Arc<Pipe>>,
+    nonblock: AtomicBool,
+}
+
+impl FifoFile {
+    fn ends(&self) -> impl Iterator<Item = &Arc<Pipe>> {
+        self.read_end.iter().chain(self.write_end.iter())
+    }
+}
+
+impl File for FifoFile {
+    fn readable(&self) -> bool {
+        self.read_end.is_some()
+    }
+    fn writable(&self) -> bool {
+        self.write_end.is_some()
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        let end = self.read_end.as_ref().unwrap();
+        end.read_with(buf, self.nonblocking())
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        let end = self.write_end.as_ref().unwrap();
+        end.write_with(buf, self.nonblocking())
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        self.ends()
+            .fold(PollEvents::empty(), |ready, end| ready | end.poll(events))
+    }
+    fn register_poller(&self, waker: &Arc<PollWaker>) {
+        // both ends are on the same buffer
+        if let Some(end) = self.ends().next() {
+            end.register_poller(waker);
+        }
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+    fn fcntl(&self, cmd: usize, arg: usize) -> isize {
+        match self.ends().next() {
+            Some(end) => end.fcntl(cmd, arg),
+            None => EINVAL,
+        }
+    }
+    fn is_open_on(&self, object: usize) -> bool {
+        self.ends().any(|end| Arc::as_ptr(end) as usize == object)
     }
 }
 
 /// Crate a pipe
 /// return (read_end, write_end)
 pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
-    let buffer = Arc::new(unsafe {
-        UPSafeCell::new(PipeRingBuffer::new())
-    });
-    let read_end = Arc::new(
-        Pipe::read_end_with_buffer(buffer.clone())
-    );
-    let write_end = Arc::new(
-        Pipe::write_end_with_buffer(buffer.clone())
-    );
+    let buffer = Arc::new(unsafe { UPSafeCell::new(PipeRingBuffer::new()) });
+    let read_end = Arc::new(Pipe::read_end_with_buffer(buffer.clone()));
+    let write_end = Arc::new(Pipe::write_end_with_buffer(buffer.clone()));
     buffer.exclusive_access().set_write_end(&write_end);
+    buffer.exclusive_access().set_read_end(&read_end);
     (read_end, write_end)
 }
 
-impl File for Pipe {
-    fn readable(&self) -> bool { self.readable }
-    fn writable(&self) -> bool { self.writable }
-    fn read(&self, buf: UserBuffer) -> usize {
-        assert_eq!(self.readable(), true);
+impl Pipe {
+    /// Read into `buf`, failing with `EAGAIN` instead of waiting if `nonblock`
+    fn read_with(&self, buf: UserBuffer, nonblock: bool) -> usize {
+        assert!(self.readable());
         let mut buf_iter = buf.into_iter();
         let mut read_size = 0usize;
-        loop {
+        let mut waited = false;
+        'read: loop {
             let mut ring_buffer = self.buffer.exclusive_access();
             let loop_read = ring_buffer.available_read();
             if loop_read == 0 {
                 if ring_buffer.all_write_ends_closed() {
-                    return read_size;
+                    break;
+                }
+                if nonblock {
+                    // only wait to fill the buffer when allowed to wait at all
+                    return if read_size > 0 {
+                        read_size
+                    } else {
+                        EAGAIN as usize
+                    };
                 }
+                let write_end = ring_buffer.write_end.clone().unwrap();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                if current_interrupted() {
+                    if waited {
+                        unblock(current_thread());
+                    }
+                    return if read_size > 0 {
+                        read_size
+                    } else {
+                        EINTR as usize
+                    };
+                }
+                if let Err(err) = block_on_pipe_end(&write_end) {
+                    // a negative length makes sys_read fail
+                    return if read_size > 0 {
+                        read_size
+                    } else {
+                        err as usize
+                    };
+                }
+                waited = true;
+                let task = current_task().unwrap();
+                self.buffer.exclusive_access().readers.push_back(task);
+                block_current_interruptible();
                 continue;
             }
+            let read_end = ring_buffer.read_end.clone().unwrap();
             // read at most loop_read bytes
             for _ in 0..loop_read {
                 if let Some(byte_ref) = buf_iter.next() {
-                    unsafe { *byte_ref = ring_buffer.read_byte(); }
+                    unsafe {
+                        *byte_ref = ring_buffer.read_byte();
+                    }
                     read_size += 1;
                 } else {
-                    return read_size;
+                    ring_buffer.wake_writers();
+                    drop(ring_buffer);
+                    pipe_end_progressed(&read_end);
+                    break 'read;
                 }
             }
+            ring_buffer.wake_writers();
+            drop(ring_buffer);
+            pipe_end_progressed(&read_end);
         }
+        if waited {
+            unblock(current_thread());
+        }
+        read_size
     }
-    fn write(&self, buf: UserBuffer) -> usize {
-        assert_eq!(self.writable(), true);
+    /// Write `buf`, failing with `EAGAIN` instead of waiting if `nonblock`
+    fn write_with(&self, buf: UserBuffer, nonblock: bool) -> usize {
+        assert!(self.writable());
         let mut buf_iter = buf.into_iter();
         let mut write_size = 0usize;
-        loop {
+        let mut waited = false;
+        'write: loop {
             let mut ring_buffer = self.buffer.exclusive_access();
+            if ring_buffer.all_read_ends_closed() {
+                drop(ring_buffer);
+                if write_size > 0 {
+                    break;
+                }
+                // nobody will ever read it, SIGPIPE kills the writer by default
+                current_process()
+                    .inner_exclusive_access()
+                    .send_signal(SignalFlags::SIGPIPE);
+                return EPIPE as usize;
+            }
             let loop_write = ring_buffer.available_write();
             if loop_write == 0 {
+                if nonblock {
+                    return if write_size > 0 {
+                        write_size
+                    } else {
+                        EAGAIN as usize
+                    };
+                }
+                let read_end = ring_buffer.read_end.clone().unwrap();
                 drop(ring_buffer);
-                suspend_current_and_run_next();
+                if current_interrupted() {
+                    if waited {
+                        unblock(current_thread());
+                    }
+                    return if write_size > 0 {
+                        write_size
+                    } else {
+                        EINTR as usize
+                    };
+                }
+                if let Err(err) = block_on_pipe_end(&read_end) {
+                    return if write_size > 0 {
+                        write_size
+                    } else {
+                        err as usize
+                    };
+                }
+                waited = true;
+                let task = current_task().unwrap();
+                self.buffer.exclusive_access().writers.push_back(task);
+                block_current_interruptible();
                 continue;
             }
+            let write_end = ring_buffer.write_end.clone().unwrap();
             // write at most loop_write bytes
             for _ in 0..loop_write {
                 if let Some(byte_ref) = buf_iter.next() {
                     ring_buffer.write_byte(unsafe { *byte_ref });
                     write_size += 1;
                 } else {
-                    return write_size;
+                    ring_buffer.wake_readers();
+                    drop(ring_buffer);
+                    pipe_end_progressed(&write_end);
+                    break 'write;
                 }
             }
+            ring_buffer.wake_readers();
+            drop(ring_buffer);
+            pipe_end_progressed(&write_end);
+        }
+        if waited {
+            unblock(current_thread());
+        }
+        write_size
+    }
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+    fn writable(&self) -> bool {
+        self.writable
+    }
+    fn read(&self, buf: UserBuffer) -> usize {
+        self.read_with(buf, self.nonblocking())
+    }
+    fn write(&self, buf: UserBuffer) -> usize {
+        self.write_with(buf, self.nonblocking())
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        let ring_buffer = self.buffer.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if self.readable {
+            if ring_buffer.available_read() > 0 {
+                ready |= PollEvents::IN;
+            }
+            if ring_buffer.all_write_ends_closed() {
+                ready |= PollEvents::HUP;
+            }
+        } else if ring_buffer.all_read_ends_closed() {
+            ready |= PollEvents::ERR;
+        } else if ring_buffer.available_write() > 0 {
+            ready |= PollEvents::OUT;
+        }
+        ready & (events | PollEvents::ERR | PollEvents::HUP)
+    }
+    fn register_poller(&self, waker: &Arc<PollWaker>) {
+        self.buffer.exclusive_access().pollers.register(waker);
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+    fn fcntl(&self, cmd: usize, arg: usize) -> isize {
+        let mut ring_buffer = self.buffer.exclusive_access();
+        match cmd {
+            F_GETPIPE_SZ => ring_buffer.capacity as isize,
+            F_SETPIPE_SZ => match ring_buffer.set_capacity(arg) {
+                Ok(capacity) => capacity as isize,
+                Err(err) => err,
+            },
+            _ => EINVAL,
         }
     }
 }
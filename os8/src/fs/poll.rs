@@ -0,0 +1,87 @@
+//! Readiness of files, for `ppoll` and non-blocking I/O
+//!
+//! A thread polling several files registers one [`PollWaker`] with each of
+//! them before going to sleep. The first file whose readiness may have
+//! changed wakes it up, the others find the waker used up.
+
+use crate::sync::UPSafeCell;
+use crate::task::{current_task, TaskControlBlock};
+use crate::timer::wakeup_task;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+bitflags! {
+    /// Events of `struct pollfd`
+    pub struct PollEvents: u16 {
+        /// There is data to read
+        const IN = 1 << 0;
+        /// There is urgent data to read
+        const PRI = 1 << 1;
+        /// Writing would not block
+        const OUT = 1 << 2;
+        /// Error condition, like a pipe without readers
+        const ERR = 1 << 3;
+        /// The other end hung up
+        const HUP = 1 << 4;
+        /// The file descriptor is not open
+        const NVAL = 1 << 5;
+    }
+}
+
+/// A thread sleeping until one of the files it polls is ready
+pub struct PollWaker {
+    task: Arc<TaskControlBlock>,
+    /// Set once the thread was woken up, or went on by itself
+    done: UPSafeCell<bool>,
+}
+
+impl PollWaker {
+    /// A waker for the calling thread
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            task: current_task().unwrap(),
+            done: unsafe { UPSafeCell::new(false) },
+        })
+    }
+
+    /// Wake the thread up, unless it already was
+    pub fn wake(&self) {
+        let mut done = self.done.exclusive_access();
+        if !*done {
+            *done = true;
+            drop(done);
+            wakeup_task(Arc::clone(&self.task));
+        }
+    }
+
+    /// The thread went on, later wakeups must not enqueue it again
+    pub fn finish(&self) {
+        *self.done.exclusive_access() = true;
+    }
+}
+
+/// Threads polling a file, to be woken up when its readiness changes
+#[derive(Default)]
+pub struct PollQueue {
+    wakers: Vec<Weak<PollWaker>>,
+}
+
+impl PollQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, waker: &Arc<PollWaker>) {
+        // forget the threads which went on without us
+        self.wakers.retain(|waker| waker.strong_count() > 0);
+        self.wakers.push(Arc::downgrade(waker));
+    }
+
+    pub fn wake_all(&mut self) {
+        for waker in self.wakers.drain(..) {
+            if let Some(waker) = waker.upgrade() {
+                waker.wake();
+            }
+        }
+    }
+}
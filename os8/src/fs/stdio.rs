@@ -1,36 +1,55 @@
-use super::File;
-use crate::mm::{UserBuffer};
-use crate::sbi::console_getchar;
-use crate::task::suspend_current_and_run_next;
+use super::tty::{tty_ioctl, tty_read, tty_readable, tty_register_poller, tty_write};
+use super::{File, PollEvents, PollWaker};
+use crate::mm::UserBuffer;
+use alloc::sync::Arc;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 /// The standard input
-pub struct Stdin;
+pub struct Stdin {
+    nonblock: AtomicBool,
+}
 /// The standard output
 pub struct Stdout;
 
+impl Stdin {
+    pub fn new() -> Self {
+        Self {
+            nonblock: AtomicBool::new(false),
+        }
+    }
+}
+
 impl File for Stdin {
     fn readable(&self) -> bool { true }
     fn writable(&self) -> bool { false }
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                suspend_current_and_run_next();
-                continue;
-            } else {
-                break;
-            }
+    fn read(&self, user_buf: UserBuffer) -> usize {
+        match tty_read(user_buf, self.nonblocking()) {
+            Ok(read_size) => read_size,
+            Err(err) => err as usize,
         }
-        let ch = c as u8;
-        unsafe { user_buf.buffers[0].as_mut_ptr().write_volatile(ch); }
-        1
     }
     fn write(&self, _user_buf: UserBuffer) -> usize {
         panic!("Cannot write to stdin!");
     }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
+    }
+    fn poll(&self, events: PollEvents) -> PollEvents {
+        if tty_readable() {
+            PollEvents::IN & events
+        } else {
+            PollEvents::empty()
+        }
+    }
+    fn register_poller(&self, waker: &Arc<PollWaker>) {
+        tty_register_poller(waker);
+    }
+    fn nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
+    }
+    fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
 }
 
 impl File for Stdout {
@@ -40,9 +59,9 @@ impl File for Stdout {
         panic!("Cannot read from stdout!");
     }
     fn write(&self, user_buf: UserBuffer) -> usize {
-        for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
-        }
-        user_buf.len()
+        tty_write(user_buf)
+    }
+    fn ioctl(&self, request: usize, arg: usize) -> isize {
+        tty_ioctl(request, arg)
     }
 }
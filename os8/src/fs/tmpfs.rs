@@ -0,0 +1,235 @@
+//! tmpfs, a filesystem kept in memory frames and lost on unmount
+
+use super::vfs::{alloc_dev, FileSystem, Inode, InodeType};
+use crate::config::PAGE_SIZE;
+use crate::mm::{frame_alloc, FrameTracker};
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::EINVAL;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+/// Bytes of file data a tmpfs holds at most unless mounted with `size=`,
+/// 1 MiB out of the 128 MiB of memory
+const DEFAULT_SIZE: usize = 0x10_0000;
+
+/// A tmpfs instance, whose files are freed along with it on unmount
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+    dev: u64,
+}
+
+/// What the inodes of a tmpfs share
+struct TmpFsInner {
+    /// Frames holding file data
+    used_pages: usize,
+    /// Frames it may hold file data in
+    max_pages: usize,
+    next_ino: u64,
+    /// Every inode, to link to them by number
+    inodes: BTreeMap<u64, Weak<TmpInode>>,
+}
+
+pub struct TmpInode {
+    ino: u64,
+    type_: InodeType,
+    fs: Arc<UPSafeCell<TmpFsInner>>,
+    inner: UPSafeCell<TmpInodeInner>,
+}
+
+struct TmpInodeInner {
+    nlink: u32,
+    /// Size of a file, its data is in `pages` and zero past it
+    size: usize,
+    pages: Vec<FrameTracker>,
+    /// Entries of a directory
+    entries: BTreeMap<String, Arc<TmpInode>>,
+}
+
+impl TmpFs {
+    /// Make an empty tmpfs, the only option in `data` being `size=` in
+    /// bytes with an optional `k` or `m` suffix
+    pub fn mount(_source: &str, data: &str) -> Result<Arc<dyn FileSystem>, isize> {
+        let mut size = DEFAULT_SIZE;
+        for option in data.split(',').filter(|option| !option.is_empty()) {
+            size = match option.split_once('=') {
+                Some(("size", value)) => parse_size(value).ok_or(EINVAL)?,
+                _ => return Err(EINVAL),
+            };
+        }
+        let fs = Arc::new(unsafe {
+            UPSafeCell::new(TmpFsInner {
+                used_pages: 0,
+                max_pages: (size + PAGE_SIZE - 1) / PAGE_SIZE,
+                next_ino: 1,
+                inodes: BTreeMap::new(),
+            })
+        });
+        Ok(Arc::new(Self {
+            root: TmpInode::new(&fs, InodeType::Directory),
+            dev: alloc_dev(),
+        }))
+    }
+}
+
+/// `value` bytes, in `k` or `m` units if it ends with one
+fn parse_size(value: &str) -> Option<usize> {
+    let (digits, unit) = match value.as_bytes().last()? {
+        b'k' | b'K' => (&value[..value.len() - 1], 1 << 10),
+        b'm' | b'M' => (&value[..value.len() - 1], 1 << 20),
+        _ => (value, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+impl FileSystem for TmpFs {
+    fn root_inode(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+    fn dev(&self) -> u64 {
+        self.dev
+    }
+}
+
+impl TmpInode {
+    fn new(fs: &Arc<UPSafeCell<TmpFsInner>>, type_: InodeType) -> Arc<Self> {
+        let mut fs_inner = fs.exclusive_access();
+        let ino = fs_inner.next_ino;
+        fs_inner.next_ino += 1;
+        let inode = Arc::new(Self {
+            ino,
+            type_,
+            fs: fs.clone(),
+            inner: unsafe {
+                UPSafeCell::new(TmpInodeInner {
+                    nlink: 1,
+                    size: 0,
+                    pages: Vec::new(),
+                    entries: BTreeMap::new(),
+                })
+            },
+        });
+        fs_inner.inodes.insert(ino, Arc::downgrade(&inode));
+        inode
+    }
+}
+
+impl Inode for TmpInode {
+    fn type_(&self) -> InodeType {
+        self.type_
+    }
+    fn ino(&self) -> u64 {
+        self.ino
+    }
+    fn nlink(&self) -> u32 {
+        self.inner.exclusive_access().nlink
+    }
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let inner = self.inner.exclusive_access();
+        let end = inner.size.min(offset + buf.len());
+        let mut pos = offset;
+        while pos < end {
+            let page = inner.pages[pos / PAGE_SIZE].ppn.get_bytes_array();
+            let len = (PAGE_SIZE - pos % PAGE_SIZE).min(end - pos);
+            buf[pos - offset..pos - offset + len]
+                .copy_from_slice(&page[pos % PAGE_SIZE..pos % PAGE_SIZE + len]);
+            pos += len;
+        }
+        end.saturating_sub(offset)
+    }
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        let mut inner = self.inner.exclusive_access();
+        let mut fs = self.fs.exclusive_access();
+        let pages = (offset + buf.len() + PAGE_SIZE - 1) / PAGE_SIZE;
+        while inner.pages.len() < pages && fs.used_pages < fs.max_pages {
+            match frame_alloc() {
+                Some(frame) => inner.pages.push(frame),
+                None => break,
+            }
+            fs.used_pages += 1;
+        }
+        // as much as fits into the frames it got
+        let end = (inner.pages.len() * PAGE_SIZE).min(offset + buf.len());
+        if end <= offset {
+            return 0;
+        }
+        let mut pos = offset;
+        while pos < end {
+            let page = inner.pages[pos / PAGE_SIZE].ppn.get_bytes_array();
+            let len = (PAGE_SIZE - pos % PAGE_SIZE).min(end - pos);
+            page[pos % PAGE_SIZE..pos % PAGE_SIZE + len]
+                .copy_from_slice(&buf[pos - offset..pos - offset + len]);
+            pos += len;
+        }
+        inner.size = inner.size.max(end);
+        end - offset
+    }
+    fn clear(&self) {
+        let mut inner = self.inner.exclusive_access();
+        self.fs.exclusive_access().used_pages -= inner.pages.len();
+        inner.pages.clear();
+        inner.size = 0;
+    }
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        let inner = self.inner.exclusive_access();
+        inner
+            .entries
+            .get(name)
+            .map(|inode| inode.clone() as Arc<dyn Inode>)
+    }
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.entries.contains_key(name) {
+            return None;
+        }
+        let inode = TmpInode::new(&self.fs, type_);
+        inner.entries.insert(String::from(name), inode.clone());
+        Some(inode)
+    }
+    fn ls(&self) -> Vec<String> {
+        self.inner
+            .exclusive_access()
+            .entries
+            .keys()
+            .cloned()
+            .collect()
+    }
+    fn link(&self, name: &str, ino: u64) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        if inner.entries.contains_key(name) {
+            return false;
+        }
+        let inode = match self.fs.exclusive_access().inodes.get(&ino) {
+            Some(inode) => inode.upgrade(),
+            None => None,
+        };
+        match inode {
+            Some(inode) if inode.type_ != InodeType::Directory => {
+                inode.inner.exclusive_access().nlink += 1;
+                inner.entries.insert(String::from(name), inode);
+                true
+            }
+            _ => false,
+        }
+    }
+    fn unlink(&self, name: &str) -> bool {
+        let mut inner = self.inner.exclusive_access();
+        match inner.entries.get(name) {
+            Some(inode) if inode.type_ != InodeType::Directory => {}
+            _ => return false,
+        }
+        // the inode is freed here unless it is open
+        let inode = inner.entries.remove(name).unwrap();
+        inode.inner.exclusive_access().nlink -= 1;
+        true
+    }
+}
+
+impl Drop for TmpInode {
+    fn drop(&mut self) {
+        let mut fs = self.fs.exclusive_access();
+        fs.used_pages -= self.inner.exclusive_access().pages.len();
+        fs.inodes.remove(&self.ino);
+    }
+}
@@ -0,0 +1,414 @@
+//! Line discipline of the console
+//!
+//! Sits between the UART and the standard streams. In canonical mode the
+//! typed line is edited and echoed here and handed to `read` once it is
+//! complete. Control characters become signals or an end of file. Programs
+//! such as editors switch to raw mode through `TCSETS`.
+
+use super::{PollQueue, PollWaker};
+use crate::drivers::UART;
+use crate::mm::{translated_ref, translated_refmut, UserBuffer};
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::EAGAIN;
+use crate::task::{
+    add_task, block_current_interruptible, current_interrupted, current_process, current_task,
+    current_user_token, kill_process_group, processes_in_group, SignalFlags, TaskControlBlock,
+};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use bitflags::*;
+use lazy_static::*;
+
+/// Get the terminal attributes
+pub const TCGETS: usize = 0x5401;
+/// Set the terminal attributes now
+pub const TCSETS: usize = 0x5402;
+/// Set the terminal attributes once the output is drained
+pub const TCSETSW: usize = 0x5403;
+/// Set the terminal attributes and discard pending input
+pub const TCSETSF: usize = 0x5404;
+/// Get the foreground process group of the terminal
+pub const TIOCGPGRP: usize = 0x540f;
+/// Set the foreground process group of the terminal
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// Bytes typed or pasted ahead of `read` beyond this are dropped
+const INPUT_BUFFER_SIZE: usize = 4096;
+/// Number of control characters in [`Termios`]
+const NCCS: usize = 19;
+/// Index of the interrupt character, sends SIGINT
+const VINTR: usize = 0;
+/// Index of the quit character, sends SIGQUIT
+const VQUIT: usize = 1;
+/// Index of the character erasing the last one of the line
+const VERASE: usize = 2;
+/// Index of the character erasing the whole line
+const VKILL: usize = 3;
+/// Index of the end of file character
+const VEOF: usize = 4;
+/// Index of the raw mode read timeout, not supported
+const VTIME: usize = 5;
+/// Index of the minimum number of bytes of a raw mode read
+const VMIN: usize = 6;
+/// Index of the suspend character, sends SIGTSTP
+const VSUSP: usize = 10;
+/// Backspace, erases like [`VERASE`] whatever that is set to
+const BS: u8 = 0x08;
+
+bitflags! {
+    /// Input modes of [`Termios`]
+    pub struct InputFlags: u32 {
+        /// Translate carriage return to newline
+        const ICRNL = 0o400;
+    }
+}
+
+bitflags! {
+    /// Output modes of [`Termios`]
+    pub struct OutputFlags: u32 {
+        /// Process the output
+        const OPOST = 0o1;
+        /// Translate newline to carriage return and newline
+        const ONLCR = 0o4;
+    }
+}
+
+bitflags! {
+    /// Local modes of [`Termios`]
+    pub struct LocalFlags: u32 {
+        /// Turn the interrupt, quit and suspend characters into signals
+        const ISIG = 0o1;
+        /// Canonical mode, input is edited and read by lines
+        const ICANON = 0o2;
+        /// Echo the input
+        const ECHO = 0o10;
+        /// Erase the last character on screen for `VERASE`
+        const ECHOE = 0o20;
+        /// Erase the line on screen for `VKILL`
+        const ECHOK = 0o40;
+    }
+}
+
+/// Terminal attributes, laid out as in Linux
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    /// Control modes, only reported
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; NCCS],
+}
+
+impl Termios {
+    fn new() -> Self {
+        let mut cc = [0; NCCS];
+        cc[VINTR] = 0x03;
+        cc[VQUIT] = 0x1c;
+        cc[VERASE] = 0x7f;
+        cc[VKILL] = 0x15;
+        cc[VEOF] = 0x04;
+        cc[VTIME] = 0;
+        cc[VMIN] = 1;
+        cc[VSUSP] = 0x1a;
+        Self {
+            iflag: InputFlags::ICRNL.bits(),
+            oflag: (OutputFlags::OPOST | OutputFlags::ONLCR).bits(),
+            // CS8 | CREAD
+            cflag: 0o260,
+            lflag: (LocalFlags::ISIG
+                | LocalFlags::ICANON
+                | LocalFlags::ECHO
+                | LocalFlags::ECHOE
+                | LocalFlags::ECHOK)
+                .bits(),
+            line: 0,
+            cc,
+        }
+    }
+    fn iflag(&self) -> InputFlags {
+        InputFlags::from_bits_truncate(self.iflag)
+    }
+    fn oflag(&self) -> OutputFlags {
+        OutputFlags::from_bits_truncate(self.oflag)
+    }
+    fn lflag(&self) -> LocalFlags {
+        LocalFlags::from_bits_truncate(self.lflag)
+    }
+    fn canonical(&self) -> bool {
+        self.lflag().contains(LocalFlags::ICANON)
+    }
+}
+
+/// State of the console as a controlling terminal
+struct Tty {
+    termios: Termios,
+    /// Input ready to be read, `None` is an end of file typed on an empty line
+    input: VecDeque<Option<u8>>,
+    /// Line being edited in canonical mode
+    line: Vec<u8>,
+    /// Process group receiving the signals typed on the keyboard
+    foreground: Option<usize>,
+    /// Threads waiting for input
+    readers: VecDeque<Arc<TaskControlBlock>>,
+    /// Threads polling the standard input
+    pollers: PollQueue,
+}
+
+lazy_static! {
+    static ref TTY: UPSafeCell<Tty> = unsafe {
+        UPSafeCell::new(Tty {
+            termios: Termios::new(),
+            input: VecDeque::new(),
+            line: Vec::new(),
+            foreground: None,
+            readers: VecDeque::new(),
+            pollers: PollQueue::new(),
+        })
+    };
+}
+
+impl Tty {
+    fn wake_readers(&mut self) {
+        for task in self.readers.drain(..) {
+            add_task(task);
+        }
+        self.pollers.wake_all();
+    }
+    /// Whether a read of `len` bytes would return without waiting
+    fn ready(&self, len: usize) -> bool {
+        if self.termios.canonical() {
+            !self.input.is_empty()
+        } else {
+            let min = (self.termios.cc[VMIN] as usize).min(len);
+            self.input.len() >= min
+        }
+    }
+    /// Hand the edited line over to the readers
+    fn commit_line(&mut self) {
+        let line = core::mem::take(&mut self.line);
+        self.input.extend(line.into_iter().map(Some));
+        self.wake_readers();
+    }
+    fn echo(&self, bytes: &[u8]) {
+        if self.termios.lflag().contains(LocalFlags::ECHO) {
+            self.output(bytes);
+        }
+    }
+    fn output(&self, bytes: &[u8]) {
+        let onlcr = self
+            .termios
+            .oflag()
+            .contains(OutputFlags::OPOST | OutputFlags::ONLCR);
+        for &ch in bytes {
+            if onlcr && ch == b'\n' {
+                UART.write(b'\r');
+            }
+            UART.write(ch);
+        }
+    }
+    /// Run `ch` through the line discipline, returning the signal it stands for
+    fn receive(&mut self, mut ch: u8) -> Option<SignalFlags> {
+        let termios = self.termios;
+        let lflag = termios.lflag();
+        if ch == b'\r' && termios.iflag().contains(InputFlags::ICRNL) {
+            ch = b'\n';
+        }
+        if lflag.contains(LocalFlags::ISIG) {
+            let signal = if ch == termios.cc[VINTR] {
+                Some(SignalFlags::SIGINT)
+            } else if ch == termios.cc[VQUIT] {
+                Some(SignalFlags::SIGQUIT)
+            } else if ch == termios.cc[VSUSP] {
+                Some(SignalFlags::SIGTSTP)
+            } else {
+                None
+            };
+            if let Some(signal) = signal {
+                self.line.clear();
+                self.echo(&[b'^', ch ^ 0x40, b'\n']);
+                // readers go and look at their signals
+                self.wake_readers();
+                return Some(signal);
+            }
+        }
+        if !termios.canonical() {
+            if self.input.len() < INPUT_BUFFER_SIZE {
+                self.input.push_back(Some(ch));
+            }
+            self.echo(&[ch]);
+            self.wake_readers();
+            return None;
+        }
+        if ch == termios.cc[VERASE] || ch == BS {
+            if self.line.pop().is_some() && lflag.contains(LocalFlags::ECHOE) {
+                self.echo(b"\x08 \x08");
+            }
+        } else if ch == termios.cc[VKILL] {
+            if lflag.contains(LocalFlags::ECHOK) {
+                for _ in 0..self.line.len() {
+                    self.echo(b"\x08 \x08");
+                }
+            }
+            self.line.clear();
+        } else if ch == termios.cc[VEOF] {
+            if self.line.is_empty() {
+                self.input.push_back(None);
+                self.wake_readers();
+            } else {
+                self.commit_line();
+            }
+        } else if ch == b'\n' {
+            self.line.push(ch);
+            self.echo(&[ch]);
+            self.commit_line();
+        } else if self.input.len() + self.line.len() + 1 < INPUT_BUFFER_SIZE {
+            // keep room for the newline ending the line
+            self.line.push(ch);
+            self.echo(&[ch]);
+        }
+        None
+    }
+}
+
+/// Run the bytes received by the UART through the line discipline
+///
+/// Called on UART interrupts, so that typed signals also reach jobs which
+/// never read the console.
+pub fn console_receive() {
+    while let Some(ch) = UART.read() {
+        let mut tty = TTY.exclusive_access();
+        let signal = tty.receive(ch);
+        let foreground = tty.foreground;
+        drop(tty);
+        if let (Some(signal), Some(pgid)) = (signal, foreground) {
+            kill_process_group(pgid, signal);
+        }
+    }
+}
+
+/// Wait for input and read it into `user_buf`
+///
+/// In canonical mode at most one line is returned, and an end of file typed
+/// on an empty line reads as 0 bytes. In raw mode the read waits for `VMIN`
+/// bytes or as many as fit, `VTIME` is ignored.
+pub fn tty_read(user_buf: UserBuffer, nonblock: bool) -> Result<usize, isize> {
+    let mut tty = loop {
+        let mut tty = TTY.exclusive_access();
+        if tty.ready(user_buf.len()) || user_buf.len() == 0 {
+            break tty;
+        }
+        if nonblock {
+            return Err(EAGAIN);
+        }
+        if current_interrupted() {
+            // e.g. by Ctrl-C
+            return Ok(0);
+        }
+        tty.readers.push_back(current_task().unwrap());
+        drop(tty);
+        block_current_interruptible();
+    };
+    let canonical = tty.termios.canonical();
+    if canonical && tty.input.front() == Some(&None) {
+        tty.input.pop_front();
+        return Ok(0);
+    }
+    let mut read_size = 0;
+    for byte in user_buf.into_iter() {
+        let ch = loop {
+            match tty.input.front() {
+                // end of file markers mean nothing in raw mode
+                Some(None) if !canonical => {
+                    tty.input.pop_front();
+                }
+                Some(Some(ch)) => break Some(*ch),
+                _ => break None,
+            }
+        };
+        let ch = match ch {
+            Some(ch) => ch,
+            None => break,
+        };
+        tty.input.pop_front();
+        unsafe { byte.write_volatile(ch) };
+        read_size += 1;
+        if canonical && ch == b'\n' {
+            break;
+        }
+    }
+    Ok(read_size)
+}
+
+/// Write `user_buf` to the terminal, applying the output modes
+pub fn tty_write(user_buf: UserBuffer) -> usize {
+    let tty = TTY.exclusive_access();
+    for buffer in user_buf.buffers.iter() {
+        tty.output(buffer);
+    }
+    user_buf.len()
+}
+
+/// Whether input is waiting to be read
+pub fn tty_readable() -> bool {
+    !TTY.exclusive_access().input.is_empty()
+}
+
+/// Have `waker` woken up the next time input arrives
+pub fn tty_register_poller(waker: &Arc<PollWaker>) {
+    TTY.exclusive_access().pollers.register(waker);
+}
+
+/// Terminal requests shared by the standard streams
+pub fn tty_ioctl(request: usize, arg: usize) -> isize {
+    let token = current_user_token();
+    match request {
+        TCGETS => {
+            *translated_refmut(token, arg as *mut Termios) = TTY.exclusive_access().termios;
+            0
+        }
+        TCSETS | TCSETSW | TCSETSF => {
+            let termios = *translated_ref(token, arg as *const Termios);
+            let mut tty = TTY.exclusive_access();
+            if request == TCSETSF {
+                tty.input.clear();
+                tty.line.clear();
+            }
+            tty.termios = termios;
+            if !termios.canonical() && !tty.line.is_empty() {
+                // what was typed so far becomes readable as is
+                tty.commit_line();
+            }
+            // a raw mode reader may be satisfied by a smaller VMIN
+            tty.wake_readers();
+            0
+        }
+        TIOCGPGRP => {
+            let foreground = TTY.exclusive_access().foreground;
+            match foreground {
+                Some(pgid) => {
+                    *translated_refmut(token, arg as *mut i32) = pgid as i32;
+                    0
+                }
+                None => -1,
+            }
+        }
+        TIOCSPGRP => {
+            let pgid = *translated_ref(token, arg as *const i32) as usize;
+            let sid = current_process().inner_exclusive_access().sid;
+            // the group must exist in the caller's session
+            let valid = processes_in_group(pgid)
+                .iter()
+                .any(|p| p.inner_exclusive_access().sid == sid);
+            if !valid {
+                return -1;
+            }
+            TTY.exclusive_access().foreground = Some(pgid);
+            0
+        }
+        _ => -1,
+    }
+}
@@ -0,0 +1,230 @@
+//! Virtual filesystem: the interface of filesystems and the mount table
+//!
+//! Every filesystem is reached through the kernel [`Inode`] and
+//! [`FileSystem`] traits. Paths are looked up from the filesystem mounted
+//! at the longest prefix of them, the one at `/` being easy-fs on the disk.
+
+use super::devfs::DevFs;
+use super::easyfs::EasyFs;
+use super::tmpfs::TmpFs;
+use super::{File, OpenFlags};
+use crate::sync::{SleepLock, UPSafeCell};
+use crate::syscall::errno::{EBUSY, EINVAL, ENODEV, ENOENT, ENOTDIR};
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+use lazy_static::*;
+
+/// What an inode is
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InodeType {
+    File,
+    Directory,
+    /// Named pipe, opened through [`super::open_fifo`]
+    Fifo,
+    /// Device node of a device read and written by bytes, see
+    /// [`Inode::open_device`]
+    CharDevice,
+    /// Device node of a device read and written by blocks
+    BlockDevice,
+}
+
+/// An inode of some filesystem
+pub trait Inode: Send + Sync {
+    fn type_(&self) -> InodeType;
+    /// Inode number, unique within its filesystem
+    fn ino(&self) -> u64;
+    /// Number of names it has
+    fn nlink(&self) -> u32 {
+        1
+    }
+    /// Read from `offset`, returning how much was read, 0 at the end
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize;
+    /// Write at `offset`, returning how much was written, short once full
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize;
+    /// Truncate to zero size
+    fn clear(&self);
+    /// Entry `name` of this directory
+    fn find(&self, name: &str) -> Option<Arc<dyn Inode>>;
+    /// Make entry `name` of this directory, failing if it exists already
+    fn create(&self, name: &str, type_: InodeType) -> Option<Arc<dyn Inode>>;
+    /// Names of the entries of this directory
+    fn ls(&self) -> Vec<String>;
+    /// Make entry `name` of this directory for inode `ino` of the same
+    /// filesystem, failing if it exists already
+    fn link(&self, name: &str, ino: u64) -> bool;
+    /// Remove entry `name` of this directory, which is no directory itself
+    ///
+    /// What becomes of an inode left without names is up to the filesystem:
+    /// tmpfs frees it once nobody has it open either, while easy-fs drops
+    /// its data right away and keeps the disk inode, which it cannot free.
+    fn unlink(&self, name: &str) -> bool;
+    /// The device of a device node, opened with `flags` instead of the inode
+    fn open_device(&self, _flags: OpenFlags) -> Option<Arc<dyn File + Send + Sync>> {
+        None
+    }
+}
+
+/// A filesystem which can be mounted
+pub trait FileSystem: Send + Sync {
+    fn root_inode(&self) -> Arc<dyn Inode>;
+    /// Device number, see [`alloc_dev`]
+    fn dev(&self) -> u64;
+}
+
+/// An inode and the filesystem it belongs to
+pub type FsInode = (Arc<dyn FileSystem>, Arc<dyn Inode>);
+
+/// A new device number for a filesystem instance
+pub fn alloc_dev() -> u64 {
+    static NEXT_DEV: AtomicU64 = AtomicU64::new(1);
+    NEXT_DEV.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Makes a filesystem of some type out of the `source` and the options in
+/// `data` given to `mount`
+type MountFn = fn(source: &str, data: &str) -> Result<Arc<dyn FileSystem>, isize>;
+
+/// Filesystem types `mount` knows, by name
+const FILESYSTEM_TYPES: &[(&str, MountFn)] = &[("devfs", DevFs::mount), ("tmpfs", TmpFs::mount)];
+
+struct MountPoint {
+    /// Components of the absolute path it is mounted at
+    path: Vec<String>,
+    fs: Arc<dyn FileSystem>,
+}
+
+lazy_static! {
+    /// Taken around every filesystem operation, since easy-fs holds spin
+    /// locks while it sleeps waiting for the disk
+    pub(super) static ref FS_LOCK: SleepLock = SleepLock::new();
+    /// In the order they were mounted, the root filesystem first, then the
+    /// device nodes at `/dev` and a tmpfs at `/tmp` for scratch files
+    static ref MOUNTS: UPSafeCell<Vec<MountPoint>> = unsafe {
+        UPSafeCell::new(vec![
+            MountPoint {
+                path: Vec::new(),
+                fs: Arc::new(EasyFs::open()),
+            },
+            MountPoint {
+                path: vec![String::from("dev")],
+                fs: DevFs::mount("", "").unwrap(),
+            },
+            MountPoint {
+                path: vec![String::from("tmp")],
+                fs: TmpFs::mount("", "").unwrap(),
+            },
+        ])
+    };
+}
+
+/// Components of `path`, relative paths starting from `/` as well
+fn split_path(path: &str) -> Vec<String> {
+    let mut components: Vec<String> = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            name => components.push(String::from(name)),
+        }
+    }
+    components
+}
+
+/// Filesystem and inode at the absolute path `components`
+fn lookup_components(components: &[String]) -> Option<FsInode> {
+    let mounts = MOUNTS.exclusive_access();
+    let mount = mounts
+        .iter()
+        .filter(|mount| components.starts_with(&mount.path))
+        .max_by_key(|mount| mount.path.len())?;
+    let fs = mount.fs.clone();
+    let depth = mount.path.len();
+    drop(mounts);
+    let mut inode = fs.root_inode();
+    for name in &components[depth..] {
+        if inode.type_() != InodeType::Directory {
+            return None;
+        }
+        inode = inode.find(name)?;
+    }
+    Some((fs, inode))
+}
+
+/// Filesystem and inode at `path`
+pub fn lookup(path: &str) -> Option<FsInode> {
+    lookup_components(&split_path(path))
+}
+
+/// Directory holding `path`, with the last name of it
+pub fn lookup_parent(path: &str) -> Option<(FsInode, String)> {
+    let mut components = split_path(path);
+    let name = components.pop()?;
+    let (fs, dir) = lookup_components(&components)?;
+    if dir.type_() != InodeType::Directory {
+        return None;
+    }
+    Some(((fs, dir), name))
+}
+
+/// Mount a new filesystem of type `fstype` made from `source` at `target`,
+/// with the comma-separated options in `data`
+///
+/// easy-fs has no directories to mount on, so the mount point need not
+/// exist, but its parent has to be a directory.
+pub fn mount(source: &str, target: &str, fstype: &str, data: &str) -> Result<(), isize> {
+    let _fs = FS_LOCK.lock();
+    let make_fs = FILESYSTEM_TYPES
+        .iter()
+        .find(|(name, _)| *name == fstype)
+        .map(|(_, make_fs)| make_fs)
+        .ok_or(ENODEV)?;
+    let path = split_path(target);
+    if path.is_empty() {
+        return Err(EBUSY);
+    }
+    let (_, parent) = lookup_components(&path[..path.len() - 1]).ok_or(ENOENT)?;
+    if parent.type_() != InodeType::Directory {
+        return Err(ENOTDIR);
+    }
+    if let Some((_, inode)) = lookup_components(&path) {
+        if inode.type_() != InodeType::Directory {
+            return Err(ENOTDIR);
+        }
+    }
+    if MOUNTS
+        .exclusive_access()
+        .iter()
+        .any(|mount| mount.path == path)
+    {
+        return Err(EBUSY);
+    }
+    let fs = make_fs(source, data)?;
+    MOUNTS.exclusive_access().push(MountPoint { path, fs });
+    Ok(())
+}
+
+/// Unmount the filesystem at `target`, which nobody may be using
+pub fn umount(target: &str) -> Result<(), isize> {
+    let _fs = FS_LOCK.lock();
+    let path = split_path(target);
+    let mut mounts = MOUNTS.exclusive_access();
+    let index = mounts
+        .iter()
+        .position(|mount| mount.path == path)
+        .ok_or(EINVAL)?;
+    let mount = &mounts[index];
+    // open files keep their filesystem, and so do filesystems mounted below
+    let covered = mounts
+        .iter()
+        .any(|other| other.path.len() > path.len() && other.path.starts_with(&path));
+    if path.is_empty() || covered || Arc::strong_count(&mount.fs) > 1 {
+        return Err(EBUSY);
+    }
+    mounts.remove(index);
+    Ok(())
+}
@@ -64,6 +64,7 @@ pub fn rust_main() -> ! {
     println!("[kernel] Hello, world!");
     mm::init();
     mm::remap_test();
+    drivers::init();
     trap::init();
     trap::enable_timer_interrupt();
     timer::set_next_trigger();
@@ -64,6 +64,64 @@ impl MemorySet {
             None,
         );
     }
+    /// Map `frames` shared with other memory sets from `start_vpn` on
+    ///
+    /// The frames live as long as some memory set maps them.
+    pub fn insert_shared_area(
+        &mut self,
+        start_vpn: VirtPageNum,
+        frames: &[Arc<FrameTracker>],
+        permission: MapPermission,
+    ) {
+        let end_vpn = VirtPageNum(start_vpn.0 + frames.len());
+        let mut map_area = MapArea::new(
+            start_vpn.into(),
+            end_vpn.into(),
+            MapType::Shared,
+            permission,
+        );
+        for (vpn, frame) in map_area.vpn_range.into_iter().zip(frames) {
+            map_area.data_frames.insert(vpn, Arc::clone(frame));
+        }
+        self.push(map_area, None);
+    }
+    /// Unmap the shared area starting at `start_vpn`, returning whether there was one
+    pub fn remove_shared_area(&mut self, start_vpn: VirtPageNum) -> bool {
+        let shared = self.areas.iter().any(|area| {
+            area.map_type == MapType::Shared && area.vpn_range.get_start() == start_vpn
+        });
+        if shared {
+            self.remove_area_with_start_vpn(start_vpn);
+        }
+        shared
+    }
+    /// Whether a shared area maps frame `ppn`
+    pub fn shares_frame(&self, ppn: PhysPageNum) -> bool {
+        self.areas.iter().any(|area| {
+            area.map_type == MapType::Shared
+                && area.data_frames.values().any(|frame| frame.ppn == ppn)
+        })
+    }
+    /// Whether no area maps any page of `[start_vpn, end_vpn)`
+    pub fn is_free(&self, start_vpn: VirtPageNum, end_vpn: VirtPageNum) -> bool {
+        self.areas.iter().all(|area| {
+            area.vpn_range.get_end() <= start_vpn || end_vpn <= area.vpn_range.get_start()
+        })
+    }
+    /// The lowest `pages` unmapped pages from `from` on
+    pub fn find_free_area(&self, from: VirtPageNum, pages: usize) -> VirtPageNum {
+        let mut start_vpn = from;
+        loop {
+            let end_vpn = VirtPageNum(start_vpn.0 + pages);
+            // skip past the areas in the way until there are none
+            match self.areas.iter().find(|area| {
+                area.vpn_range.get_start() < end_vpn && start_vpn < area.vpn_range.get_end()
+            }) {
+                Some(area) => start_vpn = area.vpn_range.get_end(),
+                None => return start_vpn,
+            }
+        }
+    }
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
             .areas
@@ -222,6 +280,11 @@ impl MemorySet {
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
+            // shared memory stays shared with the child
+            if area.map_type == MapType::Shared {
+                memory_set.push(area.clone(), None);
+                continue;
+            }
             let new_area = MapArea::from_another(area);
             memory_set.push(new_area, None);
             // copy data from another space
@@ -262,7 +325,8 @@ impl MemorySet {
 #[derive(Clone)]
 pub struct MapArea {
     vpn_range: VPNRange,
-    data_frames: BTreeMap<VirtPageNum, FrameTracker>,
+    /// Frames of shared areas are also mapped by other memory sets
+    data_frames: BTreeMap<VirtPageNum, Arc<FrameTracker>>,
     map_type: MapType,
     map_perm: MapPermission,
 }
@@ -300,7 +364,10 @@ impl MapArea {
             MapType::Framed => {
                 let frame = frame_alloc().unwrap();
                 ppn = frame.ppn;
-                self.data_frames.insert(vpn, frame);
+                self.data_frames.insert(vpn, Arc::new(frame));
+            }
+            MapType::Shared => {
+                ppn = self.data_frames[&vpn].ppn;
             }
         }
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits).unwrap();
@@ -310,7 +377,7 @@ impl MapArea {
     pub fn unmap_one(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) {
         #[allow(clippy::single_match)]
         match self.map_type {
-            MapType::Framed => {
+            MapType::Framed | MapType::Shared => {
                 self.data_frames.remove(&vpn);
             }
             _ => {}
@@ -352,10 +419,12 @@ impl MapArea {
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
-/// map type for memory set: identical or framed
+/// map type for memory set: identical, framed or shared
 pub enum MapType {
     Identical,
     Framed,
+    /// Framed with frames given beforehand, which other areas may map too
+    Shared,
 }
 
 bitflags! {
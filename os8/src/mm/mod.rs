@@ -12,6 +12,7 @@ mod frame_allocator;
 mod heap_allocator;
 mod memory_set;
 mod page_table;
+mod shm;
 
 pub use address::{PhysAddr, PhysPageNum, VirtAddr, VirtPageNum};
 pub use address::{StepByOne, VPNRange};
@@ -20,6 +21,7 @@ pub use memory_set::{remap_test, kernel_token};
 pub use memory_set::{MapPermission, MemorySet, KERNEL_SPACE};
 pub use page_table::{translated_byte_buffer, translated_refmut, translated_ref, translated_str, PageTableEntry};
 pub use page_table::{PTEFlags, PageTable, UserBuffer};
+pub use shm::{shm_frames, shm_get, shm_remove, IPC_PRIVATE};
 
 /// initiate heap allocator, frame allocator and kernel space
 pub fn init() {
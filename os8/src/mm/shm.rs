@@ -0,0 +1,86 @@
+//! Shared memory segments, frames that several memory sets map at once
+//!
+//! A segment only holds its frames until it is removed. Processes attaching
+//! it share the frames with it, so they stay alive until the last process
+//! detaches them, exits or replaces its image.
+
+use super::{frame_alloc, FrameTracker};
+use crate::config::PAGE_SIZE;
+use crate::sync::UPSafeCell;
+use crate::syscall::errno::{EEXIST, EINVAL, ENOENT, ENOMEM};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// Key asking for a new segment nobody else can look up
+pub const IPC_PRIVATE: usize = 0;
+
+struct ShmSegment {
+    key: usize,
+    /// Size asked for at creation, in bytes
+    size: usize,
+    frames: Vec<Arc<FrameTracker>>,
+}
+
+struct ShmTable {
+    segments: BTreeMap<usize, ShmSegment>,
+    next_id: usize,
+}
+
+lazy_static! {
+    static ref SHM_TABLE: UPSafeCell<ShmTable> = unsafe {
+        UPSafeCell::new(ShmTable {
+            segments: BTreeMap::new(),
+            next_id: 0,
+        })
+    };
+}
+
+/// Id of the segment of `key`, created with `size` bytes if `create`
+///
+/// Fails with `EEXIST` if `exclusive` and it exists already, with `ENOENT`
+/// if it does not and `create` is false, and with `EINVAL` if it is smaller
+/// than `size`.
+pub fn shm_get(key: usize, size: usize, create: bool, exclusive: bool) -> Result<usize, isize> {
+    let mut table = SHM_TABLE.exclusive_access();
+    if key != IPC_PRIVATE {
+        if let Some((&id, segment)) = table.segments.iter().find(|(_, seg)| seg.key == key) {
+            if create && exclusive {
+                return Err(EEXIST);
+            }
+            if size > segment.size {
+                return Err(EINVAL);
+            }
+            return Ok(id);
+        }
+        if !create {
+            return Err(ENOENT);
+        }
+    }
+    if size == 0 {
+        return Err(EINVAL);
+    }
+    let mut frames = Vec::new();
+    for _ in 0..(size + PAGE_SIZE - 1) / PAGE_SIZE {
+        frames.push(Arc::new(frame_alloc().ok_or(ENOMEM)?));
+    }
+    let id = table.next_id;
+    table.next_id += 1;
+    table.segments.insert(id, ShmSegment { key, size, frames });
+    Ok(id)
+}
+
+/// Frames of segment `id`, to be mapped by a process attaching it
+pub fn shm_frames(id: usize) -> Option<Vec<Arc<FrameTracker>>> {
+    SHM_TABLE
+        .exclusive_access()
+        .segments
+        .get(&id)
+        .map(|segment| segment.frames.clone())
+}
+
+/// Remove segment `id`, whose frames are freed once nobody maps them
+pub fn shm_remove(id: usize) -> bool {
+    SHM_TABLE.exclusive_access().segments.remove(&id).is_some()
+}
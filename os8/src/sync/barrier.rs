@@ -0,0 +1,93 @@
+use super::UPSafeCell;
+use super::{block_on, current_thread, remove_object, set_holders, task_key, unblock};
+use crate::syscall::errno::EINTR;
+use crate::task::TaskControlBlock;
+use crate::task::{add_task, block_current_interruptible, current_process, current_task};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// A barrier releasing its waiters once `count` threads have arrived
+///
+/// It can be used again right away for the next round.
+pub struct Barrier {
+    count: usize,
+    pub inner: UPSafeCell<BarrierInner>,
+}
+
+pub struct BarrierInner {
+    /// Threads arrived in the current round
+    wait_queue: Vec<Arc<TaskControlBlock>>,
+}
+
+impl Barrier {
+    pub fn new(count: usize) -> Self {
+        Self {
+            count,
+            inner: unsafe {
+                UPSafeCell::new(BarrierInner {
+                    wait_queue: Vec::new(),
+                })
+            },
+        }
+    }
+
+    /// Identity of this barrier in the wait-for graph
+    fn object(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Wait for the other threads, returns whether the calling thread is the
+    /// last one to arrive
+    ///
+    /// Fails with `EDEADLK` if not enough threads could ever arrive, and with
+    /// `EINTR` if the thread has to exit before they do.
+    pub fn wait(&self) -> Result<bool, isize> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.wait_queue.len() + 1 >= self.count {
+            for task in inner.wait_queue.drain(..) {
+                if let Some(thread) = task_key(&task) {
+                    unblock(thread);
+                }
+                add_task(task);
+            }
+            set_holders(self.object(), Vec::new());
+            return Ok(true);
+        }
+        // any thread of the process which has not arrived yet may do so
+        let task = current_task().unwrap();
+        let arrived: Vec<usize> = inner
+            .wait_queue
+            .iter()
+            .chain(core::iter::once(&task))
+            .filter_map(|task| task_key(task))
+            .map(|(_, tid)| tid)
+            .collect();
+        let (pid, _) = current_thread();
+        let holders = current_process()
+            .inner_exclusive_access()
+            .live_tids()
+            .into_iter()
+            .filter(|tid| !arrived.contains(tid))
+            .map(|tid| (pid, tid))
+            .collect();
+        set_holders(self.object(), holders);
+        block_on(self.object())?;
+        inner.wait_queue.push(Arc::clone(&task));
+        drop(inner);
+        block_current_interruptible();
+        // the last thread to arrive took us off the queue
+        let mut inner = self.inner.exclusive_access();
+        if let Some(idx) = inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+            inner.wait_queue.remove(idx);
+            unblock(current_thread());
+            return Err(EINTR);
+        }
+        Ok(false)
+    }
+}
+
+impl Drop for Barrier {
+    fn drop(&mut self) {
+        remove_object(self.object());
+    }
+}
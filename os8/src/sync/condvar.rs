@@ -1,5 +1,7 @@
 use crate::sync::{Mutex, UPSafeCell};
-use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use crate::syscall::errno::{EINTR, ETIMEDOUT};
+use crate::task::{current_interrupted, current_task, TaskControlBlock};
+use crate::timer::{block_current_until, wakeup_task};
 use alloc::{collections::VecDeque, sync::Arc};
 
 pub struct Condvar {
@@ -24,16 +26,66 @@ impl Condvar {
     pub fn signal(&self) {
         let mut inner = self.inner.exclusive_access();
         if let Some(task) = inner.wait_queue.pop_front() {
-            add_task(task);
+            wakeup_task(task);
         }
     }
 
-    pub fn wait(&self, mutex: Arc<dyn Mutex>) {
-        mutex.unlock();
+    /// Wake up all waiters, returning how many there were
+    pub fn broadcast(&self) -> usize {
         let mut inner = self.inner.exclusive_access();
-        inner.wait_queue.push_back(current_task().unwrap());
+        let waiters = inner.wait_queue.len();
+        for task in inner.wait_queue.drain(..) {
+            wakeup_task(task);
+        }
+        waiters
+    }
+
+    /// Whether threads are waiting, so that it cannot be destroyed
+    pub fn is_busy(&self) -> bool {
+        !self.inner.exclusive_access().wait_queue.is_empty()
+    }
+
+    /// Wait for a signal, then take `mutex` back
+    ///
+    /// Fails with `EPERM` if the calling thread does not hold `mutex`, and
+    /// without holding `mutex` if taking it back would deadlock.
+    pub fn wait(&self, mutex: Arc<dyn Mutex>) -> Result<(), isize> {
+        self.wait_until(mutex, None)
+    }
+
+    /// Like [`Self::wait`], but stop waiting for a signal at `expire_ms` if given
+    ///
+    /// The mutex is taken back either way, then it fails with `ETIMEDOUT`
+    /// if the deadline passed first. A thread which has to exit meanwhile
+    /// fails with `EINTR` without taking it back.
+    pub fn wait_until(&self, mutex: Arc<dyn Mutex>, expire_ms: Option<usize>) -> Result<(), isize> {
+        let task = current_task().unwrap();
+        // queued before the mutex is released, so that any signal sent once
+        // it can be taken finds us
+        let mut inner = self.inner.exclusive_access();
+        inner.wait_queue.push_back(Arc::clone(&task));
+        if let Err(err) = mutex.unlock() {
+            inner.wait_queue.pop_back();
+            return Err(err);
+        }
+        drop(inner);
+        block_current_until(expire_ms);
+        // signal() took us off the queue, unless the timer fired or we were
+        // interrupted first
+        let mut inner = self.inner.exclusive_access();
+        let timed_out = match inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+            Some(idx) => inner.wait_queue.remove(idx).is_some(),
+            None => false,
+        };
         drop(inner);
-        block_current_and_run_next();
-        mutex.lock();
+        if current_interrupted() {
+            return Err(EINTR);
+        }
+        mutex.lock()?;
+        if timed_out {
+            Err(ETIMEDOUT)
+        } else {
+            Ok(())
+        }
     }
 }
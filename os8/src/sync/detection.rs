@@ -1,178 +1,275 @@
-//! Weak Bank Detector
+//! Deadlock detection over the synchronization objects of a process
+//!
+//! Every mutex and semaphore of a process is a [`Resource`] with a number of
+//! free units and the units held by each thread. A thread blocked in a lock,
+//! a `down` or a condvar wait is recorded as [`Waiting`] for something. The
+//! safety check then runs the banker's algorithm over all of them at once:
+//! threads which are not waiting are assumed to finish and give back what
+//! they hold, a condvar waiter can go on once some live thread may still
+//! signal it and the mutex it sleeps with is free.
 
+use alloc::collections::{BTreeMap, BTreeSet};
 use alloc::vec::Vec;
 
-#[derive(Clone)]
-pub struct ResourceList{
-    pub avail: Vec<i32>,
-    pub allocated: Vec<Vec<usize>>,
-    pub need: Vec<Vec<usize>>,
+/// A synchronization object that threads hold units of
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resource {
+    Mutex(usize),
+    Semaphore(usize),
 }
 
-impl ResourceList{
-    pub fn new() -> Self{
-        Self { avail: Vec::new(), allocated: Vec::new(), need: Vec::new()}
+/// What a blocked thread is waiting for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waiting {
+    /// One unit of the resource
+    Acquire(Resource),
+    /// A signal on the condvar, then the mutex released by the wait
+    Condvar { condvar: usize, mutex: usize },
+}
+
+/// An edge of the wait-for graph
+#[derive(Debug)]
+pub struct WaitFor {
+    pub tid: usize,
+    pub waiting: Waiting,
+    /// Threads holding units of the awaited resource
+    pub holders: Vec<usize>,
+}
+
+#[derive(Clone, Default)]
+struct ResourceState {
+    avail: usize,
+    /// Units held, by tid
+    allocated: BTreeMap<usize, usize>,
+}
+
+#[derive(Clone, Default)]
+pub struct Detector {
+    resources: BTreeMap<Resource, ResourceState>,
+    /// Blocked threads, with the order in which they started waiting
+    waiting: BTreeMap<usize, (usize, Waiting)>,
+    next_seq: usize,
+}
+
+impl Detector {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    pub fn init_size(&mut self, size: usize, rid: usize){
-        if rid >= self.avail.len(){
-            let n = rid - self.avail.len();
-            for _ in 0..n {
-                self.avail.push(0);
-            }
-            self.avail.push(size as i32);
-        }else{
-            self.avail[rid] = size as i32;
-        }
+    /// Register a resource with `units` free units
+    pub fn add_resource(&mut self, resource: Resource, units: usize) {
+        self.resources.insert(
+            resource,
+            ResourceState {
+                avail: units,
+                allocated: BTreeMap::new(),
+            },
+        );
     }
 
-    pub fn alloc_one(&mut self, size: usize, rid: usize, tid: usize){
-        debug!("{} alloc {}, size: {}", tid, rid, size);
-        if tid >= self.allocated.len(){
-            let n = tid - self.allocated.len();
-            for _ in 0..=n{
-                self.allocated.push(Vec::new());
-            }
-        }
-        if rid >= self.allocated[tid].len(){
-            let n = rid - self.allocated[tid].len();
-            for _ in 0..=n{
-                self.allocated[tid].push(0);
-            }
-        }
-        debug!("before alloc: allocated: {:?}", self.allocated[tid]);
-        self.allocated[tid][rid] += size;
-        self.avail[rid] -= size as i32;
-        self.need[tid][rid] -= size;
-        debug!("after alloc: allocated: {:?}", self.allocated[tid]);
-        // let check = self.need[tid].iter().any(|n| *n !=0);
-        // if !check{
-        //     self.need[tid].clear();
-        // }
-    }
-
-    pub fn is_enough(&self, rid: usize, size: usize) -> bool{
-        if self.avail[rid] < size as i32{
-            return false;
-        }
-        true
+    /// Forget a destroyed resource
+    pub fn remove_resource(&mut self, resource: Resource) {
+        self.resources.remove(&resource);
     }
 
-    pub fn is_dead(&mut self, tid: usize, rid: usize, size: usize, task_set: Vec<bool>) -> bool{
-        debug!("{} check deadlock, request {}", tid, rid);
-        if tid >= self.need.len(){
-            let n = tid - self.need.len();
-            for _ in 0..=n{
-                self.need.push(Vec::new());
-            }
-        }
-        if rid >= self.need[tid].len(){
-            let n = rid - self.need[tid].len();
-            for _ in 0..=n{
-                self.need[tid].push(0);
-            }
-        }
-        debug!("before {} add need: \navail: {:?}, allocated: {:?}, need: {:?}, task_set: {:?}", 
-            tid, self.avail, self.allocated, self.need, task_set);
-        self.need[tid][rid] = size;
-        if tid != 0 && rid == 0{
-            return false;
-        }
-        if self.is_enough(rid, size){
-            return false;
-        }
-        let not_finish = task_set.iter().any(|t| *t == false);
-        if !not_finish{
-            return false;
-        }
-        let mut task_set = task_set;
-        let allocated = self.allocated.clone();
-        let mut avail = self.avail.clone();
-        loop{
-            let all_finish = task_set.iter().all(|t| *t == true);
-            if all_finish{
-                return false;
-            }
-            let mut cnt = 0;
-            for i in 0..task_set.len(){
-                if i >= self.need.len(){
-                    break;
-                }
-                if task_set[i]{
-                    continue;
-                }
-                let enough_i = self.need[i].iter().enumerate()
-                    .all(|(idx, &t)| avail[idx] >= t as i32);
-                if enough_i{
-                    avail.iter_mut().enumerate()
-                        .for_each(|(idx, n)| 
-                        if idx < allocated[i].len(){
-                            *n += allocated[i][idx] as i32;
-                        }
-                    );
-                    task_set[i] = true;
-                    cnt += 1;
-                }
-            }
-            if cnt == 0{
-                return true;
-            }
-        }
+    /// Record that `tid` asks for one unit of `resource`
+    pub fn request(&mut self, tid: usize, resource: Resource) {
+        self.wait(tid, Waiting::Acquire(resource));
     }
 
-    pub fn cycle(&mut self, rid: usize, size: usize, tid: usize){
-        self.avail[rid] += size as i32;
-        debug!("{} release {} size: {}", tid, rid, size);
-        debug!("before release, allocated: {:?}", self.allocated[tid]);
-        self.allocated[tid][rid] -= size;
-        debug!("after release, allocated: {:?}", self.allocated[tid]);
+    /// Record that `tid` waits on `condvar`, giving `mutex` back
+    pub fn wait_condvar(&mut self, tid: usize, condvar: usize, mutex: usize) {
+        self.release(tid, Resource::Mutex(mutex));
+        self.wait(tid, Waiting::Condvar { condvar, mutex });
     }
-}
 
-#[derive(Clone)]
-pub struct Detector{
-    pub mutexes: ResourceList,
-    pub semes: ResourceList,
-}
+    /// Forget the pending request of `tid`, after it has been refused
+    pub fn cancel(&mut self, tid: usize) {
+        self.waiting.remove(&tid);
+    }
 
-impl Detector{
-    pub fn new() -> Self{
-        Self{
-            mutexes: ResourceList::new(),
-            semes: ResourceList::new(),
-        }
+    /// `tid` got the unit of `resource` it asked for
+    ///
+    /// The unit is only accounted for here, by the thread which actually got
+    /// it, since the primitives do not all pick the next owner the same way.
+    pub fn acquire(&mut self, tid: usize, resource: Resource) {
+        self.waiting.remove(&tid);
+        self.take(tid, resource);
     }
 
-    pub fn create_mutex(&mut self, mid: usize){
-        self.mutexes.init_size(1, mid);
+    /// `tid` got a unit of `resource` without being recorded as waiting for it
+    ///
+    /// Used for trylocks and timed waits, which cannot block for ever.
+    pub fn take(&mut self, tid: usize, resource: Resource) {
+        let state = self.resources.entry(resource).or_default();
+        state.avail = state.avail.saturating_sub(1);
+        *state.allocated.entry(tid).or_insert(0) += 1;
     }
 
-    pub fn cycle_mutex(&mut self, tid: usize, mid: usize){
-        self.mutexes.cycle(mid, 1, tid);
+    /// `tid` gives one unit of `resource` back
+    ///
+    /// Semaphores may be raised by threads holding none of their units. The
+    /// unit stays free until its next owner calls [`Self::acquire`] or
+    /// [`Self::take`].
+    pub fn release(&mut self, tid: usize, resource: Resource) {
+        let state = self.resources.entry(resource).or_default();
+        if let Some(units) = state.allocated.get_mut(&tid) {
+            *units -= 1;
+            if *units == 0 {
+                state.allocated.remove(&tid);
+            }
+        }
+        state.avail += 1;
     }
 
-    pub fn create_sem(&mut self, sid: usize, size: usize){
-        self.semes.init_size(size, sid);
+    /// The longest waiter on `condvar` was woken up and now waits for its mutex
+    pub fn signal(&mut self, condvar: usize) {
+        let next = self
+            .waiting
+            .iter()
+            .filter(|(_, &(_, waiting))| {
+                matches!(waiting, Waiting::Condvar { condvar: c, .. } if c == condvar)
+            })
+            .min_by_key(|(_, &(seq, _))| seq)
+            .map(|(&tid, &(_, waiting))| (tid, waiting));
+        if let Some((tid, Waiting::Condvar { mutex, .. })) = next {
+            self.wait(tid, Waiting::Acquire(Resource::Mutex(mutex)));
+        }
     }
 
-    pub fn cycle_sem(&mut self, tid: usize, sid: usize){
-        self.semes.cycle(sid, 1, tid)
+    /// Whether `tid` can never go on, `live` being the threads which have not exited
+    pub fn is_deadlocked(&self, tid: usize, live: &[usize]) -> bool {
+        self.stuck_threads(live, None).contains(&tid)
     }
 
-    pub fn check_mutex(&mut self, tid: usize, mid: usize, task_set: Vec<bool>) -> bool{
-        self.mutexes.is_dead(tid, mid, 1, task_set)
+    /// Whether `tid` could never go on if it waited on `condvar`, giving
+    /// `mutex` back
+    ///
+    /// The wait is not recorded, so nothing is left to undo if it is refused.
+    pub fn would_deadlock_on_condvar(
+        &self,
+        tid: usize,
+        condvar: usize,
+        mutex: usize,
+        live: &[usize],
+    ) -> bool {
+        self.stuck_threads(live, Some((tid, condvar, mutex)))
+            .contains(&tid)
     }
 
-    pub fn alloc_mutex(&mut self, tid: usize, mid: usize){
-        self.mutexes.alloc_one(1, mid, tid);
+    /// The wait-for graph: every blocked thread with the threads it waits for
+    ///
+    /// Condvar waiters are listed with the holders of their mutex, since any
+    /// thread may signal them.
+    pub fn wait_for_graph(&self) -> Vec<WaitFor> {
+        self.waiting
+            .iter()
+            .map(|(&tid, &(_, waiting))| {
+                let resource = match waiting {
+                    Waiting::Acquire(resource) => resource,
+                    Waiting::Condvar { mutex, .. } => Resource::Mutex(mutex),
+                };
+                let holders = self
+                    .resources
+                    .get(&resource)
+                    .map(|state| state.allocated.keys().copied().collect())
+                    .unwrap_or_default();
+                WaitFor {
+                    tid,
+                    waiting,
+                    holders,
+                }
+            })
+            .collect()
     }
 
-    pub fn check_semaphore(&mut self, tid: usize, sid: usize, task_set: Vec<bool>) -> bool{
-        self.semes.is_dead(tid, sid, 1, task_set)
+    /// Log the wait-for graph, meant to be called when a deadlock is found
+    pub fn dump(&self) {
+        warn!("deadlock detected, wait-for graph:");
+        for edge in self.wait_for_graph() {
+            warn!(
+                "  thread {} waits for {:?} held by {:?}",
+                edge.tid, edge.waiting, edge.holders
+            );
+        }
     }
 
-    pub fn alloc_semaphore(&mut self, tid: usize, sid: usize){
-        self.semes.alloc_one(1, sid, tid);
+    fn wait(&mut self, tid: usize, waiting: Waiting) {
+        self.waiting.insert(tid, (self.next_seq, waiting));
+        self.next_seq += 1;
     }
 
+    /// Threads which cannot finish even if all others that can do
+    ///
+    /// With `condvar_wait`, thread `tid` is taken to wait on `condvar` having
+    /// given `mutex` back. The unit is left free, as [`Self::release`] does.
+    fn stuck_threads(
+        &self,
+        live: &[usize],
+        condvar_wait: Option<(usize, usize, usize)>,
+    ) -> BTreeSet<usize> {
+        let mut work: BTreeMap<Resource, usize> = self
+            .resources
+            .iter()
+            .map(|(&resource, state)| (resource, state.avail))
+            .collect();
+        let waiting = |tid: usize| match condvar_wait {
+            Some((waiter, condvar, mutex)) if waiter == tid => {
+                Some(Waiting::Condvar { condvar, mutex })
+            }
+            _ => self.waiting.get(&tid).map(|&(_, waiting)| waiting),
+        };
+        // units still held by a thread, less the mutex the condvar wait gives back
+        let held = |tid: usize, resource: Resource, units: usize| match condvar_wait {
+            Some((waiter, _, mutex)) if waiter == tid && resource == Resource::Mutex(mutex) => {
+                units - 1
+            }
+            _ => units,
+        };
+        if let Some((tid, _, mutex)) = condvar_wait {
+            let resource = Resource::Mutex(mutex);
+            let holds = self
+                .resources
+                .get(&resource)
+                .map_or(false, |state| state.allocated.contains_key(&tid));
+            if holds {
+                *work.entry(resource).or_insert(0) += 1;
+            }
+        }
+        // exited threads still holding units are assumed to give them back
+        let mut threads: BTreeSet<usize> = live.iter().copied().collect();
+        for state in self.resources.values() {
+            threads.extend(state.allocated.keys());
+        }
+        threads.extend(self.waiting.keys());
+        threads.extend(condvar_wait.map(|(tid, _, _)| tid));
+        // whether some live thread may still signal a condvar
+        let mut signaller = false;
+        loop {
+            let free = |resource: Resource| work.get(&resource).map_or(false, |&n| n > 0);
+            let finished: Vec<usize> = threads
+                .iter()
+                .copied()
+                .filter(|&tid| match waiting(tid) {
+                    None => true,
+                    Some(Waiting::Acquire(resource)) => free(resource),
+                    Some(Waiting::Condvar { mutex, .. }) => {
+                        signaller && free(Resource::Mutex(mutex))
+                    }
+                })
+                .collect();
+            if finished.is_empty() {
+                return threads;
+            }
+            for tid in finished {
+                threads.remove(&tid);
+                signaller |= live.contains(&tid);
+                for (resource, state) in self.resources.iter() {
+                    if let Some(&units) = state.allocated.get(&tid) {
+                        *work.entry(*resource).or_insert(0) += held(tid, *resource, units);
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,142 @@
+//! Futexes, wait queues keyed by the physical address of a user word
+//!
+//! User-space locks update their word with atomics and only enter the kernel
+//! to sleep while it holds some value, or to wake up sleepers after changing
+//! it. Keying the queues by physical address lets processes sharing the
+//! page meet on the same queue, whatever virtual address they map it at.
+//!
+//! Only threads of a process mapping the word can wake up its waiters, the
+//! waiter's own process or those sharing the page, so these are the holders
+//! of a futex in the wait-for graph.
+
+use super::{block_on, current_thread, deadlock_detection_enabled, set_holders, task_key};
+use super::{unblock, ThreadKey, UPSafeCell};
+use crate::mm::PhysAddr;
+use crate::syscall::errno::{EINTR, ETIMEDOUT};
+use crate::task::{current_interrupted, current_process, current_task, processes_sharing_frame};
+use crate::task::{TaskControlBlock, TaskStatus};
+use crate::timer::{block_current_until, wakeup_task};
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+lazy_static! {
+    static ref FUTEXES: UPSafeCell<BTreeMap<usize, VecDeque<Arc<TaskControlBlock>>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
+}
+
+/// Block the current thread on futex `key`, until woken up or `expire_ms`
+///
+/// The caller has checked the user word beforehand. Fails with `ETIMEDOUT`
+/// if the deadline passed first, and with `EINTR` if the thread has to exit.
+/// Timed waits end anyway, so they are not checked for deadlocks.
+pub fn futex_wait(key: usize, expire_ms: Option<usize>) -> Result<(), isize> {
+    if expire_ms.is_none() {
+        if deadlock_detection_enabled() {
+            set_holders(key, futex_holders(key));
+        }
+        block_on(key)?;
+    }
+    let task = current_task().unwrap();
+    FUTEXES
+        .exclusive_access()
+        .entry(key)
+        .or_default()
+        .push_back(Arc::clone(&task));
+    block_current_until(expire_ms);
+    if expire_ms.is_none() {
+        unblock(current_thread());
+    }
+    // wakers take the thread off its queue, only the timer or an interruption leaves it there
+    if !remove_waiter(&task) {
+        Ok(())
+    } else if current_interrupted() {
+        Err(EINTR)
+    } else {
+        Err(ETIMEDOUT)
+    }
+}
+
+/// Wake up to `count` threads blocked on futex `key`, returning how many
+pub fn futex_wake(key: usize, count: usize) -> usize {
+    futex_requeue(key, count, key, 0)
+}
+
+/// Wake up to `count` threads blocked on futex `key`, then move up to
+/// `requeue` of the others onto futex `key2`
+///
+/// Returns the number of threads woken up.
+pub fn futex_requeue(key: usize, count: usize, key2: usize, requeue: usize) -> usize {
+    let mut futexes = FUTEXES.exclusive_access();
+    let mut queue = match futexes.remove(&key) {
+        Some(queue) => queue,
+        None => return 0,
+    };
+    let mut woken = 0;
+    while woken < count {
+        let task = match queue.pop_front() {
+            Some(task) => task,
+            None => break,
+        };
+        // threads of an exiting process must not run again
+        if task.inner_exclusive_access().task_status == TaskStatus::Exited {
+            continue;
+        }
+        if let Some(waiter) = task_key(&task) {
+            unblock(waiter);
+        }
+        wakeup_task(task);
+        woken += 1;
+    }
+    let rest = queue.split_off(queue.len().min(requeue));
+    if !rest.is_empty() {
+        futexes.insert(key, rest);
+    }
+    if !queue.is_empty() {
+        // the wait-for graph loses track of them rather than having them
+        // wait on the wrong futex
+        for waiter in queue.iter().filter_map(|task| task_key(task)) {
+            unblock(waiter);
+        }
+        futexes.entry(key2).or_default().extend(queue);
+    }
+    woken
+}
+
+/// Threads able to wake up the waiters of futex `key`: those of the calling
+/// process and of the processes sharing the page of the word
+fn futex_holders(key: usize) -> Vec<ThreadKey> {
+    let process = current_process();
+    let mut processes = processes_sharing_frame(PhysAddr::from(key).floor());
+    if !processes.iter().any(|p| Arc::ptr_eq(p, &process)) {
+        processes.push(process);
+    }
+    processes
+        .iter()
+        .flat_map(|process| {
+            let pid = process.getpid();
+            let tids = process.inner_exclusive_access().live_tids();
+            tids.into_iter().map(move |tid| (pid, tid))
+        })
+        .collect()
+}
+
+/// Take `task` off whatever futex queue it is on, returning whether it was
+fn remove_waiter(task: &Arc<TaskControlBlock>) -> bool {
+    let mut futexes = FUTEXES.exclusive_access();
+    let found = futexes.iter_mut().find_map(|(&key, queue)| {
+        let idx = queue.iter().position(|waiter| Arc::ptr_eq(waiter, task))?;
+        queue.remove(idx);
+        Some((key, queue.is_empty()))
+    });
+    match found {
+        Some((key, empty)) => {
+            if empty {
+                futexes.remove(&key);
+            }
+            true
+        }
+        None => false,
+    }
+}
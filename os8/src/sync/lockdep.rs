@@ -0,0 +1,191 @@
+//! Lock-order validator for [`UPSafeCell`](super::UPSafeCell) borrows and
+//! the sleeping locks, [`MutexBlocking`](super::MutexBlocking) and
+//! [`SleepLock`](super::SleepLock)
+//!
+//! Only built with debug assertions. Every cell belongs to the lock class of
+//! the type it wraps, so that all process control blocks, or all pipe ring
+//! buffers, share one class, and every sleeping lock to the class of its own
+//! type. Each time a cell is borrowed while others are,
+//! the order between their classes is recorded along with the call sites
+//! which established it.
+//!
+//! Two code paths borrowing the same cells in opposite orders only panic
+//! when they happen to overlap, e.g. when `sys_waitpid` runs while a child
+//! is in `exit_current_and_run_next`, which testing rarely triggers. Here
+//! the inversion is reported as soon as both orders have been seen once,
+//! even far apart in time. Only the first one is, as the others often
+//! follow from it.
+//!
+//! A sleeping lock stays held while other threads run, so it is only
+//! ordered against what its holder takes.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::panic::Location;
+use lazy_static::*;
+
+/// A lock class, the name of the type behind the cells
+pub type Class = &'static str;
+
+type Site = &'static Location<'static>;
+
+/// A borrow currently alive
+struct Held {
+    id: usize,
+    class: Class,
+    site: Site,
+    /// Thread holding a sleeping lock, none for a cell borrow
+    owner: Option<usize>,
+}
+
+struct LockDep {
+    held: Vec<Held>,
+    /// For each class, the classes taken while holding it, with the call
+    /// sites of the first time it happened
+    order: BTreeMap<Class, BTreeMap<Class, (Site, Site)>>,
+    next_id: usize,
+    reported: bool,
+    /// Thread running, by the address of its control block, 0 when idle
+    current: usize,
+}
+
+/// Not a `UPSafeCell`, which would come back here
+struct LockDepCell(RefCell<LockDep>);
+
+unsafe impl Sync for LockDepCell {}
+
+lazy_static! {
+    static ref LOCKDEP: LockDepCell = LockDepCell(RefCell::new(LockDep {
+        held: Vec::new(),
+        order: BTreeMap::new(),
+        next_id: 0,
+        reported: false,
+        current: 0,
+    }));
+}
+
+impl LockDep {
+    /// Classes leading from `from` to `to` in the recorded order, if any
+    fn path(&self, from: Class, to: Class) -> Option<Vec<Class>> {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![vec![from]];
+        while let Some(path) = stack.pop() {
+            let last = *path.last().unwrap();
+            if last == to {
+                return Some(path);
+            }
+            if !visited.insert(last) {
+                continue;
+            }
+            if let Some(after) = self.order.get(last) {
+                for &next in after.keys() {
+                    let mut longer = path.clone();
+                    longer.push(next);
+                    stack.push(longer);
+                }
+            }
+        }
+        None
+    }
+
+    fn report(&self, held: &Held, class: Class, site: Site, path: &[Class]) {
+        println!(
+            "\u{1B}[31m[lockdep] lock order inversion: {} borrowed at {}\u{1B}[0m",
+            class, site
+        );
+        println!(
+            "\u{1B}[31m[lockdep]   while holding {} borrowed at {}\u{1B}[0m",
+            held.class, held.site
+        );
+        println!("\u{1B}[31m[lockdep] but the opposite order was seen before:\u{1B}[0m");
+        for pair in path.windows(2) {
+            let (first, then) = self.order[pair[0]][pair[1]];
+            println!(
+                "\u{1B}[31m[lockdep]   {} borrowed at {}, then {} at {}\u{1B}[0m",
+                pair[0], first, pair[1], then
+            );
+        }
+    }
+}
+
+/// Record a borrow of a cell of `class` at `site`, returning its id
+pub fn acquire(class: Class, site: Site) -> usize {
+    record(class, site, false)
+}
+
+/// Record that the running thread took a sleeping lock of `class` at
+/// `site`, returning its id
+pub fn acquire_sleeping(class: Class, site: Site) -> usize {
+    record(class, site, true)
+}
+
+fn record(class: Class, site: Site, sleeping: bool) -> usize {
+    // a borrow made while reporting, by the console for instance, is ignored
+    let mut lockdep = match LOCKDEP.0.try_borrow_mut() {
+        Ok(lockdep) => lockdep,
+        Err(_) => return usize::MAX,
+    };
+    let lockdep = &mut *lockdep;
+    let current = lockdep.current;
+    for held in lockdep.held.iter() {
+        // a sleeping lock of another thread, which may well be blocked
+        if held.owner.map_or(false, |owner| owner != current) {
+            continue;
+        }
+        // cells of one class nest, like a parent and a child process
+        if held.class == class {
+            continue;
+        }
+        if let Some(after) = lockdep.order.get(held.class) {
+            if after.contains_key(class) {
+                continue;
+            }
+        }
+        if let Some(path) = lockdep.path(class, held.class) {
+            if !lockdep.reported {
+                lockdep.reported = true;
+                lockdep.report(held, class, site, &path);
+            }
+            continue;
+        }
+        lockdep
+            .order
+            .entry(held.class)
+            .or_default()
+            .insert(class, (held.site, site));
+    }
+    let id = lockdep.next_id;
+    lockdep.next_id += 1;
+    lockdep.held.push(Held {
+        id,
+        class,
+        site,
+        owner: if sleeping { Some(current) } else { None },
+    });
+    id
+}
+
+/// Record the end of the borrow `id`
+pub fn release(id: usize) {
+    if let Ok(mut lockdep) = LOCKDEP.0.try_borrow_mut() {
+        if let Some(idx) = lockdep.held.iter().rposition(|held| held.id == id) {
+            lockdep.held.remove(idx);
+        }
+    }
+}
+
+/// The thread at `thread` runs from now on, 0 for the idle control flow
+pub fn switch_to(thread: usize) {
+    if let Ok(mut lockdep) = LOCKDEP.0.try_borrow_mut() {
+        lockdep.current = thread;
+    }
+}
+
+/// Forget the sleeping locks `thread` held when it exited
+pub fn thread_exited(thread: usize) {
+    if let Ok(mut lockdep) = LOCKDEP.0.try_borrow_mut() {
+        lockdep.held.retain(|held| held.owner != Some(thread));
+    }
+}
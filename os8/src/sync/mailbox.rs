@@ -0,0 +1,159 @@
+//! Per-process mailboxes, bounded queues of messages sent by pid
+
+use super::{UPRefMut, UPSafeCell};
+use crate::fs::{PollEvents, PollQueue, PollWaker};
+use crate::syscall::errno::{EAGAIN, EINTR, ESRCH};
+use crate::task::{
+    add_task, block_current_interruptible, current_interrupted, current_task, TaskControlBlock,
+};
+use alloc::vec::Vec;
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// Messages a mailbox holds at most
+pub const MAIL_SLOTS: usize = 16;
+/// Longer messages are truncated to this many bytes
+pub const MAIL_MAX_LEN: usize = 256;
+
+pub struct Mailbox {
+    inner: UPSafeCell<MailboxInner>,
+}
+
+pub struct MailboxInner {
+    messages: VecDeque<Vec<u8>>,
+    /// Threads waiting for a message to arrive
+    readers: VecDeque<Arc<TaskControlBlock>>,
+    /// Threads waiting for a free slot
+    writers: VecDeque<Arc<TaskControlBlock>>,
+    /// Threads polling it through a `mailfd`
+    pollers: PollQueue,
+    /// The owner exited, nothing will be read any more
+    closed: bool,
+}
+
+impl MailboxInner {
+    fn readable(&self) -> bool {
+        !self.messages.is_empty()
+    }
+
+    fn writable(&self) -> bool {
+        self.messages.len() < MAIL_SLOTS
+    }
+}
+
+impl Mailbox {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(MailboxInner {
+                    messages: VecDeque::new(),
+                    readers: VecDeque::new(),
+                    writers: VecDeque::new(),
+                    pollers: PollQueue::new(),
+                    closed: false,
+                })
+            },
+        }
+    }
+
+    /// Wait until a message is waiting, or fail with `EAGAIN` if `nonblock`
+    pub fn wait_readable(&self, nonblock: bool) -> Result<(), isize> {
+        self.wait(MailboxInner::readable, false, nonblock)
+            .map(|_| ())
+    }
+
+    /// Wait until a slot is free, or fail with `EAGAIN` if `nonblock`
+    ///
+    /// Fails with `ESRCH` once the owner has exited.
+    pub fn wait_writable(&self, nonblock: bool) -> Result<(), isize> {
+        self.wait(MailboxInner::writable, true, nonblock)
+            .map(|_| ())
+    }
+
+    /// Take the oldest message, waiting for one unless `nonblock`
+    pub fn recv(&self, nonblock: bool) -> Result<Vec<u8>, isize> {
+        let mut inner = self.wait(MailboxInner::readable, false, nonblock)?;
+        let message = inner.messages.pop_front().unwrap();
+        wake_all(&mut inner.writers);
+        inner.pollers.wake_all();
+        Ok(message)
+    }
+
+    /// Queue `message`, waiting for a free slot unless `nonblock`
+    pub fn send(&self, mut message: Vec<u8>, nonblock: bool) -> Result<(), isize> {
+        message.truncate(MAIL_MAX_LEN);
+        let mut inner = self.wait(MailboxInner::writable, true, nonblock)?;
+        inner.messages.push_back(message);
+        wake_all(&mut inner.readers);
+        inner.pollers.wake_all();
+        Ok(())
+    }
+
+    /// Drop the messages of an exiting owner and fail the pending sends
+    pub fn close(&self) {
+        let mut inner = self.inner.exclusive_access();
+        inner.closed = true;
+        inner.messages.clear();
+        wake_all(&mut inner.writers);
+        inner.pollers.wake_all();
+    }
+
+    /// Which of `events` would not block: `IN` with a message waiting, `OUT`
+    /// with a free slot
+    pub fn poll(&self, events: PollEvents) -> PollEvents {
+        let inner = self.inner.exclusive_access();
+        let mut ready = PollEvents::empty();
+        if inner.readable() {
+            ready |= PollEvents::IN;
+        }
+        if inner.writable() && !inner.closed {
+            ready |= PollEvents::OUT;
+        }
+        ready & events
+    }
+
+    /// Have `waker` woken up once a message arrives or a slot is freed
+    pub fn register_poller(&self, waker: &Arc<PollWaker>) {
+        self.inner.exclusive_access().pollers.register(waker);
+    }
+
+    /// Block until `ready` holds, returning the locked mailbox
+    ///
+    /// Everyone waiting is woken up on a change, since a thread only
+    /// checking for readiness does not consume the message it waited for.
+    fn wait(
+        &self,
+        ready: fn(&MailboxInner) -> bool,
+        writer: bool,
+        nonblock: bool,
+    ) -> Result<UPRefMut<'_, MailboxInner>, isize> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if inner.closed {
+                return Err(ESRCH);
+            }
+            if ready(&inner) {
+                return Ok(inner);
+            }
+            if nonblock {
+                return Err(EAGAIN);
+            }
+            if current_interrupted() {
+                return Err(EINTR);
+            }
+            let task = current_task().unwrap();
+            if writer {
+                inner.writers.push_back(task);
+            } else {
+                inner.readers.push_back(task);
+            }
+            drop(inner);
+            block_current_interruptible();
+        }
+    }
+}
+
+fn wake_all(queue: &mut VecDeque<Arc<TaskControlBlock>>) {
+    for task in queue.drain(..) {
+        add_task(task);
+    }
+}
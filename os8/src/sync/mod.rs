@@ -1,13 +1,31 @@
 //! Synchronization and interior mutability primitives
 
+mod barrier;
 mod condvar;
+mod futex;
+#[cfg(debug_assertions)]
+pub mod lockdep;
+mod mailbox;
 mod mutex;
+mod rwlock;
 mod semaphore;
+mod sleep_lock;
 mod up;
 mod detection;
+mod wait_graph;
 
+pub use barrier::Barrier;
 pub use condvar::Condvar;
-pub use mutex::{Mutex, MutexBlocking, MutexSpin};
+pub use futex::{futex_requeue, futex_wait, futex_wake};
+pub use mailbox::{Mailbox, MAIL_MAX_LEN, MAIL_SLOTS};
+pub use mutex::{refresh_effective_priority, Mutex, MutexBlocking, MutexSpin};
+pub use rwlock::RwLock;
 pub use semaphore::Semaphore;
-pub use up::UPSafeCell;
-pub use detection::*;
\ No newline at end of file
+pub use sleep_lock::{SleepLock, SleepLockGuard};
+pub use up::{UPRefMut, UPSafeCell};
+pub use detection::*;
+pub use wait_graph::{
+    block_on, current_thread, deadlock_detection_enabled, forget_process, forget_thread,
+    inherit_deadlock_policy, remove_object, set_deadlock_policy, set_holders, task_key, unblock,
+    DeadlockPolicy, ThreadKey,
+};
\ No newline at end of file
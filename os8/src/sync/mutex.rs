@@ -1,88 +1,394 @@
-use super::UPSafeCell;
+use super::{block_on, current_thread, remove_object, set_holders, task_key, unblock};
+use super::{ThreadKey, UPSafeCell};
+use crate::syscall::errno::{EBUSY, EINTR, EPERM, ETIMEDOUT};
 use crate::task::TaskControlBlock;
-use crate::task::{add_task, current_task};
-use crate::task::{block_current_and_run_next, suspend_current_and_run_next};
+use crate::task::{current_interrupted, suspend_current_and_run_next};
+use crate::task::{current_process, current_task};
+use crate::timer::{block_current_until, get_time_ms, wakeup_task};
+use alloc::sync::Weak;
+use alloc::vec;
+use alloc::vec::Vec;
 use alloc::{collections::VecDeque, sync::Arc};
 
 pub trait Mutex: Sync + Send {
-    fn lock(&self);
-    fn unlock(&self);
+    /// Take the mutex, failing with `EDEADLK` if it would never be released
+    fn lock(&self) -> Result<(), isize>;
+    /// Take the mutex if it is free, else wait for it until `expire_ms` if given
+    ///
+    /// Fails with `EBUSY` without a deadline and `ETIMEDOUT` once it passes.
+    fn try_lock(&self, expire_ms: Option<usize>) -> Result<(), isize>;
+    /// Give the mutex back, failing with `EPERM` if the calling thread does not hold it
+    fn unlock(&self) -> Result<(), isize>;
+    /// Take the mutex once more if it is recursive and the calling thread holds it
+    fn relock(&self) -> bool {
+        false
+    }
+    /// Give back one of the times the calling thread took a recursive mutex
+    ///
+    /// Returns false, doing nothing, unless it still holds it afterwards.
+    fn unlock_nested(&self) -> bool {
+        false
+    }
+    /// Whether it is held or waited for, so that it cannot be destroyed
+    fn is_busy(&self) -> bool;
 }
 
 pub struct MutexSpin {
-    locked: UPSafeCell<bool>,
+    /// Holder, if locked
+    owner: UPSafeCell<Option<ThreadKey>>,
 }
 
 impl MutexSpin {
     pub fn new() -> Self {
         Self {
-            locked: unsafe { UPSafeCell::new(false) },
+            owner: unsafe { UPSafeCell::new(None) },
         }
     }
 }
 
 impl Mutex for MutexSpin {
-    fn lock(&self) {
+    fn lock(&self) -> Result<(), isize> {
         loop {
-            let mut locked = self.locked.exclusive_access();
-            if *locked {
-                drop(locked);
+            let mut owner = self.owner.exclusive_access();
+            if owner.is_some() {
+                drop(owner);
+                if current_interrupted() {
+                    return Err(EINTR);
+                }
                 suspend_current_and_run_next();
                 continue;
             } else {
-                *locked = true;
-                return;
+                *owner = Some(current_thread());
+                return Ok(());
             }
         }
     }
 
-    fn unlock(&self) {
-        let mut locked = self.locked.exclusive_access();
-        *locked = false;
+    fn try_lock(&self, expire_ms: Option<usize>) -> Result<(), isize> {
+        loop {
+            let mut owner = self.owner.exclusive_access();
+            if owner.is_none() {
+                *owner = Some(current_thread());
+                return Ok(());
+            }
+            drop(owner);
+            match expire_ms {
+                None => return Err(EBUSY),
+                Some(expire_ms) if get_time_ms() >= expire_ms => return Err(ETIMEDOUT),
+                Some(_) if current_interrupted() => return Err(EINTR),
+                Some(_) => suspend_current_and_run_next(),
+            }
+        }
+    }
+
+    fn is_busy(&self) -> bool {
+        self.owner.exclusive_access().is_some()
+    }
+
+    fn unlock(&self) -> Result<(), isize> {
+        let mut owner = self.owner.exclusive_access();
+        if *owner != Some(current_thread()) {
+            return Err(EPERM);
+        }
+        *owner = None;
+        Ok(())
     }
 }
 
+/// A mutex putting waiters to sleep, with priority inheritance
+///
+/// The owner runs with the highest priority of the threads waiting for it,
+/// passed on to the owner of the mutex it is blocked on in turn, and the
+/// mutex goes to the waiter of highest priority.
+///
+/// A recursive mutex can be taken again by its holder, and is released once
+/// given back as many times.
 pub struct MutexBlocking {
+    recursive: bool,
     inner: UPSafeCell<MutexBlockingInner>,
+    /// Handle stored in the threads holding or waiting for this mutex
+    this: Weak<MutexBlocking>,
 }
 
 pub struct MutexBlockingInner {
     locked: bool,
+    /// Tid of the holder
+    owner: Option<usize>,
+    /// Times the holder took it
+    depth: usize,
     wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Id of the hold in the lock-order validator
+    #[cfg(debug_assertions)]
+    lockdep_id: usize,
+}
+
+impl MutexBlockingInner {
+    /// Index and effective priority of the first waiter of highest priority
+    fn top_waiter(&self) -> Option<(usize, isize)> {
+        let mut top: Option<(usize, isize)> = None;
+        for (idx, task) in self.wait_queue.iter().enumerate() {
+            let priority = task.inner_exclusive_access().effective_priority;
+            if top.map_or(true, |(_, top_priority)| priority > top_priority) {
+                top = Some((idx, priority));
+            }
+        }
+        top
+    }
 }
 
 impl MutexBlocking {
-    pub fn new() -> Self {
-        Self {
+    pub fn new() -> Arc<Self> {
+        Self::with_recursion(false)
+    }
+
+    pub fn new_recursive() -> Arc<Self> {
+        Self::with_recursion(true)
+    }
+
+    fn with_recursion(recursive: bool) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            recursive,
             inner: unsafe {
                 UPSafeCell::new(MutexBlockingInner {
                     locked: false,
+                    owner: None,
+                    depth: 0,
                     wait_queue: VecDeque::new(),
+                    #[cfg(debug_assertions)]
+                    lockdep_id: usize::MAX,
                 })
             },
+            this: this.clone(),
+        })
+    }
+
+    /// Identity of this mutex in the wait-for graph
+    fn object(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Thread holding this mutex, mutexes being private to a process
+    fn owner_task(&self) -> Option<Arc<TaskControlBlock>> {
+        let owner = self.inner.exclusive_access().owner?;
+        let process = current_process();
+        let process_inner = process.inner_exclusive_access();
+        process_inner.tasks.get(owner).cloned().flatten()
+    }
+
+    /// Whether the calling thread holds this mutex
+    fn held_by_current(&self, mutex_inner: &MutexBlockingInner) -> bool {
+        let tid = current_task()
+            .unwrap()
+            .inner_exclusive_access()
+            .res
+            .as_ref()
+            .map(|res| res.tid);
+        mutex_inner.locked && mutex_inner.owner == tid
+    }
+}
+
+/// Raise the owner of `mutex` to `priority`, and so on down the chain of
+/// mutexes the owners are blocked on
+fn boost_owners(mut mutex: Arc<MutexBlocking>, priority: isize) {
+    while let Some(owner) = mutex.owner_task() {
+        let mut owner_inner = owner.inner_exclusive_access();
+        // also stops at a cycle, all its threads being raised already
+        if owner_inner.effective_priority >= priority {
+            return;
+        }
+        owner_inner.effective_priority = priority;
+        match owner_inner.blocked_on.as_ref().and_then(Weak::upgrade) {
+            Some(next) => mutex = next,
+            None => return,
         }
     }
 }
 
-impl Mutex for MutexBlocking {
-    fn lock(&self) {
+/// Recompute the priority of the owner of `mutex` once a waiter gave up, and
+/// so on down the chain of mutexes the owners are blocked on
+fn restore_owners(mut mutex: Arc<MutexBlocking>) {
+    while let Some(owner) = mutex.owner_task() {
+        let before = owner.inner_exclusive_access().effective_priority;
+        refresh_effective_priority(&owner);
+        let owner_inner = owner.inner_exclusive_access();
+        // the rest of the chain is left as it is too, which also ends a cycle
+        if owner_inner.effective_priority == before {
+            return;
+        }
+        match owner_inner.blocked_on.as_ref().and_then(Weak::upgrade) {
+            Some(next) => mutex = next,
+            None => return,
+        }
+    }
+}
+
+/// Recompute the priority `task` runs with from its own priority and the
+/// waiters of the mutexes it still holds
+pub fn refresh_effective_priority(task: &TaskControlBlock) {
+    let held: Vec<Arc<MutexBlocking>> = task
+        .inner_exclusive_access()
+        .held_mutexes
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect();
+    let inherited = held
+        .iter()
+        .filter_map(|mutex| mutex.inner.exclusive_access().top_waiter())
+        .map(|(_, priority)| priority)
+        .max();
+    let mut task_inner = task.inner_exclusive_access();
+    task_inner.effective_priority = inherited.map_or(task_inner.priority, |inherited| {
+        inherited.max(task_inner.priority)
+    });
+}
+
+impl MutexBlocking {
+    /// Take the mutex, waiting for it until `expire_ms` if given
+    ///
+    /// Timed waits end anyway, so they are not checked for deadlocks. Fails
+    /// with `EINTR` if the thread has to exit meanwhile.
+    #[track_caller]
+    fn lock_until(&self, expire_ms: Option<usize>) -> Result<(), isize> {
         let mut mutex_inner = self.inner.exclusive_access();
+        let task = current_task().unwrap();
         if mutex_inner.locked {
-            mutex_inner.wait_queue.push_back(current_task().unwrap());
+            if expire_ms.is_none() {
+                block_on(self.object())?;
+            }
+            let mut task_inner = task.inner_exclusive_access();
+            task_inner.blocked_on = Some(self.this.clone());
+            let priority = task_inner.effective_priority;
+            drop(task_inner);
+            mutex_inner.wait_queue.push_back(Arc::clone(&task));
             drop(mutex_inner);
-            block_current_and_run_next();
+            boost_owners(self.this.upgrade().unwrap(), priority);
+            block_current_until(expire_ms);
+            // unlock() handed the mutex over to us, unless the timer fired
+            // or we were interrupted first
+            let mut mutex_inner = self.inner.exclusive_access();
+            let idx = mutex_inner
+                .wait_queue
+                .iter()
+                .position(|waiter| Arc::ptr_eq(waiter, &task));
+            if let Some(idx) = idx {
+                mutex_inner.wait_queue.remove(idx);
+                drop(mutex_inner);
+                task.inner_exclusive_access().blocked_on = None;
+                if expire_ms.is_none() {
+                    unblock(current_thread());
+                }
+                // the owners no longer run on our behalf
+                restore_owners(self.this.upgrade().unwrap());
+                return Err(if current_interrupted() {
+                    EINTR
+                } else {
+                    ETIMEDOUT
+                });
+            }
         } else {
+            let mut task_inner = task.inner_exclusive_access();
             mutex_inner.locked = true;
+            mutex_inner.owner = task_inner.res.as_ref().map(|res| res.tid);
+            mutex_inner.depth = 1;
+            task_inner.held_mutexes.push(self.this.clone());
+            drop(task_inner);
+            set_holders(self.object(), vec![current_thread()]);
+            drop(mutex_inner);
+        }
+        #[cfg(debug_assertions)]
+        {
+            let id = super::lockdep::acquire_sleeping(
+                core::any::type_name::<Self>(),
+                core::panic::Location::caller(),
+            );
+            self.inner.exclusive_access().lockdep_id = id;
+        }
+        Ok(())
+    }
+}
+
+impl Mutex for MutexBlocking {
+    fn lock(&self) -> Result<(), isize> {
+        self.lock_until(None)
+    }
+
+    fn try_lock(&self, expire_ms: Option<usize>) -> Result<(), isize> {
+        if expire_ms.is_none() && self.inner.exclusive_access().locked {
+            return Err(EBUSY);
         }
+        self.lock_until(expire_ms)
+    }
+
+    fn is_busy(&self) -> bool {
+        // waiters only queue up while it is held
+        self.inner.exclusive_access().locked
     }
 
-    fn unlock(&self) {
+    fn relock(&self) -> bool {
         let mut mutex_inner = self.inner.exclusive_access();
-        assert!(mutex_inner.locked);
-        if let Some(waking_task) = mutex_inner.wait_queue.pop_front() {
-            add_task(waking_task);
+        if !self.recursive || !self.held_by_current(&mutex_inner) {
+            return false;
+        }
+        mutex_inner.depth += 1;
+        true
+    }
+
+    fn unlock_nested(&self) -> bool {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if mutex_inner.depth <= 1 || !self.held_by_current(&mutex_inner) {
+            return false;
+        }
+        mutex_inner.depth -= 1;
+        true
+    }
+
+    fn unlock(&self) -> Result<(), isize> {
+        let mut mutex_inner = self.inner.exclusive_access();
+        if !self.held_by_current(&mutex_inner) {
+            return Err(EPERM);
+        }
+        #[cfg(debug_assertions)]
+        super::lockdep::release(mutex_inner.lockdep_id);
+        let task = current_task().unwrap();
+        task.inner_exclusive_access()
+            .held_mutexes
+            .retain(|mutex| !Weak::ptr_eq(mutex, &self.this));
+        let next = mutex_inner
+            .top_waiter()
+            .and_then(|(idx, _)| mutex_inner.wait_queue.remove(idx));
+        if let Some(waking_task) = next {
+            // the lock is handed over to the woken thread
+            let owner = task_key(&waking_task);
+            if let Some(owner) = owner {
+                unblock(owner);
+            }
+            set_holders(self.object(), owner.into_iter().collect());
+            let inherited = mutex_inner.top_waiter().map(|(_, priority)| priority);
+            let mut waking_inner = waking_task.inner_exclusive_access();
+            mutex_inner.owner = waking_inner.res.as_ref().map(|res| res.tid);
+            mutex_inner.depth = 1;
+            waking_inner.blocked_on = None;
+            waking_inner.held_mutexes.push(self.this.clone());
+            if let Some(inherited) = inherited {
+                waking_inner.effective_priority = waking_inner.effective_priority.max(inherited);
+            }
+            drop(waking_inner);
+            wakeup_task(waking_task);
         } else {
             mutex_inner.locked = false;
+            mutex_inner.owner = None;
+            mutex_inner.depth = 0;
+            set_holders(self.object(), Vec::new());
         }
+        drop(mutex_inner);
+        // give back what the waiters lent us
+        refresh_effective_priority(&task);
+        Ok(())
+    }
+}
+
+impl Drop for MutexBlocking {
+    fn drop(&mut self) {
+        remove_object(self.object());
     }
 }
@@ -0,0 +1,141 @@
+use super::{block_on, current_thread, remove_object, set_holders, task_key, unblock};
+use super::{ThreadKey, UPRefMut, UPSafeCell};
+use crate::syscall::errno::{EINTR, EPERM};
+use crate::task::{add_task, block_current_interruptible, current_task, TaskControlBlock};
+use alloc::vec::Vec;
+use alloc::{collections::VecDeque, sync::Arc};
+
+/// A reader-writer lock preferring writers
+///
+/// Readers arriving while a writer waits queue up behind it, so that a
+/// stream of readers cannot starve writers. The lock is handed over to the
+/// woken threads directly.
+pub struct RwLock {
+    pub inner: UPSafeCell<RwLockInner>,
+}
+
+pub struct RwLockInner {
+    writer: Option<ThreadKey>,
+    /// Threads holding a read lock, once per lock taken
+    readers: Vec<ThreadKey>,
+    /// Blocked threads, with whether they want to write
+    wait_queue: VecDeque<(Arc<TaskControlBlock>, bool)>,
+}
+
+impl RwLockInner {
+    fn holders(&self) -> Vec<ThreadKey> {
+        self.writer
+            .iter()
+            .chain(self.readers.iter())
+            .copied()
+            .collect()
+    }
+
+    fn writer_waiting(&self) -> bool {
+        self.wait_queue.iter().any(|&(_, write)| write)
+    }
+}
+
+impl RwLock {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(RwLockInner {
+                    writer: None,
+                    readers: Vec::new(),
+                    wait_queue: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    /// Identity of this lock in the wait-for graph
+    fn object(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// Take the lock shared, failing with `EDEADLK` if it would never be released
+    pub fn read(&self) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer.is_some() || inner.writer_waiting() {
+            return self.wait(inner, false);
+        }
+        inner.readers.push(current_thread());
+        set_holders(self.object(), inner.holders());
+        Ok(())
+    }
+
+    /// Take the lock exclusive, failing with `EDEADLK` if it would never be released
+    pub fn write(&self) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        if inner.writer.is_some() || !inner.readers.is_empty() {
+            return self.wait(inner, true);
+        }
+        inner.writer = Some(current_thread());
+        set_holders(self.object(), inner.holders());
+        Ok(())
+    }
+
+    /// Wait until unlock() hands the lock over, failing with `EINTR` if the
+    /// thread has to exit first
+    fn wait(&self, mut inner: UPRefMut<RwLockInner>, write: bool) -> Result<(), isize> {
+        block_on(self.object())?;
+        let task = current_task().unwrap();
+        inner.wait_queue.push_back((Arc::clone(&task), write));
+        drop(inner);
+        block_current_interruptible();
+        let mut inner = self.inner.exclusive_access();
+        let idx = inner
+            .wait_queue
+            .iter()
+            .position(|(waiter, _)| Arc::ptr_eq(waiter, &task));
+        if let Some(idx) = idx {
+            inner.wait_queue.remove(idx);
+            unblock(current_thread());
+            return Err(EINTR);
+        }
+        Ok(())
+    }
+
+    /// Give back the lock the calling thread holds, fails with `EPERM` if none
+    pub fn unlock(&self) -> Result<(), isize> {
+        let mut inner = self.inner.exclusive_access();
+        let thread = current_thread();
+        if inner.writer == Some(thread) {
+            inner.writer = None;
+        } else if let Some(idx) = inner.readers.iter().position(|&reader| reader == thread) {
+            inner.readers.remove(idx);
+        } else {
+            return Err(EPERM);
+        }
+        if inner.writer.is_none() && inner.readers.is_empty() {
+            let writer = inner.wait_queue.iter().position(|&(_, write)| write);
+            if let Some(idx) = writer {
+                let (task, _) = inner.wait_queue.remove(idx).unwrap();
+                inner.writer = task_key(&task);
+                wake(task);
+            } else {
+                // no writer is waiting, so let all the readers in
+                for (task, _) in core::mem::take(&mut inner.wait_queue) {
+                    inner.readers.extend(task_key(&task));
+                    wake(task);
+                }
+            }
+        }
+        set_holders(self.object(), inner.holders());
+        Ok(())
+    }
+}
+
+fn wake(task: Arc<TaskControlBlock>) {
+    if let Some(thread) = task_key(&task) {
+        unblock(thread);
+    }
+    add_task(task);
+}
+
+impl Drop for RwLock {
+    fn drop(&mut self) {
+        remove_object(self.object());
+    }
+}
@@ -1,5 +1,9 @@
-use crate::sync::UPSafeCell;
-use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use crate::sync::{block_on, current_thread, remove_object, set_holders, task_key, unblock};
+use crate::sync::{ThreadKey, UPSafeCell};
+use crate::syscall::errno::{EINTR, ETIMEDOUT};
+use crate::task::{current_interrupted, current_task, TaskControlBlock};
+use crate::timer::{block_current_until, wakeup_task};
+use alloc::vec::Vec;
 use alloc::{collections::VecDeque, sync::Arc};
 
 pub struct Semaphore {
@@ -9,6 +13,8 @@ pub struct Semaphore {
 pub struct SemaphoreInner {
     pub count: isize,
     pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Threads holding the units taken, once per unit
+    pub holders: Vec<ThreadKey>,
 }
 
 impl Semaphore {
@@ -18,28 +24,92 @@ impl Semaphore {
                 UPSafeCell::new(SemaphoreInner {
                     count: res_count as isize,
                     wait_queue: VecDeque::new(),
+                    holders: Vec::new(),
                 })
             },
         }
     }
 
+    /// Identity of this semaphore in the wait-for graph
+    fn object(&self) -> usize {
+        self as *const Self as usize
+    }
+
     pub fn up(&self) {
         let mut inner = self.inner.exclusive_access();
         inner.count += 1;
+        // threads that never took a unit may raise the semaphore too
+        let thread = current_thread();
+        if let Some(idx) = inner.holders.iter().position(|&holder| holder == thread) {
+            inner.holders.remove(idx);
+        }
         if inner.count <= 0 {
             if let Some(task) = inner.wait_queue.pop_front() {
-                add_task(task);
+                // the unit is handed over to the woken thread
+                if let Some(holder) = task_key(&task) {
+                    unblock(holder);
+                    inner.holders.push(holder);
+                }
+                wakeup_task(task);
             }
         }
+        set_holders(self.object(), inner.holders.clone());
     }
 
-    pub fn down(&self) {
+    /// Take a unit, failing with `EDEADLK` if none would ever be given back
+    pub fn down(&self) -> Result<(), isize> {
+        self.down_until(None)
+    }
+
+    /// Take a unit, waiting for one until `expire_ms` if given
+    ///
+    /// Fails with `ETIMEDOUT` once the deadline passes, and with `EINTR` if
+    /// the thread has to exit meanwhile. Timed waits end anyway, so they are
+    /// not checked for deadlocks.
+    pub fn down_until(&self, expire_ms: Option<usize>) -> Result<(), isize> {
         let mut inner = self.inner.exclusive_access();
         inner.count -= 1;
         if inner.count < 0 {
-            inner.wait_queue.push_back(current_task().unwrap());
+            if expire_ms.is_none() {
+                if let Err(err) = block_on(self.object()) {
+                    inner.count += 1;
+                    return Err(err);
+                }
+            }
+            let task = current_task().unwrap();
+            inner.wait_queue.push_back(Arc::clone(&task));
             drop(inner);
-            block_current_and_run_next();
+            block_current_until(expire_ms);
+            // up() took us off the queue, unless the timer fired or we were
+            // interrupted first
+            let mut inner = self.inner.exclusive_access();
+            if let Some(idx) = inner.wait_queue.iter().position(|t| Arc::ptr_eq(t, &task)) {
+                inner.wait_queue.remove(idx);
+                inner.count += 1;
+                if expire_ms.is_none() {
+                    unblock(current_thread());
+                }
+                return Err(if current_interrupted() {
+                    EINTR
+                } else {
+                    ETIMEDOUT
+                });
+            }
+        } else {
+            inner.holders.push(current_thread());
+            set_holders(self.object(), inner.holders.clone());
         }
+        Ok(())
+    }
+
+    /// Whether threads are waiting, so that it cannot be destroyed
+    pub fn is_busy(&self) -> bool {
+        !self.inner.exclusive_access().wait_queue.is_empty()
+    }
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        remove_object(self.object());
     }
 }
@@ -0,0 +1,73 @@
+//! A lock for kernel code which may sleep while holding it
+
+use super::UPSafeCell;
+use crate::task::{add_task, block_current_and_run_next, current_task, TaskControlBlock};
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+
+/// Puts the threads waiting for it to sleep, unlike the spin locks, which
+/// would never be released by a holder sleeping in turn
+pub struct SleepLock {
+    inner: UPSafeCell<SleepLockInner>,
+}
+
+struct SleepLockInner {
+    locked: bool,
+    waiters: VecDeque<Arc<TaskControlBlock>>,
+}
+
+/// Releases the [`SleepLock`] when dropped
+pub struct SleepLockGuard<'a> {
+    lock: &'a SleepLock,
+    /// Id of the hold in the lock-order validator
+    #[cfg(debug_assertions)]
+    id: usize,
+}
+
+impl SleepLock {
+    pub fn new() -> Self {
+        Self {
+            inner: unsafe {
+                UPSafeCell::new(SleepLockInner {
+                    locked: false,
+                    waiters: VecDeque::new(),
+                })
+            },
+        }
+    }
+
+    #[track_caller]
+    pub fn lock(&self) -> SleepLockGuard<'_> {
+        loop {
+            let mut inner = self.inner.exclusive_access();
+            if !inner.locked {
+                inner.locked = true;
+                drop(inner);
+                return SleepLockGuard {
+                    lock: self,
+                    #[cfg(debug_assertions)]
+                    id: super::lockdep::acquire_sleeping(
+                        core::any::type_name::<Self>(),
+                        core::panic::Location::caller(),
+                    ),
+                };
+            }
+            inner.waiters.push_back(current_task().unwrap());
+            drop(inner);
+            block_current_and_run_next();
+        }
+    }
+}
+
+impl Drop for SleepLockGuard<'_> {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        super::lockdep::release(self.id);
+        let mut inner = self.lock.inner.exclusive_access();
+        inner.locked = false;
+        // they all try again, so that a waiter killed meanwhile is no matter
+        for task in inner.waiters.drain(..) {
+            add_task(task);
+        }
+    }
+}
@@ -1,6 +1,8 @@
 //! Uniprocessor interior mutability primitives
 
 use core::cell::{RefCell, RefMut};
+#[cfg(debug_assertions)]
+use core::ops::{Deref, DerefMut};
 
 /// Wrap a static data structure inside it so that we are
 /// able to access it without any `unsafe`.
@@ -9,6 +11,9 @@ use core::cell::{RefCell, RefMut};
 ///
 /// In order to get mutable reference of inner data, call
 /// `exclusive_access`.
+///
+/// In debug builds, borrows are checked by the lock-order validator in
+/// [`lockdep`](super::lockdep).
 pub struct UPSafeCell<T> {
     /// inner data
     inner: RefCell<T>,
@@ -16,6 +21,18 @@ pub struct UPSafeCell<T> {
 
 unsafe impl<T> Sync for UPSafeCell<T> {}
 
+/// Mutable borrow of the data of an [`UPSafeCell`]
+#[cfg(not(debug_assertions))]
+pub type UPRefMut<'a, T> = RefMut<'a, T>;
+
+/// Mutable borrow of the data of an [`UPSafeCell`], telling the lock-order
+/// validator when it ends
+#[cfg(debug_assertions)]
+pub struct UPRefMut<'a, T> {
+    inner: RefMut<'a, T>,
+    id: usize,
+}
+
 impl<T> UPSafeCell<T> {
     /// User is responsible to guarantee that inner struct is only used in
     /// uniprocessor.
@@ -25,7 +42,39 @@ impl<T> UPSafeCell<T> {
         }
     }
     /// Panic if the data has been borrowed.
-    pub fn exclusive_access(&self) -> RefMut<'_, T> {
+    #[cfg(not(debug_assertions))]
+    pub fn exclusive_access(&self) -> UPRefMut<'_, T> {
         self.inner.borrow_mut()
     }
+    /// Panic if the data has been borrowed.
+    #[cfg(debug_assertions)]
+    #[track_caller]
+    pub fn exclusive_access(&self) -> UPRefMut<'_, T> {
+        let inner = self.inner.borrow_mut();
+        let id =
+            super::lockdep::acquire(core::any::type_name::<T>(), core::panic::Location::caller());
+        UPRefMut { inner, id }
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<T> Deref for UPRefMut<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.inner
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<T> DerefMut for UPRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+}
+
+#[cfg(debug_assertions)]
+impl<T> Drop for UPRefMut<'_, T> {
+    fn drop(&mut self) {
+        super::lockdep::release(self.id);
+    }
 }
@@ -0,0 +1,207 @@
+//! Kernel-wide wait-for graph between the threads of all processes
+//!
+//! Blocking primitives register a thread about to sleep as waiting on an
+//! object, identified by its address, and keep up to date the threads that
+//! can release each object: the owner of a mutex, the holders of semaphore
+//! units, the processes on the other end of a pipe, the processes mapping a
+//! futex word or the children a parent waits for. A waiter goes on as soon as
+//! any one of those makes progress, so it is deadlocked once every thread
+//! reachable from it is blocked as well. Waits on a condvar, a mailbox or a
+//! terminal are not recorded, so cycles through them go unnoticed.
+
+use super::UPSafeCell;
+use crate::syscall::errno::EDEADLK;
+use crate::task::{current_task, pid2process, SignalFlags, TaskControlBlock};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec;
+use alloc::vec::Vec;
+use lazy_static::*;
+
+/// A thread, as (pid, tid)
+pub type ThreadKey = (usize, usize);
+
+/// What to do when a thread would block forever
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DeadlockPolicy {
+    /// Let it block
+    Ignore,
+    /// Fail the blocking call with `EDEADLK`
+    ReturnError,
+    /// Fail the call and kill the process of the thread closing the cycle
+    KillVictim,
+}
+
+impl DeadlockPolicy {
+    pub fn from_usize(policy: usize) -> Option<Self> {
+        match policy {
+            0 => Some(Self::Ignore),
+            1 => Some(Self::ReturnError),
+            2 => Some(Self::KillVictim),
+            _ => None,
+        }
+    }
+}
+
+struct WaitGraph {
+    /// Policy of each process that chose one other than `Ignore`
+    policies: BTreeMap<usize, DeadlockPolicy>,
+    /// Object each blocked thread waits on
+    waiting: BTreeMap<ThreadKey, usize>,
+    /// Threads able to release each object
+    holders: BTreeMap<usize, Vec<ThreadKey>>,
+}
+
+impl WaitGraph {
+    /// Threads reachable from `start` if none of them can make progress
+    ///
+    /// An object without known holders might be released by anyone, so
+    /// threads waiting on it are never reported.
+    fn knot(&self, start: ThreadKey) -> Option<Vec<ThreadKey>> {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![start];
+        while let Some(thread) = stack.pop() {
+            if !visited.insert(thread) {
+                continue;
+            }
+            let object = self.waiting.get(&thread)?;
+            let holders = self.holders.get(object)?;
+            if holders.is_empty() {
+                return None;
+            }
+            stack.extend(holders.iter().copied());
+        }
+        Some(visited.into_iter().collect())
+    }
+
+    fn policy(&self, pid: usize) -> DeadlockPolicy {
+        self.policies
+            .get(&pid)
+            .copied()
+            .unwrap_or(DeadlockPolicy::Ignore)
+    }
+
+    fn forget(&mut self, gone: impl Fn(&ThreadKey) -> bool) {
+        self.waiting.retain(|thread, _| !gone(thread));
+        for holders in self.holders.values_mut() {
+            holders.retain(|thread| !gone(thread));
+        }
+    }
+}
+
+lazy_static! {
+    static ref WAIT_GRAPH: UPSafeCell<WaitGraph> = unsafe {
+        UPSafeCell::new(WaitGraph {
+            policies: BTreeMap::new(),
+            waiting: BTreeMap::new(),
+            holders: BTreeMap::new(),
+        })
+    };
+}
+
+/// Set the policy for the blocking calls of process `pid`
+pub fn set_deadlock_policy(pid: usize, policy: DeadlockPolicy) {
+    let mut graph = WAIT_GRAPH.exclusive_access();
+    if policy == DeadlockPolicy::Ignore {
+        graph.policies.remove(&pid);
+    } else {
+        graph.policies.insert(pid, policy);
+    }
+}
+
+/// Give the forked process `child` the policy of `parent`
+pub fn inherit_deadlock_policy(parent: usize, child: usize) {
+    let policy = WAIT_GRAPH.exclusive_access().policy(parent);
+    set_deadlock_policy(child, policy);
+}
+
+/// Whether blocking calls are checked at all
+///
+/// Lets callers skip computing holders that are costly to find. A cycle
+/// may go through any process, so holders are kept for all of them as soon
+/// as one process checks its calls.
+pub fn deadlock_detection_enabled() -> bool {
+    !WAIT_GRAPH.exclusive_access().policies.is_empty()
+}
+
+/// Key of `task`, `None` once it has exited
+pub fn task_key(task: &TaskControlBlock) -> Option<ThreadKey> {
+    let pid = task.process.upgrade()?.getpid();
+    let tid = task.inner_exclusive_access().res.as_ref()?.tid;
+    Some((pid, tid))
+}
+
+/// Key of the calling thread
+pub fn current_thread() -> ThreadKey {
+    task_key(&current_task().unwrap()).unwrap()
+}
+
+/// Replace the threads able to release `object`
+pub fn set_holders(object: usize, holders: Vec<ThreadKey>) {
+    WAIT_GRAPH
+        .exclusive_access()
+        .holders
+        .insert(object, holders);
+}
+
+/// Forget `object`, which is being destroyed
+pub fn remove_object(object: usize) {
+    WAIT_GRAPH.exclusive_access().holders.remove(&object);
+}
+
+/// Record that the calling thread is about to block on `object`
+///
+/// Fails with `EDEADLK` if it could never be woken up and the policy says
+/// so, in which case it is not recorded as waiting.
+pub fn block_on(object: usize) -> Result<(), isize> {
+    let thread = current_thread();
+    let mut graph = WAIT_GRAPH.exclusive_access();
+    graph.waiting.insert(thread, object);
+    let policy = graph.policy(thread.0);
+    if policy == DeadlockPolicy::Ignore {
+        return Ok(());
+    }
+    let knot = match graph.knot(thread) {
+        Some(knot) => knot,
+        None => return Ok(()),
+    };
+    graph.waiting.remove(&thread);
+    warn!("deadlock detected, threads (pid, tid) blocked for ever:");
+    for waiter in knot.iter() {
+        let object = graph.waiting.get(waiter).copied().unwrap_or(object);
+        warn!(
+            "  {:?} waits on {:#x} held by {:?}",
+            waiter, object, graph.holders[&object]
+        );
+    }
+    drop(graph);
+    if policy == DeadlockPolicy::KillVictim {
+        warn!("killing process {} to break the deadlock", thread.0);
+        if let Some(process) = pid2process(thread.0) {
+            process
+                .inner_exclusive_access()
+                .send_signal(SignalFlags::SIGKILL);
+        }
+    }
+    Err(EDEADLK)
+}
+
+/// `thread` is no longer blocked
+pub fn unblock(thread: ThreadKey) {
+    WAIT_GRAPH.exclusive_access().waiting.remove(&thread);
+}
+
+/// Forget `thread`, which exited
+pub fn forget_thread(thread: ThreadKey) {
+    WAIT_GRAPH.exclusive_access().forget(|&key| key == thread);
+}
+
+/// Forget all threads of process `pid`, which exited or replaced its image
+///
+/// Its policy survives `exec`, so it goes only with `exited`.
+pub fn forget_process(pid: usize, exited: bool) {
+    let mut graph = WAIT_GRAPH.exclusive_access();
+    graph.forget(|&(thread_pid, _)| thread_pid == pid);
+    if exited {
+        graph.policies.remove(&pid);
+    }
+}
@@ -0,0 +1,47 @@
+//! Error numbers returned by syscalls, as negative values
+
+/// Operation not permitted
+pub const EPERM: isize = -1;
+/// No such file, or no such object with the given key
+pub const ENOENT: isize = -2;
+/// No such process
+pub const ESRCH: isize = -3;
+/// A wait was interrupted, as the thread has to exit
+pub const EINTR: isize = -4;
+/// No reader on the other end of a named pipe opened without blocking
+pub const ENXIO: isize = -6;
+/// Bad file descriptor
+pub const EBADF: isize = -9;
+/// The call would have to block, or the value changed meanwhile
+pub const EAGAIN: isize = -11;
+/// Out of memory
+pub const ENOMEM: isize = -12;
+/// Bad address
+pub const EFAULT: isize = -14;
+/// The resource is in use
+pub const EBUSY: isize = -16;
+/// The object exists already
+pub const EEXIST: isize = -17;
+/// Linking across filesystems
+pub const EXDEV: isize = -18;
+/// No filesystem of the given type
+pub const ENODEV: isize = -19;
+/// A path component is not a directory
+pub const ENOTDIR: isize = -20;
+/// The path is a directory, which the call does not take
+pub const EISDIR: isize = -21;
+/// Invalid argument
+pub const EINVAL: isize = -22;
+/// Too many open files
+pub const EMFILE: isize = -24;
+/// No space left in the filesystem
+pub const ENOSPC: isize = -28;
+/// Writing to a pipe nobody can read any more
+pub const EPIPE: isize = -32;
+/// The deadline passed before the call could complete
+pub const ETIMEDOUT: isize = -110;
+
+/// Granting the request would deadlock
+///
+/// Kept at the value the deadlock detection tests have always checked for.
+pub const EDEADLK: isize = -0xdead;
@@ -1,16 +1,55 @@
 //! File and filesystem-related syscalls
 
+use super::errno::{EBADF, EEXIST, EINTR, EINVAL, EMFILE};
+use crate::config::MAX_FD;
 use crate::fs::make_pipe;
 use crate::fs::open_file;
 use crate::fs::OpenFlags;
 use crate::fs::Stat;
+use crate::fs::{fifo_ino, link, mkfifo, mount, open_device, open_fifo, umount, unlink};
+use crate::fs::{File, PollEvents, PollWaker};
 use crate::mm::translated_byte_buffer;
+use crate::mm::translated_ref;
 use crate::mm::translated_refmut;
 use crate::mm::translated_str;
 use crate::mm::UserBuffer;
+use crate::task::current_interrupted;
 use crate::task::current_process;
 use crate::task::current_user_token;
+use crate::timer::{block_current_until, get_time_ms};
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Duplicate a file to the lowest free descriptor not below the argument
+const F_DUPFD: usize = 0;
+/// Get the descriptor flags
+const F_GETFD: usize = 1;
+/// Set the descriptor flags
+const F_SETFD: usize = 2;
+/// Get the status flags of a file
+const F_GETFL: usize = 3;
+/// Set the status flags of a file, only `O_NONBLOCK` can change
+const F_SETFL: usize = 4;
+/// Like `F_DUPFD`, with `FD_CLOEXEC` set on the new descriptor
+const F_DUPFD_CLOEXEC: usize = 1030;
+/// The descriptor is closed by exec
+const FD_CLOEXEC: usize = 1;
+
+/// `struct pollfd` of `ppoll`
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+/// `struct timespec` of `ppoll`
+#[repr(C)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
 
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let token = current_user_token();
@@ -46,17 +85,178 @@ pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
     }
 }
 
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    if fd >= inner.fd_table.len() {
+        return -1;
+    }
+    if let Some(file) = &inner.fd_table[fd] {
+        let file = file.clone();
+        drop(inner);
+        file.ioctl(request, arg)
+    } else {
+        -1
+    }
+}
+
+/// Manipulate descriptor `fd` or the file it refers to
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return EBADF,
+    };
+    match cmd {
+        F_DUPFD | F_DUPFD_CLOEXEC => {
+            if arg >= MAX_FD {
+                return EINVAL;
+            }
+            let new_fd = match inner.alloc_fd_from(arg) {
+                Some(new_fd) => new_fd,
+                None => return EMFILE,
+            };
+            inner.fd_table[new_fd] = Some(file);
+            if cmd == F_DUPFD_CLOEXEC {
+                inner.cloexec.insert(new_fd);
+            }
+            new_fd as isize
+        }
+        F_GETFD => {
+            if inner.cloexec.contains(&fd) {
+                FD_CLOEXEC as isize
+            } else {
+                0
+            }
+        }
+        F_SETFD => {
+            if arg & FD_CLOEXEC != 0 {
+                inner.cloexec.insert(fd);
+            } else {
+                inner.cloexec.remove(&fd);
+            }
+            0
+        }
+        F_GETFL => {
+            let mut flags = match (file.readable(), file.writable()) {
+                (true, true) => OpenFlags::RDWR,
+                (false, true) => OpenFlags::WRONLY,
+                _ => OpenFlags::RDONLY,
+            };
+            if file.nonblocking() {
+                flags |= OpenFlags::NONBLOCK;
+            }
+            flags.bits() as isize
+        }
+        F_SETFL => {
+            let flags = OpenFlags::from_bits_truncate(arg as u32);
+            file.set_nonblocking(flags.contains(OpenFlags::NONBLOCK));
+            0
+        }
+        _ => {
+            drop(inner);
+            file.fcntl(cmd, arg)
+        }
+    }
+}
+
 pub fn sys_open(path: *const u8, flags: u32) -> isize {
     let process = current_process();
     let token = current_user_token();
     let path = translated_str(token, path);
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut inner = process.inner_exclusive_access();
-        let fd = inner.alloc_fd();
-        inner.fd_table[fd] = Some(inode);
-        fd as isize
+    let flags = OpenFlags::from_bits(flags).unwrap();
+    let nonblock = flags.contains(OpenFlags::NONBLOCK);
+    let file: Arc<dyn File + Send + Sync> = if let Some(ino) = fifo_ino(path.as_str()) {
+        let (readable, writable) = flags.read_write();
+        match open_fifo(ino, readable, writable, nonblock) {
+            Ok(fifo) => fifo,
+            Err(err) => return err,
+        }
+    } else if let Some(device) = open_device(path.as_str(), flags) {
+        device.set_nonblocking(nonblock);
+        device
+    } else if let Some(inode) = open_file(path.as_str(), flags) {
+        inode.set_nonblocking(nonblock);
+        inode
     } else {
-        -1
+        return -1;
+    };
+    let mut inner = process.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return EMFILE,
+    };
+    inner.fd_table[fd] = Some(file);
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.cloexec.insert(fd);
+    }
+    fd as isize
+}
+
+/// File type bits of a mode
+const S_IFMT: u32 = 0o170000;
+/// The mode of a named pipe
+const S_IFIFO: u32 = 0o010000;
+
+/// Create a named pipe at `path`, which `mode` has to ask for
+///
+/// Named pipes are the only nodes `mknodat` can make so far, permissions are
+/// ignored like everywhere else.
+pub fn sys_mkfifo(path: *const u8, mode: u32) -> isize {
+    if mode & S_IFMT != S_IFIFO {
+        return EINVAL;
+    }
+    let path = translated_str(current_user_token(), path);
+    if mkfifo(path.as_str()) {
+        0
+    } else {
+        EEXIST
+    }
+}
+
+/// Mount a filesystem of type `fstype` made from `source` at `target`
+///
+/// `source` may be null for filesystems which do not need one, and so may
+/// `data`, the comma-separated options of the filesystem. `flags` are
+/// ignored.
+pub fn sys_mount(
+    source: *const u8,
+    target: *const u8,
+    fstype: *const u8,
+    _flags: usize,
+    data: *const u8,
+) -> isize {
+    let token = current_user_token();
+    let source = if source.is_null() {
+        String::new()
+    } else {
+        translated_str(token, source)
+    };
+    let target = translated_str(token, target);
+    let fstype = translated_str(token, fstype);
+    let data = if data.is_null() {
+        String::new()
+    } else {
+        translated_str(token, data)
+    };
+    match mount(
+        source.as_str(),
+        target.as_str(),
+        fstype.as_str(),
+        data.as_str(),
+    ) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+/// Unmount the filesystem at `target`, `flags` are ignored
+pub fn sys_umount2(target: *const u8, _flags: usize) -> isize {
+    let target = translated_str(current_user_token(), target);
+    match umount(target.as_str()) {
+        Ok(()) => 0,
+        Err(err) => err,
     }
 }
 
@@ -70,6 +270,7 @@ pub fn sys_close(fd: usize) -> isize {
         return -1;
     }
     inner.fd_table[fd].take();
+    inner.cloexec.remove(&fd);
     0
 }
 
@@ -78,9 +279,18 @@ pub fn sys_pipe(pipe: *mut usize) -> isize {
     let token = current_user_token();
     let mut inner = process.inner_exclusive_access();
     let (pipe_read, pipe_write) = make_pipe();
-    let read_fd = inner.alloc_fd();
+    let read_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return EMFILE,
+    };
     inner.fd_table[read_fd] = Some(pipe_read);
-    let write_fd = inner.alloc_fd();
+    let write_fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => {
+            inner.fd_table[read_fd] = None;
+            return EMFILE;
+        }
+    };
     inner.fd_table[write_fd] = Some(pipe_write);
     *translated_refmut(token, pipe) = read_fd;
     *translated_refmut(token, unsafe { pipe.add(1) }) = write_fd;
@@ -96,19 +306,138 @@ pub fn sys_dup(fd: usize) -> isize {
     if inner.fd_table[fd].is_none() {
         return -1;
     }
-    let new_fd = inner.alloc_fd();
+    let new_fd = match inner.alloc_fd() {
+        Some(new_fd) => new_fd,
+        None => return EMFILE,
+    };
     inner.fd_table[new_fd] = Some(Arc::clone(inner.fd_table[fd].as_ref().unwrap()));
     new_fd as isize
 }
 
-pub fn sys_fstat(_fd: usize, _st: *mut Stat) -> isize {
-    -1
+/// Make `new_fd` refer to the file of `old_fd`, closing what it referred to
+///
+/// `flags` may only hold `O_CLOEXEC`, which sets `FD_CLOEXEC` on `new_fd`.
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    let flags = match OpenFlags::from_bits(flags) {
+        Some(flags) if (flags - OpenFlags::CLOEXEC).is_empty() => flags,
+        _ => return EINVAL,
+    };
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(old_fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return EBADF,
+    };
+    if new_fd >= MAX_FD {
+        return EBADF;
+    }
+    if new_fd == old_fd {
+        return EINVAL;
+    }
+    if new_fd >= inner.fd_table.len() {
+        inner.fd_table.resize(new_fd + 1, None);
+    }
+    inner.fd_table[new_fd] = Some(file);
+    if flags.contains(OpenFlags::CLOEXEC) {
+        inner.cloexec.insert(new_fd);
+    } else {
+        inner.cloexec.remove(&new_fd);
+    }
+    new_fd as isize
+}
+
+pub fn sys_fstat(fd: usize, st: *mut Stat) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let file = match inner.fd_table.get(fd) {
+        Some(Some(file)) => file.clone(),
+        _ => return EBADF,
+    };
+    drop(inner);
+    match file.stat() {
+        Some(stat) => {
+            *translated_refmut(token, st) = stat;
+            0
+        }
+        None => EINVAL,
+    }
+}
+
+/// Give the file at `old_name` the name `new_name` as well, both relative
+/// to the root since there is no working directory
+pub fn sys_linkat(old_name: *const u8, new_name: *const u8) -> isize {
+    let token = current_user_token();
+    let old_name = translated_str(token, old_name);
+    let new_name = translated_str(token, new_name);
+    match link(old_name.as_str(), new_name.as_str()) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
 }
 
-pub fn sys_linkat(_old_name: *const u8, _new_name: *const u8) -> isize {
-    -1
+pub fn sys_unlinkat(name: *const u8) -> isize {
+    let name = translated_str(current_user_token(), name);
+    match unlink(name.as_str()) {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
 }
 
-pub fn sys_unlinkat(_name: *const u8) -> isize {
-    -1
+/// Wait until one of the `nfds` files of `fds` is ready, or for `timeout`
+///
+/// A null `timeout` waits forever and a zero one only checks. The signal
+/// mask is not supported. Returns how many files have events to report,
+/// 0 once the time is up.
+pub fn sys_ppoll(
+    fds: *mut PollFd,
+    nfds: usize,
+    timeout: *const TimeSpec,
+    _sigmask: usize,
+) -> isize {
+    let token = current_user_token();
+    let expire_ms = (!timeout.is_null()).then(|| {
+        let timeout = translated_ref(token, timeout);
+        get_time_ms() + timeout.sec * 1000 + timeout.nsec / 1_000_000
+    });
+    let process = current_process();
+    let inner = process.inner_exclusive_access();
+    let files: Vec<Option<Arc<dyn File + Send + Sync>>> = (0..nfds)
+        .map(|i| {
+            let fd = translated_ref(token, unsafe { fds.add(i) }).fd;
+            if fd < 0 {
+                return None;
+            }
+            inner.fd_table.get(fd as usize).cloned().flatten()
+        })
+        .collect();
+    drop(inner);
+    loop {
+        let mut ready = 0;
+        for (i, file) in files.iter().enumerate() {
+            let pollfd = translated_refmut(token, unsafe { fds.add(i) });
+            let events = PollEvents::from_bits_truncate(pollfd.events as u16);
+            let revents = match file {
+                Some(file) => file.poll(events),
+                None if pollfd.fd < 0 => PollEvents::empty(),
+                None => PollEvents::NVAL,
+            };
+            pollfd.revents = revents.bits() as i16;
+            if !revents.is_empty() {
+                ready += 1;
+            }
+        }
+        if ready > 0 || expire_ms.map_or(false, |expire_ms| get_time_ms() >= expire_ms) {
+            return ready;
+        }
+        if current_interrupted() {
+            return EINTR;
+        }
+        let waker = PollWaker::new();
+        for file in files.iter().flatten() {
+            file.register_poller(&waker);
+        }
+        block_current_until(expire_ms);
+        waker.finish();
+    }
 }
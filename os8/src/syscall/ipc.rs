@@ -0,0 +1,189 @@
+//! Message passing between processes through their mailboxes, and shared
+//! memory segments
+
+use super::errno::{EINVAL, EMFILE, ESRCH};
+use crate::config::{PAGE_SIZE, SHM_BASE, USER_SPACE_END};
+use crate::fs::MailboxFile;
+use crate::mm::{
+    shm_frames, shm_get, shm_remove, translated_byte_buffer, MapPermission, VirtAddr, VirtPageNum,
+};
+use crate::sync::{Mailbox, MAIL_MAX_LEN};
+use crate::task::{current_process, current_user_token, pid2process};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+/// Fail with `EAGAIN` instead of waiting
+const MAIL_NONBLOCK: usize = 1;
+
+fn current_mailbox() -> Arc<Mailbox> {
+    Arc::clone(&current_process().inner_exclusive_access().mailbox)
+}
+
+/// Receive the oldest message of the calling process into `buf`
+///
+/// Returns the length copied, the rest of a longer message being lost. With
+/// `len` 0 it only waits until a message arrives and returns 0, which also
+/// tells with `MAIL_NONBLOCK` whether one is waiting.
+pub fn sys_mail_read(buf: *mut u8, len: usize, flags: usize) -> isize {
+    if flags & !MAIL_NONBLOCK != 0 {
+        return EINVAL;
+    }
+    let nonblock = flags & MAIL_NONBLOCK != 0;
+    let mailbox = current_mailbox();
+    if len == 0 {
+        return match mailbox.wait_readable(nonblock) {
+            Ok(()) => 0,
+            Err(err) => err,
+        };
+    }
+    let message = match mailbox.recv(nonblock) {
+        Ok(message) => message,
+        Err(err) => return err,
+    };
+    let len = len.min(message.len());
+    let mut copied = 0;
+    for slice in translated_byte_buffer(current_user_token(), buf, len) {
+        slice.copy_from_slice(&message[copied..copied + slice.len()]);
+        copied += slice.len();
+    }
+    len as isize
+}
+
+/// Open the mailbox of the calling process as a file descriptor
+///
+/// Reading it takes a message like [`sys_mail_read`], and `ppoll` reports
+/// it readable while a message is waiting. `MAIL_NONBLOCK` in `flags` makes
+/// reads fail with `EAGAIN` instead of waiting.
+pub fn sys_mailfd(flags: usize) -> isize {
+    if flags & !MAIL_NONBLOCK != 0 {
+        return EINVAL;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let fd = match inner.alloc_fd() {
+        Some(fd) => fd,
+        None => return EMFILE,
+    };
+    let file = MailboxFile::new(Arc::clone(&inner.mailbox), flags & MAIL_NONBLOCK != 0);
+    inner.fd_table[fd] = Some(Arc::new(file));
+    fd as isize
+}
+
+/// Send `len` bytes at `buf` to the mailbox of process `pid`
+///
+/// Messages are truncated to [`MAIL_MAX_LEN`] bytes and the length sent is
+/// returned. With `len` 0 it only waits for a free slot and returns 0.
+pub fn sys_mail_write(pid: usize, buf: *const u8, len: usize, flags: usize) -> isize {
+    if flags & !MAIL_NONBLOCK != 0 {
+        return EINVAL;
+    }
+    let nonblock = flags & MAIL_NONBLOCK != 0;
+    let mailbox = match pid2process(pid) {
+        Some(process) => Arc::clone(&process.inner_exclusive_access().mailbox),
+        None => return ESRCH,
+    };
+    if len == 0 {
+        return match mailbox.wait_writable(nonblock) {
+            Ok(()) => 0,
+            Err(err) => err,
+        };
+    }
+    let len = len.min(MAIL_MAX_LEN);
+    let mut message = Vec::with_capacity(len);
+    for slice in translated_byte_buffer(current_user_token(), buf, len) {
+        message.extend_from_slice(slice);
+    }
+    match mailbox.send(message, nonblock) {
+        Ok(()) => len as isize,
+        Err(err) => err,
+    }
+}
+
+/// Create the segment if there is none with the key
+const IPC_CREAT: usize = 0o1000;
+/// Fail if the segment exists already
+const IPC_EXCL: usize = 0o2000;
+/// Remove the segment once nobody has it attached
+const IPC_RMID: usize = 0;
+/// Attach the segment read-only
+const SHM_RDONLY: usize = 0o10000;
+
+/// Id of the shared memory segment of `key`, created with `size` bytes
+/// if missing and `IPC_CREAT` is given
+///
+/// `IPC_PRIVATE` always creates a new segment, only found through its id.
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    if flags & !(IPC_CREAT | IPC_EXCL) != 0 {
+        return EINVAL;
+    }
+    match shm_get(key, size, flags & IPC_CREAT != 0, flags & IPC_EXCL != 0) {
+        Ok(id) => id as isize,
+        Err(err) => err,
+    }
+}
+
+/// Control segment `id`, only removing it with `IPC_RMID` is supported
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    if cmd != IPC_RMID {
+        return EINVAL;
+    }
+    if shm_remove(id) {
+        0
+    } else {
+        EINVAL
+    }
+}
+
+/// Map segment `id` at `addr`, or wherever there is room if it is 0
+///
+/// Returns the address it was attached at.
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    if flags & !SHM_RDONLY != 0 || addr % PAGE_SIZE != 0 {
+        return EINVAL;
+    }
+    let frames = match shm_frames(id) {
+        Some(frames) => frames,
+        None => return EINVAL,
+    };
+    let mut permission = MapPermission::U | MapPermission::R;
+    if flags & SHM_RDONLY == 0 {
+        permission |= MapPermission::W;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let memory_set = &mut inner.memory_set;
+    let start_vpn = if addr == 0 {
+        memory_set.find_free_area(VirtAddr::from(SHM_BASE).into(), frames.len())
+    } else {
+        let start_vpn: VirtPageNum = VirtAddr::from(addr).into();
+        if !memory_set.is_free(start_vpn, VirtPageNum(start_vpn.0 + frames.len())) {
+            return EINVAL;
+        }
+        start_vpn
+    };
+    // the whole segment must be in the user half of the address space, away
+    // from the trampoline and the trap contexts, which the page table would
+    // otherwise find in the way
+    if start_vpn.0 + frames.len() > USER_SPACE_END / PAGE_SIZE {
+        return EINVAL;
+    }
+    memory_set.insert_shared_area(start_vpn, &frames, permission);
+    VirtAddr::from(start_vpn).0 as isize
+}
+
+/// Unmap the segment attached at `addr`
+pub fn sys_shmdt(addr: usize) -> isize {
+    if addr % PAGE_SIZE != 0 {
+        return EINVAL;
+    }
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if inner
+        .memory_set
+        .remove_shared_area(VirtAddr::from(addr).into())
+    {
+        0
+    } else {
+        EINVAL
+    }
+}
@@ -11,24 +11,47 @@
 //! submodules, and you should also implement syscalls this way.
 
 const SYSCALL_DUP: usize = 24;
+const SYSCALL_DUP3: usize = 403;
+const SYSCALL_FCNTL: usize = 25;
+const SYSCALL_IOCTL: usize = 29;
+const SYSCALL_MKNODAT: usize = 33;
 const SYSCALL_UNLINKAT: usize = 35;
 const SYSCALL_LINKAT: usize = 37;
+const SYSCALL_UMOUNT2: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
 const SYSCALL_OPEN: usize = 56;
 const SYSCALL_CLOSE: usize = 57;
 const SYSCALL_PIPE: usize = 59;
 const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_PPOLL: usize = 73;
 const SYSCALL_FSTAT: usize = 80;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_FUTEX: usize = 98;
 const SYSCALL_SLEEP: usize = 101;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SETPGID: usize = 154;
+const SYSCALL_GETPGID: usize = 155;
+const SYSCALL_SETSID: usize = 157;
 const SYSCALL_GET_TIME: usize = 169;
 const SYSCALL_GETPID: usize = 172;
 const SYSCALL_GETTID: usize = 178;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
 const SYSCALL_FORK: usize = 220;
 const SYSCALL_EXEC: usize = 221;
 const SYSCALL_WAITPID: usize = 260;
 const SYSCALL_SPAWN: usize = 400;
+const SYSCALL_MAIL_READ: usize = 401;
+const SYSCALL_MAIL_WRITE: usize = 402;
+const SYSCALL_MAILFD: usize = 404;
 const SYSCALL_MUNMAP: usize = 215;
 const SYSCALL_MMAP: usize = 222;
 const SYSCALL_SET_PRIORITY: usize = 140;
@@ -37,6 +60,7 @@ const SYSCALL_THREAD_CREATE: usize = 460;
 const SYSCALL_WAITTID: usize = 462;
 const SYSCALL_MUTEX_CREATE: usize = 463;
 const SYSCALL_MUTEX_LOCK: usize = 464;
+const SYSCALL_MUTEX_TRYLOCK: usize = 465;
 const SYSCALL_MUTEX_UNLOCK: usize = 466;
 const SYSCALL_SEMAPHORE_CREATE: usize = 467;
 const SYSCALL_SEMAPHORE_UP: usize = 468;
@@ -45,56 +69,129 @@ const SYSCALL_SEMAPHORE_DOWN: usize = 470;
 const SYSCALL_CONDVAR_CREATE: usize = 471;
 const SYSCALL_CONDVAR_SIGNAL: usize = 472;
 const SYSCALL_CONDVAR_WAIT: usize = 473;
+const SYSCALL_THREAD_DETACH: usize = 474;
+const SYSCALL_THREAD_CANCEL: usize = 475;
+const SYSCALL_SET_DEADLOCK_POLICY: usize = 476;
+const SYSCALL_RWLOCK_CREATE: usize = 477;
+const SYSCALL_RWLOCK_READ: usize = 478;
+const SYSCALL_RWLOCK_WRITE: usize = 479;
+const SYSCALL_RWLOCK_UNLOCK: usize = 480;
+const SYSCALL_BARRIER_CREATE: usize = 481;
+const SYSCALL_BARRIER_WAIT: usize = 482;
+const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 483;
+const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 484;
+const SYSCALL_CONDVAR_BROADCAST: usize = 485;
+const SYSCALL_MUTEX_DESTROY: usize = 486;
+const SYSCALL_SEMAPHORE_DESTROY: usize = 487;
+const SYSCALL_CONDVAR_DESTROY: usize = 488;
 
+pub mod errno;
 mod fs;
+mod ipc;
 pub mod process;
 mod sync;
 mod thread;
 
 use crate::fs::Stat;
+use crate::task::SignalAction;
 use fs::*;
+use ipc::*;
 use process::*;
 use sync::*;
 use thread::*;
 
 /// handle syscall exception with `syscall_id` and other arguments
-pub fn syscall(syscall_id: usize, args: [usize; 4]) -> isize {
+pub fn syscall(syscall_id: usize, args: [usize; 6]) -> isize {
     match syscall_id {
         SYSCALL_DUP => sys_dup(args[0]),
+        SYSCALL_DUP3 => sys_dup3(args[0], args[1], args[2] as u32),
+        SYSCALL_FCNTL => sys_fcntl(args[0], args[1], args[2]),
+        SYSCALL_IOCTL => sys_ioctl(args[0], args[1], args[2]),
+        SYSCALL_MKNODAT => sys_mkfifo(args[1] as *const u8, args[2] as u32),
         SYSCALL_LINKAT => sys_linkat(args[1] as *const u8, args[3] as *const u8),
         SYSCALL_UNLINKAT => sys_unlinkat(args[1] as *const u8),
+        SYSCALL_UMOUNT2 => sys_umount2(args[0] as *const u8, args[1]),
+        SYSCALL_MOUNT => sys_mount(
+            args[0] as *const u8,
+            args[1] as *const u8,
+            args[2] as *const u8,
+            args[3],
+            args[4] as *const u8,
+        ),
         SYSCALL_OPEN => sys_open(args[1] as *const u8, args[2] as u32),
         SYSCALL_CLOSE => sys_close(args[0]),
         SYSCALL_PIPE => sys_pipe(args[0] as *mut usize),
         SYSCALL_READ => sys_read(args[0], args[1] as *const u8, args[2]),
         SYSCALL_WRITE => sys_write(args[0], args[1] as *const u8, args[2]),
+        SYSCALL_PPOLL => sys_ppoll(
+            args[0] as *mut PollFd,
+            args[1],
+            args[2] as *const TimeSpec,
+            args[3],
+        ),
         SYSCALL_FSTAT => sys_fstat(args[0], args[1] as *mut Stat),
         SYSCALL_EXIT => sys_exit(args[0] as i32),
+        SYSCALL_EXIT_GROUP => sys_exit_group(args[0] as i32),
+        SYSCALL_FUTEX => sys_futex(args[0], args[1], args[2], args[3], args[4]),
         SYSCALL_SLEEP => sys_sleep(args[0]),
         SYSCALL_YIELD => sys_yield(),
+        SYSCALL_KILL => sys_kill(args[0] as isize, args[1] as i32),
+        SYSCALL_SIGACTION => sys_sigaction(
+            args[0] as i32,
+            args[1] as *const SignalAction,
+            args[2] as *mut SignalAction,
+        ),
+        SYSCALL_SIGPROCMASK => sys_sigprocmask(args[0] as u32),
+        SYSCALL_SIGRETURN => sys_sigreturn(),
+        SYSCALL_SETPGID => sys_setpgid(args[0], args[1]),
+        SYSCALL_GETPGID => sys_getpgid(args[0]),
+        SYSCALL_SETSID => sys_setsid(),
         SYSCALL_GETPID => sys_getpid(),
         SYSCALL_GETTID => sys_gettid(),
+        SYSCALL_SHMGET => sys_shmget(args[0], args[1], args[2]),
+        SYSCALL_SHMCTL => sys_shmctl(args[0], args[1]),
+        SYSCALL_SHMAT => sys_shmat(args[0], args[1], args[2]),
+        SYSCALL_SHMDT => sys_shmdt(args[0]),
         SYSCALL_FORK => sys_fork(),
         SYSCALL_EXEC => sys_exec(args[0] as *const u8, args[1] as *const usize),
-        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32),
+        SYSCALL_WAITPID => sys_waitpid(args[0] as isize, args[1] as *mut i32, args[2]),
         SYSCALL_GET_TIME => sys_get_time(args[0] as *mut TimeVal, args[1]),
         SYSCALL_MMAP => sys_mmap(args[0], args[1], args[2]),
         SYSCALL_MUNMAP => sys_munmap(args[0], args[1]),
         SYSCALL_SET_PRIORITY => sys_set_priority(args[0] as isize),
         SYSCALL_TASK_INFO => sys_task_info(args[0] as *mut TaskInfo),
         SYSCALL_SPAWN => sys_spawn(args[0] as *const u8),
+        SYSCALL_MAIL_READ => sys_mail_read(args[0] as *mut u8, args[1], args[2]),
+        SYSCALL_MAIL_WRITE => sys_mail_write(args[0], args[1] as *const u8, args[2], args[3]),
+        SYSCALL_MAILFD => sys_mailfd(args[0]),
         SYSCALL_THREAD_CREATE => sys_thread_create(args[0], args[1]),
-        SYSCALL_WAITTID => sys_waittid(args[0]) as isize,
-        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0] == 1),
+        SYSCALL_WAITTID => sys_waittid(args[0], args[1]) as isize,
+        SYSCALL_THREAD_DETACH => sys_thread_detach(args[0]),
+        SYSCALL_THREAD_CANCEL => sys_thread_cancel(args[0]),
+        SYSCALL_MUTEX_CREATE => sys_mutex_create(args[0]),
         SYSCALL_MUTEX_LOCK => sys_mutex_lock(args[0]),
+        SYSCALL_MUTEX_TRYLOCK => sys_mutex_trylock(args[0], args[1]),
         SYSCALL_MUTEX_UNLOCK => sys_mutex_unlock(args[0]),
+        SYSCALL_MUTEX_DESTROY => sys_mutex_destroy(args[0]),
         SYSCALL_SEMAPHORE_CREATE => sys_semaphore_create(args[0]),
         SYSCALL_SEMAPHORE_UP => sys_semaphore_up(args[0]),
+        SYSCALL_SEMAPHORE_DESTROY => sys_semaphore_destroy(args[0]),
         SYSCALL_ENABLE_DEADLOCK_DETECT => sys_enable_deadlock_detect(args[0]),
+        SYSCALL_SET_DEADLOCK_POLICY => sys_set_deadlock_policy(args[0]),
         SYSCALL_SEMAPHORE_DOWN => sys_semaphore_down(args[0]),
+        SYSCALL_SEMAPHORE_DOWN_TIMEOUT => sys_semaphore_down_timeout(args[0], args[1]),
         SYSCALL_CONDVAR_CREATE => sys_condvar_create(args[0]),
         SYSCALL_CONDVAR_SIGNAL => sys_condvar_signal(args[0]),
+        SYSCALL_CONDVAR_BROADCAST => sys_condvar_broadcast(args[0]),
+        SYSCALL_CONDVAR_DESTROY => sys_condvar_destroy(args[0]),
         SYSCALL_CONDVAR_WAIT => sys_condvar_wait(args[0], args[1]),
+        SYSCALL_CONDVAR_WAIT_TIMEOUT => sys_condvar_wait_timeout(args[0], args[1], args[2]),
+        SYSCALL_RWLOCK_CREATE => sys_rwlock_create(),
+        SYSCALL_RWLOCK_READ => sys_rwlock_lock(args[0], false),
+        SYSCALL_RWLOCK_WRITE => sys_rwlock_lock(args[0], true),
+        SYSCALL_RWLOCK_UNLOCK => sys_rwlock_unlock(args[0]),
+        SYSCALL_BARRIER_CREATE => sys_barrier_create(args[0]),
+        SYSCALL_BARRIER_WAIT => sys_barrier_wait(args[0]),
         _ => panic!("Unsupported syscall_id: {}", syscall_id),
     }
 }
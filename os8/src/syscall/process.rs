@@ -3,9 +3,15 @@
 use crate::config::MAX_SYSCALL_NUM;
 use crate::fs::{open_file, OpenFlags};
 use crate::mm::{translated_ref, translated_refmut, translated_str};
+use crate::sync::{
+    block_on, current_thread, deadlock_detection_enabled, refresh_effective_priority,
+    remove_object, set_holders, unblock,
+};
 use crate::task::{
-    current_process, current_task, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next, TaskStatus,
+    block_current_interruptible, current_interrupted, current_process, current_task,
+    current_user_token, exit_current_and_run_next, exit_group_and_run_next, kill_process_group,
+    pid2process, processes_in_group, restore_signal_frame, suspend_current_and_run_next,
+    SignalAction, SignalFlags, TaskStatus, MAX_SIG,
 };
 use crate::timer::get_time_us;
 use alloc::string::String;
@@ -32,6 +38,12 @@ pub fn sys_exit(exit_code: i32) -> ! {
     panic!("Unreachable in sys_exit!");
 }
 
+/// Exit the whole process, whichever thread calls it
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    exit_group_and_run_next(exit_code);
+    panic!("Unreachable in sys_exit_group!");
+}
+
 /// current task gives up resources for other tasks
 pub fn sys_yield() -> isize {
     suspend_current_and_run_next();
@@ -83,39 +95,99 @@ pub fn sys_exec(path: *const u8, mut args: *const usize) -> isize {
     }
 }
 
+/// Do not block in `sys_waitpid`/`sys_waittid`, return -2 instead
+pub const WNOHANG: usize = 1;
+/// Also report children stopped by a signal to `sys_waitpid`
+pub const WUNTRACED: usize = 2;
+
 /// If there is not a child process whose pid is same as given, return -1.
-/// Else if there is a child process but it is still running, return -2.
-pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32) -> isize {
+/// Else if there is a child process but it is still running, block until it
+/// exits, or return -2 at once if `options` contains `WNOHANG`.
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
     let process = current_process();
     // find a child process
-
-    // ---- access current TCB exclusively
-    let mut inner = process.inner_exclusive_access();
-    if !inner
-        .children
-        .iter()
-        .any(|p| pid == -1 || pid as usize == p.getpid())
-    {
-        return -1;
-        // ---- release current PCB
-    }
-    let pair = inner.children.iter().enumerate().find(|(_, p)| {
-        // ++++ temporarily access child PCB lock exclusively
-        p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
-        // ++++ release child PCB
-    });
-    if let Some((idx, _)) = pair {
-        let child = inner.children.remove(idx);
-        // confirm that child will be deallocated after removing from children list
-        assert_eq!(Arc::strong_count(&child), 1);
-        let found_pid = child.getpid();
-        // ++++ temporarily access child TCB exclusively
-        let exit_code = child.inner_exclusive_access().exit_code;
-        // ++++ release child PCB
-        *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
-        found_pid as isize
-    } else {
-        -2
+    loop {
+        // ---- access current TCB exclusively
+        let mut inner = process.inner_exclusive_access();
+        if !inner
+            .children
+            .iter()
+            .any(|p| pid == -1 || pid as usize == p.getpid())
+        {
+            return -1;
+            // ---- release current PCB
+        }
+        let pair = inner.children.iter().enumerate().find(|(_, p)| {
+            // ++++ temporarily access child PCB lock exclusively
+            p.inner_exclusive_access().is_zombie && (pid == -1 || pid as usize == p.getpid())
+            // ++++ release child PCB
+        });
+        if let Some((idx, _)) = pair {
+            let child = inner.children.remove(idx);
+            // confirm that child will be deallocated after removing from children list
+            assert_eq!(Arc::strong_count(&child), 1);
+            let found_pid = child.getpid();
+            // ++++ temporarily access child TCB exclusively
+            let exit_code = child.inner_exclusive_access().exit_code;
+            // ++++ release child PCB
+            if !exit_code_ptr.is_null() {
+                *translated_refmut(inner.memory_set.token(), exit_code_ptr) = exit_code;
+            }
+            return found_pid as isize;
+        }
+        if options & WUNTRACED != 0 {
+            let stopped = inner.children.iter().find_map(|p| {
+                if pid != -1 && pid as usize != p.getpid() {
+                    return None;
+                }
+                let signum = p.inner_exclusive_access().stop_report.take()?;
+                Some((p.getpid(), signum))
+            });
+            if let Some((found_pid, signum)) = stopped {
+                if !exit_code_ptr.is_null() {
+                    *translated_refmut(inner.memory_set.token(), exit_code_ptr) =
+                        ((signum as i32) << 8) | 0x7f;
+                }
+                return found_pid as isize;
+            }
+        }
+        if options & WNOHANG != 0 {
+            return -2;
+        }
+        drop(inner);
+        if current_interrupted() {
+            // let the trap return path finish us
+            return -1;
+        }
+        let inner = process.inner_exclusive_access();
+        // the children able to wake us up, for the wait-for graph, keyed by
+        // the waiting thread as other threads may wait for other children
+        let object = Arc::as_ptr(&current_task().unwrap()) as usize;
+        if deadlock_detection_enabled() {
+            let holders = inner
+                .children
+                .iter()
+                .filter(|p| pid == -1 || pid as usize == p.getpid())
+                .flat_map(|p| {
+                    let child_pid = p.getpid();
+                    let tids = p.inner_exclusive_access().live_tids();
+                    tids.into_iter().map(move |tid| (child_pid, tid))
+                })
+                .collect();
+            set_holders(object, holders);
+        }
+        drop(inner);
+        if let Err(err) = block_on(object) {
+            remove_object(object);
+            return err;
+        }
+        process
+            .inner_exclusive_access()
+            .wait_queue
+            .push_back(current_task().unwrap());
+        block_current_interruptible();
+        unblock(current_thread());
+        remove_object(object);
     }
     // ---- release current PCB lock automatically
 }
@@ -139,8 +211,18 @@ pub fn sys_task_info(_ti: *mut TaskInfo) -> isize {
     -1
 }
 
-pub fn sys_set_priority(_prio: isize) -> isize {
-    -1
+/// Set the priority of the calling thread, at least 2
+///
+/// The thread may keep running with a higher priority it inherited from the
+/// waiters of a mutex it holds.
+pub fn sys_set_priority(prio: isize) -> isize {
+    if prio <= 1 {
+        return -1;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().priority = prio;
+    refresh_effective_priority(&task);
+    prio
 }
 
 pub fn sys_mmap(_start: usize, _len: usize, _port: usize) -> isize {
@@ -151,8 +233,149 @@ pub fn sys_munmap(_start: usize, _len: usize) -> isize {
     -1
 }
 
+/// Send signal `signum` to process `pid`, or to process group `-pid` if `pid` is negative
+pub fn sys_kill(pid: isize, signum: i32) -> isize {
+    let signal = match signum {
+        // only probe for existence
+        0 => None,
+        _ => match SignalFlags::from_signum(signum as usize) {
+            Some(signal) => Some(signal),
+            None => return -1,
+        },
+    };
+    if pid < 0 {
+        let pgid = -pid as usize;
+        let found = match signal {
+            Some(signal) => kill_process_group(pgid, signal),
+            None => !processes_in_group(pgid).is_empty(),
+        };
+        return if found { 0 } else { -1 };
+    }
+    if let Some(process) = pid2process(pid as usize) {
+        if let Some(signal) = signal {
+            process.inner_exclusive_access().send_signal(signal);
+        }
+        0
+    } else {
+        -1
+    }
+}
+
+/// Install the action of `signum`, and store the previous one in `old_action` if not null
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let token = current_user_token();
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    if signum <= 0 || signum as usize > MAX_SIG {
+        return -1;
+    }
+    let flag = SignalFlags::from_signum(signum as usize).unwrap();
+    if flag.intersects(SignalFlags::unmaskable()) {
+        return -1;
+    }
+    if !old_action.is_null() {
+        *translated_refmut(token, old_action) = inner.signal_actions.table[signum as usize];
+    }
+    if !action.is_null() {
+        let mut new_action = *translated_ref(token, action);
+        new_action.mask.remove(SignalFlags::unmaskable());
+        inner.signal_actions.table[signum as usize] = new_action;
+    }
+    0
+}
+
+/// Replace the set of blocked signals, returning the previous one
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    let process = current_process();
+    let mut inner = process.inner_exclusive_access();
+    let old_mask = inner.signal_mask;
+    if let Some(flag) = SignalFlags::from_bits(mask) {
+        inner.signal_mask = flag - SignalFlags::unmaskable();
+        old_mask.bits() as isize
+    } else {
+        -1
+    }
+}
+
+/// Return from a signal handler to the context it interrupted
+pub fn sys_sigreturn() -> isize {
+    if let Some(a0) = restore_signal_frame() {
+        // the syscall return value overwrites a0, so hand back the saved one
+        a0 as isize
+    } else {
+        -1
+    }
+}
+
 //
 // ALERT: 注意在实现 SPAWN 时不需要复制父进程地址空间，SPAWN != FORK + EXEC
 pub fn sys_spawn(_path: *const u8) -> isize {
     -1
 }
+
+/// Move process `pid` into process group `pgid`, creating the group if `pgid == pid`
+///
+/// `pid` must be the caller or one of its children, and the group must lie in
+/// the caller's session.
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    let current = current_process();
+    let process = match pid2process(pid) {
+        Some(process) => process,
+        None => return -1,
+    };
+    let sid = current.inner_exclusive_access().sid;
+    if !Arc::ptr_eq(&process, &current) {
+        let is_child = process
+            .inner_exclusive_access()
+            .parent
+            .as_ref()
+            .and_then(|p| p.upgrade())
+            .map_or(false, |parent| Arc::ptr_eq(&parent, &current));
+        if !is_child {
+            return -1;
+        }
+    }
+    let mut inner = process.inner_exclusive_access();
+    // a session leader cannot leave its group
+    if inner.sid != sid || inner.sid == pid {
+        return -1;
+    }
+    if pgid != pid {
+        drop(inner);
+        let joinable = processes_in_group(pgid)
+            .iter()
+            .any(|p| p.inner_exclusive_access().sid == sid);
+        if !joinable {
+            return -1;
+        }
+        inner = process.inner_exclusive_access();
+    }
+    inner.pgid = pgid;
+    0
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    match pid2process(pid) {
+        Some(process) => process.inner_exclusive_access().pgid as isize,
+        None => -1,
+    }
+}
+
+/// Start a new session led by the caller, in a new process group of its own
+///
+/// Fails if the caller already leads a process group.
+pub fn sys_setsid() -> isize {
+    let process = current_process();
+    let pid = process.getpid();
+    if !processes_in_group(pid).is_empty() {
+        return -1;
+    }
+    let mut inner = process.inner_exclusive_access();
+    inner.pgid = pid;
+    inner.sid = pid;
+    pid as isize
+}
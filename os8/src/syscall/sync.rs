@@ -1,28 +1,64 @@
-use crate::sync::{Condvar, Mutex, MutexBlocking, MutexSpin, Semaphore};
-use crate::task::{block_current_and_run_next, current_process, current_task};
-use crate::timer::{add_timer, get_time_ms};
+use super::errno::{EAGAIN, EBUSY, EDEADLK, EFAULT, EINVAL, EPERM};
+use crate::mm::{PageTable, VirtAddr};
+use crate::sync::{
+    futex_requeue, futex_wait, futex_wake, set_deadlock_policy, Barrier, Condvar, DeadlockPolicy,
+    Mutex, MutexBlocking, MutexSpin, Resource, RwLock, Semaphore,
+};
+use crate::task::{current_process, current_task, current_user_token};
+use crate::timer::{block_current_until, get_time_ms};
 use alloc::sync::Arc;
-use alloc::vec::Vec;
+use core::mem::size_of;
 
 pub fn sys_sleep(ms: usize) -> isize {
-    let expire_ms = get_time_ms() + ms;
-    let task = current_task().unwrap();
-    add_timer(expire_ms, task);
-    block_current_and_run_next();
+    block_current_until(Some(get_time_ms() + ms));
     0
 }
 
+/// Tid of the calling thread
+fn current_tid() -> usize {
+    current_task()
+        .unwrap()
+        .inner_exclusive_access()
+        .res
+        .as_ref()
+        .unwrap()
+        .tid
+}
+
+/// Record that the calling thread `tid` asks for `resource`
+///
+/// Returns false, with the request dropped, if detection is on and granting
+/// it could never happen.
+fn request_resource(tid: usize, resource: Resource) -> bool {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.detector.request(tid, resource);
+    if process_inner.detection {
+        let live = process_inner.live_tids();
+        if process_inner.detector.is_deadlocked(tid, &live) {
+            process_inner.detector.dump();
+            process_inner.detector.cancel(tid);
+            return false;
+        }
+    }
+    true
+}
+
 // LAB5 HINT: you might need to maintain data structures used for deadlock detection
 // during sys_mutex_* and sys_semaphore_* syscalls
-pub fn sys_mutex_create(blocking: bool) -> isize {
+const MUTEX_SPIN: usize = 0;
+const MUTEX_BLOCKING: usize = 1;
+const MUTEX_RECURSIVE: usize = 2;
+
+pub fn sys_mutex_create(kind: usize) -> isize {
     let process = current_process();
-    let mutex: Option<Arc<dyn Mutex>> = if !blocking {
-        Some(Arc::new(MutexSpin::new()))
-    } else {
-        Some(Arc::new(MutexBlocking::new()))
+    let mutex: Option<Arc<dyn Mutex>> = match kind {
+        MUTEX_SPIN => Some(Arc::new(MutexSpin::new())),
+        MUTEX_BLOCKING => Some(MutexBlocking::new()),
+        MUTEX_RECURSIVE => Some(MutexBlocking::new_recursive()),
+        _ => return EINVAL,
     };
     let mut process_inner = process.inner_exclusive_access();
-    let det = process_inner.detection;
     let m_id = if let Some(id) = process_inner
         .mutex_list
         .iter()
@@ -31,79 +67,111 @@ pub fn sys_mutex_create(blocking: bool) -> isize {
         .map(|(id, _)| id)
     {
         process_inner.mutex_list[id] = mutex;
-        id as isize
+        id
     } else {
         process_inner.mutex_list.push(mutex);
-        process_inner.mutex_list.len() as isize - 1
+        process_inner.mutex_list.len() - 1
     };
-    if det{
-        process_inner.detector.create_mutex(m_id as usize);
-    }
-    m_id
+    process_inner
+        .detector
+        .add_resource(Resource::Mutex(m_id), 1);
+    m_id as isize
 }
 
 // LAB5 HINT: Return -0xDEAD if deadlock is detected
 pub fn sys_mutex_lock(mutex_id: usize) -> isize {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
-    let det = process_inner.detection;
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return EINVAL,
+    };
     drop(process_inner);
-    drop(process);
-    let tid = {current_task()
-        .unwrap()
-        .inner_exclusive_access()
-        .res
-        .as_ref()
-        .unwrap()
-        .tid};
-    if det{
-        let process = current_process();
-        let mut process_inner = process.inner_exclusive_access();
-        let task_set = process_inner.tasks
-            .iter().map(|t| 
-                t.as_ref().map_or(true, |tt| tt.inner_exclusive_access().res.is_none())
-            ).collect::<Vec<_>>();
-        if process_inner.detector.check_mutex(tid, mutex_id, task_set){
-            return -0xdead;
-        }
+    if mutex.relock() {
+        return 0;
     }
-    mutex.lock();
-    if det{
-        let process = current_process();
-        let mut process_inner = process.inner_exclusive_access();
-        process_inner.detector.alloc_mutex(tid, mutex_id);
+    let tid = current_tid();
+    if !request_resource(tid, Resource::Mutex(mutex_id)) {
+        return EDEADLK;
+    }
+    let result = mutex.lock();
+    let mut process_inner = process.inner_exclusive_access();
+    if let Err(err) = result {
+        process_inner.detector.cancel(tid);
+        return err;
+    }
+    process_inner
+        .detector
+        .acquire(tid, Resource::Mutex(mutex_id));
+    0
+}
+
+/// Take mutex `mutex_id` if it is free, else wait for it at most `timeout_ms`
+/// milliseconds unless it is 0
+///
+/// Fails with `EBUSY` without waiting and with `ETIMEDOUT` once the time is up.
+pub fn sys_mutex_trylock(mutex_id: usize, timeout_ms: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    if mutex.relock() {
+        return 0;
     }
+    let expire_ms = (timeout_ms != 0).then(|| get_time_ms() + timeout_ms);
+    if let Err(err) = mutex.try_lock(expire_ms) {
+        return err;
+    }
+    process
+        .inner_exclusive_access()
+        .detector
+        .take(current_tid(), Resource::Mutex(mutex_id));
     0
 }
 
 pub fn sys_mutex_unlock(mutex_id: usize) -> isize {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
-    let det = process_inner.detection;
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return EINVAL,
+    };
     drop(process_inner);
-    drop(process);
-    let tid = {current_task()
-        .unwrap()
+    if mutex.unlock_nested() {
+        return 0;
+    }
+    if let Err(err) = mutex.unlock() {
+        return err;
+    }
+    process
         .inner_exclusive_access()
-        .res
-        .as_ref()
-        .unwrap()
-        .tid};
-    if det{
-        let process = current_process();
-        let mut process_inner = process.inner_exclusive_access();
-        process_inner.detector.cycle_mutex(tid, mutex_id);
+        .detector
+        .release(current_tid(), Resource::Mutex(mutex_id));
+    0
+}
+
+/// Free mutex `mutex_id`, failing with `EBUSY` while it is held
+pub fn sys_mutex_destroy(mutex_id: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) if mutex.is_busy() => return EBUSY,
+        Some(Some(_)) => {}
+        _ => return EINVAL,
     }
-    mutex.unlock();
+    process_inner.mutex_list[mutex_id] = None;
+    process_inner
+        .detector
+        .remove_resource(Resource::Mutex(mutex_id));
     0
 }
 
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    let det = process_inner.detection;
     let id = if let Some(id) = process_inner
         .semaphore_list
         .iter()
@@ -119,30 +187,23 @@ pub fn sys_semaphore_create(res_count: usize) -> isize {
             .push(Some(Arc::new(Semaphore::new(res_count))));
         process_inner.semaphore_list.len() - 1
     };
-    if det{
-        process_inner.detector.create_sem(id, res_count);
-    }
+    process_inner
+        .detector
+        .add_resource(Resource::Semaphore(id), res_count);
     id as isize
 }
 
 pub fn sys_semaphore_up(sem_id: usize) -> isize {
     let process = current_process();
-    let process_inner = process.inner_exclusive_access();
-    let det = process_inner.detection;
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let mut process_inner = process.inner_exclusive_access();
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return EINVAL,
+    };
+    process_inner
+        .detector
+        .release(current_tid(), Resource::Semaphore(sem_id));
     drop(process_inner);
-    let tid = {current_task()
-        .unwrap()
-        .inner_exclusive_access()
-        .res
-        .as_ref()
-        .unwrap()
-        .tid};
-    if det{
-        let process = current_process();
-        let mut process_inner = process.inner_exclusive_access();
-        process_inner.detector.cycle_sem(tid, sem_id)
-    }
     sem.up();
     0
 }
@@ -151,33 +212,61 @@ pub fn sys_semaphore_up(sem_id: usize) -> isize {
 pub fn sys_semaphore_down(sem_id: usize) -> isize {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
-    let det = process_inner.detection;
-    let sem = Arc::clone(process_inner.semaphore_list[sem_id].as_ref().unwrap());
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return EINVAL,
+    };
     drop(process_inner);
-    let tid = {current_task()
-        .unwrap()
-        .inner_exclusive_access()
-        .res
-        .as_ref()
-        .unwrap()
-        .tid};
-    if det{
-        let process = current_process();
-        let mut process_inner = process.inner_exclusive_access();
-        let task_set = process_inner.tasks
-            .iter().map(|t| 
-                t.as_ref().map_or(true, |tt| tt.inner_exclusive_access().res.is_none())
-            ).collect::<Vec<_>>();
-        if process_inner.detector.check_semaphore(tid, sem_id, task_set){
-            return -0xdead;
-        }
+    let tid = current_tid();
+    if !request_resource(tid, Resource::Semaphore(sem_id)) {
+        return EDEADLK;
     }
-    sem.down();
-    if det{
-        let process = current_process();
-        let mut process_inner = process.inner_exclusive_access();
-        process_inner.detector.alloc_semaphore(tid, sem_id);
+    let result = sem.down();
+    let mut process_inner = process.inner_exclusive_access();
+    if let Err(err) = result {
+        process_inner.detector.cancel(tid);
+        return err;
     }
+    process_inner
+        .detector
+        .acquire(tid, Resource::Semaphore(sem_id));
+    0
+}
+
+/// Take a unit of semaphore `sem_id`, waiting at most `timeout_ms` milliseconds
+///
+/// Fails with `ETIMEDOUT` once the time is up.
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let sem = match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) => Arc::clone(sem),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    if let Err(err) = sem.down_until(Some(get_time_ms() + timeout_ms)) {
+        return err;
+    }
+    process
+        .inner_exclusive_access()
+        .detector
+        .take(current_tid(), Resource::Semaphore(sem_id));
+    0
+}
+
+/// Free semaphore `sem_id`, failing with `EBUSY` while threads wait for it
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    match process_inner.semaphore_list.get(sem_id) {
+        Some(Some(sem)) if sem.is_busy() => return EBUSY,
+        Some(Some(_)) => {}
+        _ => return EINVAL,
+    }
+    process_inner.semaphore_list[sem_id] = None;
+    process_inner
+        .detector
+        .remove_resource(Resource::Semaphore(sem_id));
     0
 }
 
@@ -205,26 +294,286 @@ pub fn sys_condvar_create(_arg: usize) -> isize {
 pub fn sys_condvar_signal(condvar_id: usize) -> isize {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return EINVAL,
+    };
     drop(process_inner);
+    if !condvar.inner.exclusive_access().wait_queue.is_empty() {
+        let mut process_inner = process.inner_exclusive_access();
+        process_inner.detector.signal(condvar_id);
+    }
     condvar.signal();
     0
 }
 
-pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+/// Wake up all threads waiting on condvar `condvar_id`
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
     let process = current_process();
     let process_inner = process.inner_exclusive_access();
-    let condvar = Arc::clone(process_inner.condvar_list[condvar_id].as_ref().unwrap());
-    let mutex = Arc::clone(process_inner.mutex_list[mutex_id].as_ref().unwrap());
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    let waiters = condvar.inner.exclusive_access().wait_queue.len();
+    let mut process_inner = process.inner_exclusive_access();
+    for _ in 0..waiters {
+        process_inner.detector.signal(condvar_id);
+    }
     drop(process_inner);
-    condvar.wait(mutex);
+    condvar.broadcast();
     0
 }
 
-// LAB5 YOUR JOB: Implement deadlock detection, but might not all in this syscall
-pub fn sys_enable_deadlock_detect(_enabled: usize) -> isize {
+/// Free condvar `condvar_id`, failing with `EBUSY` while threads wait on it
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
     let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    process_inner.detection = true;
+    match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) if condvar.is_busy() => return EBUSY,
+        Some(Some(_)) => {}
+        _ => return EINVAL,
+    }
+    process_inner.condvar_list[condvar_id] = None;
+    0
+}
+
+pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return EINVAL,
+    };
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return EINVAL,
+    };
+    let tid = current_tid();
+    // the wait gives the mutex back, so check the state it would leave behind
+    if process_inner.detection {
+        let live = process_inner.live_tids();
+        if process_inner
+            .detector
+            .would_deadlock_on_condvar(tid, condvar_id, mutex_id, &live)
+        {
+            process_inner.detector.dump();
+            return EDEADLK;
+        }
+    }
+    process_inner
+        .detector
+        .wait_condvar(tid, condvar_id, mutex_id);
+    drop(process_inner);
+    let result = condvar.wait(mutex);
+    let mut process_inner = process.inner_exclusive_access();
+    if let Err(err) = result {
+        process_inner.detector.cancel(tid);
+        return err;
+    }
+    process_inner
+        .detector
+        .acquire(tid, Resource::Mutex(mutex_id));
+    0
+}
+
+/// Wait for a signal on condvar `condvar_id` for at most `timeout_ms` milliseconds
+///
+/// Mutex `mutex_id` is taken back either way, then it fails with `ETIMEDOUT`
+/// if the time is up.
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let condvar = match process_inner.condvar_list.get(condvar_id) {
+        Some(Some(condvar)) => Arc::clone(condvar),
+        _ => return EINVAL,
+    };
+    let mutex = match process_inner.mutex_list.get(mutex_id) {
+        Some(Some(mutex)) => Arc::clone(mutex),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    // the detector sees the mutex held all along, as the wait ends anyway
+    match condvar.wait_until(mutex, Some(get_time_ms() + timeout_ms)) {
+        Ok(()) => 0,
+        Err(EPERM) => EPERM,
+        Err(EDEADLK) => {
+            // the mutex was given back but could not be taken again
+            process
+                .inner_exclusive_access()
+                .detector
+                .release(current_tid(), Resource::Mutex(mutex_id));
+            EDEADLK
+        }
+        Err(err) => err,
+    }
+}
+
+pub fn sys_rwlock_create() -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .rwlock_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.rwlock_list[id] = Some(Arc::new(RwLock::new()));
+        id
+    } else {
+        process_inner
+            .rwlock_list
+            .push(Some(Arc::new(RwLock::new())));
+        process_inner.rwlock_list.len() - 1
+    };
+    id as isize
+}
+
+/// Take reader-writer lock `rwlock_id` shared, or exclusive if `write` is set
+///
+/// Like the other locks, waiting for it is checked by the kernel-wide
+/// wait-for graph.
+pub fn sys_rwlock_lock(rwlock_id: usize, write: bool) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match process_inner.rwlock_list.get(rwlock_id) {
+        Some(Some(rwlock)) => Arc::clone(rwlock),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    let result = if write { rwlock.write() } else { rwlock.read() };
+    match result {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+pub fn sys_rwlock_unlock(rwlock_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let rwlock = match process_inner.rwlock_list.get(rwlock_id) {
+        Some(Some(rwlock)) => Arc::clone(rwlock),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    match rwlock.unlock() {
+        Ok(()) => 0,
+        Err(err) => err,
+    }
+}
+
+pub fn sys_barrier_create(count: usize) -> isize {
+    if count == 0 {
+        return EINVAL;
+    }
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let id = if let Some(id) = process_inner
+        .barrier_list
+        .iter()
+        .enumerate()
+        .find(|(_, item)| item.is_none())
+        .map(|(id, _)| id)
+    {
+        process_inner.barrier_list[id] = Some(Arc::new(Barrier::new(count)));
+        id
+    } else {
+        process_inner
+            .barrier_list
+            .push(Some(Arc::new(Barrier::new(count))));
+        process_inner.barrier_list.len() - 1
+    };
+    id as isize
+}
+
+/// Wait at barrier `barrier_id`, returns 1 in the last thread to arrive and 0 in the others
+pub fn sys_barrier_wait(barrier_id: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    let barrier = match process_inner.barrier_list.get(barrier_id) {
+        Some(Some(barrier)) => Arc::clone(barrier),
+        _ => return EINVAL,
+    };
+    drop(process_inner);
+    match barrier.wait() {
+        Ok(last) => last as isize,
+        Err(err) => err,
+    }
+}
+
+pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.detection = enabled != 0;
     0
 }
+
+/// Choose what happens to the threads of the calling process that would
+/// block for ever, see [`DeadlockPolicy`]; children inherit it on fork
+pub fn sys_set_deadlock_policy(policy: usize) -> isize {
+    match DeadlockPolicy::from_usize(policy) {
+        Some(policy) => {
+            set_deadlock_policy(current_process().getpid(), policy);
+            0
+        }
+        None => -1,
+    }
+}
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_REQUEUE: usize = 3;
+
+/// Physical address of the user word at `uaddr`, the key of its futex
+fn futex_key(uaddr: usize) -> Option<usize> {
+    if uaddr % size_of::<u32>() != 0 {
+        return None;
+    }
+    let page_table = PageTable::from_token(current_user_token());
+    let pte = page_table.translate(VirtAddr::from(uaddr).floor())?;
+    if !pte.is_valid() || !pte.is_user() || !pte.readable() {
+        return None;
+    }
+    page_table
+        .translate_va(VirtAddr::from(uaddr))
+        .map(usize::from)
+}
+
+/// Block on or wake up threads blocked on the futex of the user word at `uaddr`
+///
+/// - `FUTEX_WAIT` sleeps as long as the word holds `val`, for at most `arg`
+///   milliseconds unless it is 0, failing with `EAGAIN` if it does not hold
+///   `val` to begin with and with `ETIMEDOUT` once the time is up.
+/// - `FUTEX_WAKE` wakes up to `val` threads.
+/// - `FUTEX_REQUEUE` wakes up to `val` threads and moves up to `arg` of the
+///   others to the futex at `uaddr2`.
+///
+/// Wakeups return the number of threads woken up.
+pub fn sys_futex(uaddr: usize, op: usize, val: usize, arg: usize, uaddr2: usize) -> isize {
+    let key = match futex_key(uaddr) {
+        Some(key) => key,
+        None => return EFAULT,
+    };
+    match op {
+        FUTEX_WAIT => {
+            // nothing can change the word until we are queued
+            let word = unsafe { (key as *const u32).read_volatile() };
+            if word != val as u32 {
+                return EAGAIN;
+            }
+            let expire_ms = (arg != 0).then(|| get_time_ms() + arg);
+            match futex_wait(key, expire_ms) {
+                Ok(()) => 0,
+                Err(err) => err,
+            }
+        }
+        FUTEX_WAKE => futex_wake(key, val) as isize,
+        FUTEX_REQUEUE => match futex_key(uaddr2) {
+            Some(key2) => futex_requeue(key, val, key2, arg) as isize,
+            None => EFAULT,
+        },
+        _ => EINVAL,
+    }
+}
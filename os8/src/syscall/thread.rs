@@ -1,6 +1,10 @@
+use super::process::WNOHANG;
 use crate::{
     mm::kernel_token,
-    task::{add_task, current_task, TaskControlBlock},
+    task::{
+        add_task, block_current_interruptible, current_interrupted, current_process, current_task,
+        TaskControlBlock,
+    },
     trap::{trap_handler, TrapContext},
 };
 use alloc::sync::Arc;
@@ -30,6 +34,7 @@ pub fn sys_thread_create(entry: usize, arg: usize) -> isize {
         trap_handler as usize,
     );
     (*new_task_trap_cx).x[10] = arg;
+    drop(new_task_inner);
 
     let mut process_inner = process.inner_exclusive_access();
     // add new thread to current process
@@ -54,33 +59,90 @@ pub fn sys_gettid() -> isize {
 }
 
 /// thread does not exist, return -1
-/// thread has not exited yet, return -2
+/// thread has not exited yet, block until it does,
+/// or return -2 at once if `options` contains `WNOHANG`
 /// otherwise, return thread's exit code
-pub fn sys_waittid(tid: usize) -> i32 {
+pub fn sys_waittid(tid: usize, options: usize) -> i32 {
     let task = current_task().unwrap();
     let process = task.process.upgrade().unwrap();
-    let task_inner = task.inner_exclusive_access();
+    loop {
+        let task_inner = task.inner_exclusive_access();
+        let mut process_inner = process.inner_exclusive_access();
+        // a thread cannot wait for itself
+        if task_inner.res.as_ref().unwrap().tid == tid {
+            return -1;
+        }
+        let waited_task = match process_inner.tasks.get(tid) {
+            Some(Some(waited_task)) => Arc::clone(waited_task),
+            // waited thread does not exist
+            _ => return -1,
+        };
+        if waited_task.inner_exclusive_access().detached {
+            return -1;
+        }
+        let exit_code = waited_task.inner_exclusive_access().exit_code;
+        if let Some(exit_code) = exit_code {
+            // dealloc the exited thread
+            process_inner.tasks[tid] = None;
+            return exit_code;
+        }
+        // waited thread has not exited
+        if options & WNOHANG != 0 {
+            return -2;
+        }
+        drop(process_inner);
+        drop(task_inner);
+        if current_interrupted() {
+            return -1;
+        }
+        waited_task
+            .inner_exclusive_access()
+            .wait_queue
+            .push_back(Arc::clone(&task));
+        block_current_interruptible();
+    }
+}
+
+/// Let thread `tid` be reclaimed as soon as it exits, it can no longer be waited for
+///
+/// The main thread cannot be detached since the process exits with it.
+pub fn sys_thread_detach(tid: usize) -> isize {
+    let process = current_process();
     let mut process_inner = process.inner_exclusive_access();
-    // a thread cannot wait for itself
-    if task_inner.res.as_ref().unwrap().tid == tid {
+    let task = match process_inner.tasks.get(tid) {
+        Some(Some(task)) if tid != 0 => Arc::clone(task),
+        _ => return -1,
+    };
+    let mut task_inner = task.inner_exclusive_access();
+    if task_inner.detached {
         return -1;
     }
-    let mut exit_code: Option<i32> = None;
-    let waited_task = process_inner.tasks[tid].as_ref();
-    if let Some(waited_task) = waited_task {
-        if let Some(waited_exit_code) = waited_task.inner_exclusive_access().exit_code {
-            exit_code = Some(waited_exit_code);
-        }
-    } else {
-        // waited thread does not exist
-        return -1;
+    task_inner.detached = true;
+    // joiners give up
+    for joiner in task_inner.wait_queue.drain(..) {
+        add_task(joiner);
     }
-    if let Some(exit_code) = exit_code {
-        // dealloc the exited thread
+    if task_inner.exit_code.is_some() {
+        // already exited, reclaim it now
+        drop(task_inner);
         process_inner.tasks[tid] = None;
-        exit_code
-    } else {
-        // waited thread has not exited
-        -2
+    }
+    0
+}
+
+/// Ask thread `tid` to exit with [`THREAD_CANCELED`](crate::task::THREAD_CANCELED) at its next kernel entry
+pub fn sys_thread_cancel(tid: usize) -> isize {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    match process_inner.tasks.get(tid) {
+        Some(Some(task)) => {
+            let mut task_inner = task.inner_exclusive_access();
+            if task_inner.exit_code.is_some() {
+                return -1;
+            }
+            task_inner.cancelled = true;
+            0
+        }
+        _ => -1,
     }
 }
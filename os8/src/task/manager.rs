@@ -3,31 +3,65 @@
 //! It is only used to manage processes and schedule process based on ready queue.
 //! Other CPU process monitoring functions are in Processor.
 
-
-use super::TaskControlBlock;
+use super::{ProcessControlBlock, TaskControlBlock, TaskStatus};
+use crate::mm::PhysPageNum;
 use crate::sync::UPSafeCell;
-use alloc::collections::VecDeque;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use lazy_static::*;
 
 pub struct TaskManager {
     ready_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Stride of the task fetched last, below which no task is queued
+    min_stride: usize,
 }
 
-/// A simple FIFO scheduler.
+/// A stride scheduler, running the ready task with the lowest stride first.
+///
+/// It replaces the FIFO one so that priorities, including the ones inherited
+/// through a mutex, decide who runs.
 impl TaskManager {
     pub fn new() -> Self {
         Self {
             ready_queue: VecDeque::new(),
+            min_stride: 0,
         }
     }
     /// Add process back to ready queue
+    ///
+    /// New and woken up tasks start from the current stride, so that they
+    /// do not monopolize the processor to catch up. A task already queued is
+    /// not added twice, since an interrupted task may still be found in the
+    /// wait queue it left and be woken up again, and neither is one that
+    /// has exited.
     pub fn add(&mut self, task: Arc<TaskControlBlock>) {
+        let mut task_inner = task.inner_exclusive_access();
+        if matches!(
+            task_inner.task_status,
+            TaskStatus::Ready | TaskStatus::Exited
+        ) {
+            return;
+        }
+        task_inner.task_status = TaskStatus::Ready;
+        task_inner.stride = task_inner.stride.max(self.min_stride);
+        drop(task_inner);
         self.ready_queue.push_back(task);
     }
     /// Take a process out of the ready queue
+    ///
+    /// A linear scan rather than a heap, as only a handful of tasks are ready
+    /// at a time, and it keeps ties in the order they were queued, so that
+    /// tasks of equal priority still take turns.
     pub fn fetch(&mut self) -> Option<Arc<TaskControlBlock>> {
-        self.ready_queue.pop_front()
+        let (idx, stride) = self
+            .ready_queue
+            .iter()
+            .map(|task| task.inner_exclusive_access().stride)
+            .enumerate()
+            .min_by_key(|&(_, stride)| stride)?;
+        self.min_stride = stride;
+        self.ready_queue.remove(idx)
     }
 }
 
@@ -35,6 +69,9 @@ lazy_static! {
     /// TASK_MANAGER instance through lazy_static!
     pub static ref TASK_MANAGER: UPSafeCell<TaskManager> =
         unsafe { UPSafeCell::new(TaskManager::new()) };
+    /// All live processes indexed by pid, so that they can be found by `sys_kill`
+    pub static ref PID2PCB: UPSafeCell<BTreeMap<usize, Arc<ProcessControlBlock>>> =
+        unsafe { UPSafeCell::new(BTreeMap::new()) };
 }
 
 pub fn add_task(task: Arc<TaskControlBlock>) {
@@ -44,3 +81,59 @@ pub fn add_task(task: Arc<TaskControlBlock>) {
 pub fn fetch_task() -> Option<Arc<TaskControlBlock>> {
     TASK_MANAGER.exclusive_access().fetch()
 }
+
+pub fn pid2process(pid: usize) -> Option<Arc<ProcessControlBlock>> {
+    let map = PID2PCB.exclusive_access();
+    map.get(&pid).map(Arc::clone)
+}
+
+pub fn insert_into_pid2process(pid: usize, process: Arc<ProcessControlBlock>) {
+    PID2PCB.exclusive_access().insert(pid, process);
+}
+
+pub fn remove_from_pid2process(pid: usize) {
+    let mut map = PID2PCB.exclusive_access();
+    if map.remove(&pid).is_none() {
+        panic!("cannot find pid {} in pid2process!", pid);
+    }
+}
+
+/// Live processes whose process group is `pgid`
+pub fn processes_in_group(pgid: usize) -> Vec<Arc<ProcessControlBlock>> {
+    let map = PID2PCB.exclusive_access();
+    map.values()
+        .filter(|process| process.inner_exclusive_access().pgid == pgid)
+        .map(Arc::clone)
+        .collect()
+}
+
+/// Live processes mapping frame `ppn` in a shared area
+pub fn processes_sharing_frame(ppn: PhysPageNum) -> Vec<Arc<ProcessControlBlock>> {
+    let map = PID2PCB.exclusive_access();
+    map.values()
+        .filter(|process| {
+            process
+                .inner_exclusive_access()
+                .memory_set
+                .shares_frame(ppn)
+        })
+        .map(Arc::clone)
+        .collect()
+}
+
+/// Live processes having the file at address `file`, or a file open on it,
+/// in their fd table
+pub fn processes_with_file(file: usize) -> Vec<Arc<ProcessControlBlock>> {
+    let map = PID2PCB.exclusive_access();
+    map.values()
+        .filter(|process| {
+            process
+                .inner_exclusive_access()
+                .fd_table
+                .iter()
+                .flatten()
+                .any(|f| f.is_open_on(file))
+        })
+        .map(Arc::clone)
+        .collect()
+}
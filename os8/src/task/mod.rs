@@ -15,6 +15,7 @@ pub mod kthread;
 mod manager;
 mod process;
 mod processor;
+mod signal;
 pub mod stackless_coroutine;
 mod switch;
 #[allow(clippy::module_inception)]
@@ -23,20 +24,32 @@ mod task;
 pub use crate::syscall::process::TaskInfo;
 use crate::{
     fs::{open_file, OpenFlags},
-    task::id::TaskUserRes,
+    mm::{translated_byte_buffer, PageTable, StepByOne, VirtAddr},
+    sync::{forget_process, forget_thread},
+    timer::wakeup_task,
 };
-use alloc::{sync::Arc, vec::Vec};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 pub use context::TaskContext;
+use core::mem::size_of;
 pub use id::{kstack_alloc, pid_alloc, KernelStack, PidHandle};
 pub use kthread::kernel_stackful_coroutine_test;
 use lazy_static::*;
-pub use manager::add_task;
 use manager::fetch_task;
-use process::ProcessControlBlock;
+pub use manager::{
+    add_task, pid2process, processes_in_group, processes_sharing_frame, processes_with_file,
+    remove_from_pid2process,
+};
+pub use process::ProcessControlBlock;
+use process::ProcessControlBlockInner;
+use processor::release_after_switch;
 pub use processor::{
     current_process, current_task, current_trap_cx, current_trap_cx_user_va, current_user_token,
     run_tasks, schedule, take_current_task,
 };
+pub use signal::{
+    DefaultAction, SignalAction, SignalActions, SignalFlags, SignalFrame, MAX_SIG, SIG_DFL, SIG_IGN,
+};
 pub use stackless_coroutine::kernel_stackless_coroutine_test;
 use switch::__switch;
 pub use task::{TaskControlBlock, TaskStatus};
@@ -50,6 +63,44 @@ pub fn block_current_and_run_next() {
     schedule(task_cx_ptr);
 }
 
+/// Block the current task until woken up, or until it has to exit
+///
+/// [`interrupt_task`] makes it ready while it is still in the wait queue it
+/// put itself in, and it does not block at all if [`current_interrupted`]
+/// holds already. The caller has to look for that when it comes back, and
+/// leave the queue if its wakers hand anything over to the thread they take.
+pub fn block_current_interruptible() {
+    if current_interrupted() {
+        return;
+    }
+    let task = current_task().unwrap();
+    task.inner_exclusive_access().interruptible = true;
+    block_current_and_run_next();
+    task.inner_exclusive_access().interruptible = false;
+}
+
+/// Wake up `task` if it is blocked in [`block_current_interruptible`]
+pub fn interrupt_task(task: Arc<TaskControlBlock>) {
+    let task_inner = task.inner_exclusive_access();
+    let blocked = task_inner.task_status == TaskStatus::Blocking && task_inner.interruptible;
+    drop(task_inner);
+    if blocked {
+        wakeup_task(task);
+    }
+}
+
+/// Whether the current thread has to give up waiting and exit, because
+/// another thread is tearing down its process or it has been killed by a signal
+pub fn current_interrupted() -> bool {
+    if current_task().unwrap().inner_exclusive_access().killed {
+        return true;
+    }
+    current_process()
+        .inner_exclusive_access()
+        .fatal_signal()
+        .is_some()
+}
+
 /// Make current task suspended and switch to the next task
 pub fn suspend_current_and_run_next() {
     // There must be an application running.
@@ -59,41 +110,104 @@ pub fn suspend_current_and_run_next() {
     let mut task_inner = task.inner_exclusive_access();
 
     let task_cx_ptr = &mut task_inner.task_cx as *mut TaskContext;
-    // Change status to Ready
-    task_inner.task_status = TaskStatus::Ready;
     drop(task_inner);
     // ---- release current PCB
 
-    // push back to ready queue.
+    // push back to ready queue, which changes its status to Ready
     add_task(task);
     // jump to scheduling cycle
     schedule(task_cx_ptr);
 }
 
+/// Exit code of a thread terminated by `sys_thread_cancel`
+pub const THREAD_CANCELED: i32 = -0xca;
+
 /// Exit current task, recycle process resources and switch to the next task
+///
+/// The whole process exits with its main thread.
 pub fn exit_current_and_run_next(exit_code: i32) {
+    exit_current(exit_code, false);
+}
+
+/// Exit the process of the current task from any of its threads and switch to the next task
+pub fn exit_group_and_run_next(exit_code: i32) {
+    exit_current(exit_code, true);
+}
+
+/// Have every other thread of the current process exit, and wait until they have
+///
+/// They are woken up from interruptible waits and exit at the kernel boundary,
+/// dropping whatever they hold on the way out, while those inside the file
+/// system or waiting for the disk finish that first. Returns false if the
+/// current thread is killed in turn by another one doing the same.
+fn kill_other_threads() -> bool {
+    let task = current_task().unwrap();
+    let process = current_process();
+    loop {
+        if task.inner_exclusive_access().killed {
+            return false;
+        }
+        let others: Vec<Arc<TaskControlBlock>> = process
+            .inner_exclusive_access()
+            .tasks
+            .iter()
+            .flatten()
+            .filter(|other| !Arc::ptr_eq(other, &task))
+            .filter(|other| other.inner_exclusive_access().res.is_some())
+            .cloned()
+            .collect();
+        if others.is_empty() {
+            return true;
+        }
+        for other in others {
+            other.inner_exclusive_access().killed = true;
+            interrupt_task(other);
+        }
+        suspend_current_and_run_next();
+    }
+}
+
+fn exit_current(exit_code: i32, whole_process: bool) {
+    let task = current_task().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    let killed = task_inner.killed;
+    let tid = task_inner.res.as_ref().unwrap().tid;
+    drop(task_inner);
+    drop(task);
+    // a thread killed by another one tearing down the process only exits itself
+    let whole_process = !killed && (tid == 0 || whole_process) && kill_other_threads();
     // take from Processor
     let task = take_current_task().unwrap();
+    #[cfg(debug_assertions)]
+    crate::sync::lockdep::thread_exited(Arc::as_ptr(&task) as usize);
     // **** access current TCB exclusively
     let mut task_inner = task.inner_exclusive_access();
     let process = task.process.upgrade().unwrap();
-    let tid = task_inner.res.as_ref().unwrap().tid;
     // Record exit code
     task_inner.exit_code = Some(exit_code);
+    task_inner.task_status = TaskStatus::Exited;
     task_inner.res = None;
+    // wake up threads joining this one
+    for joiner in task_inner.wait_queue.drain(..) {
+        add_task(joiner);
+    }
 
-    // here we do not remove the thread since we are still using the kstack
-    // it will be deallocated when sys_waittid is called
+    let detached = task_inner.detached;
     drop(task_inner);
-    drop(task);
-    // debug!("task {} dropped", tid);
+    forget_thread((process.getpid(), tid));
 
-    if tid == 0 {
+    if whole_process {
+        remove_from_pid2process(process.getpid());
+        forget_process(process.getpid(), true);
         let mut process_inner = process.inner_exclusive_access();
         // mark this process as a zombie process
         process_inner.is_zombie = true;
-        // record exit code of main process
-        process_inner.exit_code = exit_code;
+        // record exit status of main process
+        let killed_by = process_inner.fatal_signal();
+        process_inner.exit_code = exit_status(exit_code, killed_by);
+
+        // the parent may be blocked in sys_waitpid
+        notify_parent(&process_inner);
 
         // do not move to its parent but under initproc
         // debug!("reparent");
@@ -105,32 +219,37 @@ pub fn exit_current_and_run_next(exit_code: i32) {
                 child.inner_exclusive_access().parent = Some(Arc::downgrade(&INITPROC));
                 initproc_inner.children.push(child.clone());
             }
-        }
-        let mut recycle_res = Vec::<TaskUserRes>::new();
-
-        // debug!("deallocate user res");
-        // deallocate user res (including tid/trap_cx/ustack) of all threads
-        // it has to be done before we dealloc the whole memory_set
-        // otherwise they will be deallocated twice
-        for task in process_inner.tasks.iter().filter(|t| t.is_some()) {
-            let task = task.as_ref().unwrap();
-            let mut task_inner = task.inner_exclusive_access();
-            if let Some(res) = task_inner.res.take() {
-                recycle_res.push(res);
+            if !process_inner.children.is_empty() {
+                // some of them may be zombies already
+                initproc_inner.wakeup_waiters();
             }
         }
-        drop(process_inner);
-        recycle_res.clear();
-        let mut process_inner = process.inner_exclusive_access();
+        // the other threads have exited and given back their user res already
+        process_inner.forget_other_threads(tid);
         // debug!("deallocate pcb res");
         process_inner.children.clear();
         // deallocate other data in user space i.e. program code/data section
         process_inner.memory_set.recycle_data_pages();
         // drop file descriptors
         process_inner.fd_table.clear();
+        // senders blocked on a full mailbox give up
+        process_inner.mailbox.close();
+    } else if detached {
+        // nobody is going to wait for it
+        process.inner_exclusive_access().tasks[tid] = None;
     }
     // debug!("pcb dropped");
 
+    // here we do not deallocate the thread since we are still using the kstack
+    // it will be deallocated when sys_waittid is called, or right after the
+    // switch below if it is detached
+    if detached {
+        release_after_switch(task);
+    } else {
+        drop(task);
+    }
+    // debug!("task {} dropped", tid);
+
     // ++++++ release parent PCB
     drop(process);
 
@@ -139,6 +258,231 @@ pub fn exit_current_and_run_next(exit_code: i32) {
     schedule(&mut _unused as *mut _);
 }
 
+/// Whether the current thread has been cancelled by `sys_thread_cancel`, or
+/// killed by another thread exiting the process or calling exec
+pub fn current_cancelled() -> bool {
+    let task = current_task().unwrap();
+    let task_inner = task.inner_exclusive_access();
+    task_inner.cancelled || task_inner.killed
+}
+
+/// Encode how a process ended the way `WIFEXITED`/`WEXITSTATUS`/`WIFSIGNALED` expect
+///
+/// The low 7 bits hold the terminating signal, or 0 for a normal exit whose
+/// code is then kept in bits 8..16.
+pub fn exit_status(exit_code: i32, killed_by: Option<SignalFlags>) -> i32 {
+    match killed_by.and_then(|signal| signal.first_signum()) {
+        Some(signum) => signum as i32 & 0x7f,
+        None => (exit_code & 0xff) << 8,
+    }
+}
+
+/// Send SIGCHLD to the parent of a process that has exited or stopped
+fn notify_parent(process_inner: &ProcessControlBlockInner) {
+    if let Some(parent) = process_inner.parent.as_ref().and_then(|p| p.upgrade()) {
+        parent
+            .inner_exclusive_access()
+            .send_signal(SignalFlags::SIGCHLD);
+    }
+}
+
+/// Send `signal` to every process in group `pgid`
+///
+/// Returns false if the group is empty.
+pub fn kill_process_group(pgid: usize, signal: SignalFlags) -> bool {
+    let group = processes_in_group(pgid);
+    for process in group.iter() {
+        process.inner_exclusive_access().send_signal(signal);
+    }
+    !group.is_empty()
+}
+
+/// Raise a signal caused by the instruction the current task just executed
+///
+/// Blocking or ignoring it would re-execute the faulting instruction forever,
+/// so the signal is unblocked and an ignoring handler is reset to default.
+pub fn current_force_signal(signal: SignalFlags) {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    let signum = signal.first_signum().unwrap();
+    if process_inner.signal_actions.table[signum].handler == SIG_IGN {
+        process_inner.signal_actions.table[signum] = SignalAction::default();
+    }
+    process_inner.signal_mask.remove(signal);
+    process_inner.signals |= signal;
+}
+
+/// Deliver pending signals of the current process before returning to user mode
+///
+/// A stopped process does not leave this function until it is continued or killed.
+pub fn handle_signals() {
+    loop {
+        check_pending_signals();
+        let process = current_process();
+        let process_inner = process.inner_exclusive_access();
+        let frozen = process_inner.frozen;
+        let killed = process_inner.fatal_signal().is_some();
+        drop(process_inner);
+        if !frozen || killed {
+            break;
+        }
+        suspend_current_and_run_next();
+    }
+}
+
+fn check_pending_signals() {
+    let process = current_process();
+    let mut process_inner = process.inner_exclusive_access();
+    loop {
+        let mut deliverable = process_inner.deliverable_signals();
+        if process_inner.frozen {
+            // a stopped process only reacts to being killed or continued
+            deliverable &= SignalFlags::SIGKILL | SignalFlags::SIGCONT;
+        }
+        let signum = match deliverable.first_signum() {
+            Some(signum) => signum,
+            None => return,
+        };
+        let signal = SignalFlags::from_signum(signum).unwrap();
+        if signal.contains(SignalFlags::SIGCONT) {
+            process_inner.frozen = false;
+        }
+        let action = process_inner.signal_actions.table[signum];
+        if signal.intersects(SignalFlags::unmaskable()) || action.handler == SIG_DFL {
+            match signal.default_action() {
+                // stays pending and is picked up by `check_signals_error_of_current`
+                DefaultAction::Terminate => return,
+                DefaultAction::Stop => {
+                    process_inner.frozen = true;
+                    process_inner.stop_report = Some(signum);
+                    notify_parent(&process_inner);
+                }
+                DefaultAction::Ignore | DefaultAction::Continue => {}
+            }
+            process_inner.signals.remove(signal);
+        } else if action.handler == SIG_IGN {
+            process_inner.signals.remove(signal);
+        } else {
+            process_inner.signals.remove(signal);
+            drop(process_inner);
+            call_user_signal_handler(signum, action);
+            // one handler per return to user mode, the rest are delivered on sigreturn
+            return;
+        }
+    }
+}
+
+/// Push a [`SignalFrame`] on the user stack and redirect the current task to `action.handler`
+fn call_user_signal_handler(signum: usize, action: SignalAction) {
+    let task = current_task().unwrap();
+    let process = current_process();
+    let token = current_user_token();
+    let mut task_inner = task.inner_exclusive_access();
+    let trap_cx = task_inner.get_trap_cx();
+    let frame_va = trap_cx.x[2]
+        .checked_sub(size_of::<SignalFrame>())
+        .map(|sp| sp & !0xf)
+        .filter(|&va| user_range_valid(token, va, size_of::<SignalFrame>(), true));
+    let mut process_inner = process.inner_exclusive_access();
+    let frame_va = match frame_va {
+        Some(va) => va,
+        None => {
+            // the stack is unusable, so there is no way to run the handler
+            debug!("[kernel] bad signal frame below sp {:#x}", trap_cx.x[2]);
+            process_inner.signals |= SignalFlags::SIGKILL;
+            return;
+        }
+    };
+    let frame = SignalFrame {
+        trap_cx: *trap_cx,
+        mask: process_inner.signal_mask,
+        signum,
+        prev: task_inner.signal_frame.unwrap_or(0),
+    };
+    copy_to_user(token, frame_va, &frame);
+    let signal = SignalFlags::from_signum(signum).unwrap();
+    process_inner.signal_mask |= (action.mask | signal) - SignalFlags::unmaskable();
+    task_inner.signal_frame = Some(frame_va);
+    trap_cx.sepc = action.handler;
+    trap_cx.x[10] = signum;
+    trap_cx.x[2] = frame_va;
+}
+
+/// Restore the context saved by the innermost signal frame of the current task
+///
+/// Returns the restored value of `a0`, or `None` if no handler is running.
+pub fn restore_signal_frame() -> Option<usize> {
+    let task = current_task().unwrap();
+    let process = current_process();
+    let token = current_user_token();
+    let mut task_inner = task.inner_exclusive_access();
+    let frame_va = task_inner.signal_frame?;
+    if !user_range_valid(token, frame_va, size_of::<SignalFrame>(), false) {
+        return None;
+    }
+    let frame: SignalFrame = copy_from_user(token, frame_va);
+    let trap_cx = task_inner.get_trap_cx();
+    // sstatus and the kernel fields are never taken from user memory
+    trap_cx.x = frame.trap_cx.x;
+    trap_cx.sepc = frame.trap_cx.sepc;
+    task_inner.signal_frame = if frame.prev == 0 {
+        None
+    } else {
+        Some(frame.prev)
+    };
+    let mut process_inner = process.inner_exclusive_access();
+    process_inner.signal_mask = frame.mask - SignalFlags::unmaskable();
+    Some(trap_cx.x[10])
+}
+
+/// Exit code and message if the current process has been killed by a signal
+pub fn check_signals_error_of_current() -> Option<(i32, &'static str)> {
+    let process = current_process();
+    let process_inner = process.inner_exclusive_access();
+    process_inner
+        .fatal_signal()
+        .and_then(|signal| signal.check_error())
+}
+
+fn user_range_valid(token: usize, start: usize, len: usize, write: bool) -> bool {
+    let page_table = PageTable::from_token(token);
+    let end = match start.checked_add(len) {
+        Some(end) => VirtAddr::from(end).ceil(),
+        None => return false,
+    };
+    let mut vpn = VirtAddr::from(start).floor();
+    while vpn < end {
+        match page_table.translate(vpn) {
+            Some(pte) if pte.is_valid() && pte.is_user() && (!write || pte.writable()) => {}
+            _ => return false,
+        }
+        vpn.step();
+    }
+    true
+}
+
+fn copy_to_user<T: Copy>(token: usize, va: usize, value: &T) {
+    let src =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    let mut offset = 0;
+    for dst in translated_byte_buffer(token, va as *const u8, size_of::<T>()) {
+        dst.copy_from_slice(&src[offset..offset + dst.len()]);
+        offset += dst.len();
+    }
+}
+
+fn copy_from_user<T: Copy>(token: usize, va: usize) -> T {
+    let mut value = core::mem::MaybeUninit::<T>::uninit();
+    let dst =
+        unsafe { core::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, size_of::<T>()) };
+    let mut offset = 0;
+    for src in translated_byte_buffer(token, va as *const u8, size_of::<T>()) {
+        dst[offset..offset + src.len()].copy_from_slice(src);
+        offset += src.len();
+    }
+    unsafe { value.assume_init() }
+}
+
 lazy_static! {
     /// Creation of initial process
     ///
@@ -1,14 +1,22 @@
-use super::id::RecycleAllocator;
-use super::{add_task, pid_alloc, PidHandle, TaskControlBlock};
+use super::id::{trap_cx_bottom_from_tid, ustack_bottom_from_tid, RecycleAllocator};
+use super::manager::insert_into_pid2process;
+use super::{
+    add_task, current_task, interrupt_task, kill_other_threads, pid_alloc, DefaultAction,
+    PidHandle, SignalAction, SignalActions, SignalFlags, TaskControlBlock, SIG_DFL, SIG_IGN,
+};
+use crate::config::MAX_FD;
 use crate::fs::{File, Stdin, Stdout};
-use crate::mm::{translated_refmut, MemorySet, KERNEL_SPACE};
-use crate::sync::{Condvar, Mutex, Semaphore, UPSafeCell, Detector};
+use crate::mm::{translated_refmut, MemorySet, VirtAddr, KERNEL_SPACE};
+use crate::sync::{
+    forget_process, inherit_deadlock_policy, Barrier, Condvar, Detector, Mailbox, Mutex, RwLock,
+    Semaphore, UPRefMut, UPSafeCell,
+};
 use crate::trap::{trap_handler, TrapContext};
+use alloc::collections::{BTreeSet, VecDeque};
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 use alloc::vec::Vec;
-use core::cell::RefMut;
 
 pub struct ProcessControlBlock {
     // immutable
@@ -25,13 +33,35 @@ pub struct ProcessControlBlockInner {
     pub memory_set: MemorySet,
     pub parent: Option<Weak<ProcessControlBlock>>,
     pub children: Vec<Arc<ProcessControlBlock>>,
+    /// Wait status reported to the parent, see [`exit_status`](super::exit_status)
     pub exit_code: i32,
     pub fd_table: Vec<Option<Arc<dyn File + Send + Sync>>>,
+    /// Descriptors with `FD_CLOEXEC`, closed by exec
+    pub cloexec: BTreeSet<usize>,
     pub tasks: Vec<Option<Arc<TaskControlBlock>>>,
     pub task_res_allocator: RecycleAllocator,
     pub mutex_list: Vec<Option<Arc<dyn Mutex>>>,
     pub semaphore_list: Vec<Option<Arc<Semaphore>>>,
     pub condvar_list: Vec<Option<Arc<Condvar>>>,
+    pub rwlock_list: Vec<Option<Arc<RwLock>>>,
+    pub barrier_list: Vec<Option<Arc<Barrier>>>,
+    /// Messages sent to this process, kept across exec
+    pub mailbox: Arc<Mailbox>,
+    /// Signals sent to this process but not delivered yet
+    pub signals: SignalFlags,
+    /// Signals blocked from delivery
+    pub signal_mask: SignalFlags,
+    pub signal_actions: SignalActions,
+    /// Stopped by SIGSTOP-like signals until SIGCONT arrives
+    pub frozen: bool,
+    /// Threads blocked in `sys_waitpid` until a child exits
+    pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Process group, used for job control
+    pub pgid: usize,
+    /// Session, i.e. the pid of the session leader
+    pub sid: usize,
+    /// Signal that stopped the process, not yet reported by `sys_waitpid`
+    pub stop_report: Option<usize>,
 }
 
 impl ProcessControlBlockInner {
@@ -40,13 +70,20 @@ impl ProcessControlBlockInner {
         self.memory_set.token()
     }
 
-    pub fn alloc_fd(&mut self) -> usize {
-        if let Some(fd) = (0..self.fd_table.len()).find(|fd| self.fd_table[*fd].is_none()) {
-            fd
-        } else {
-            self.fd_table.push(None);
-            self.fd_table.len() - 1
+    /// Lowest free descriptor, or `None` if [`MAX_FD`] are open already
+    pub fn alloc_fd(&mut self) -> Option<usize> {
+        self.alloc_fd_from(0)
+    }
+
+    /// Lowest free descriptor not below `min`
+    pub fn alloc_fd_from(&mut self, min: usize) -> Option<usize> {
+        let fd = (min..MAX_FD).find(|&fd| self.fd_table.get(fd).map_or(true, Option::is_none))?;
+        if fd >= self.fd_table.len() {
+            self.fd_table.resize(fd + 1, None);
         }
+        // a new descriptor does not keep the flags of the closed one
+        self.cloexec.remove(&fd);
+        Some(fd)
     }
 
     pub fn alloc_tid(&mut self) -> usize {
@@ -57,17 +94,100 @@ impl ProcessControlBlockInner {
         self.task_res_allocator.dealloc(tid)
     }
 
-    pub fn thread_count(&self) -> usize {
-        self.tasks.len()
+    /// Tids of the threads which have not exited yet
+    pub fn live_tids(&self) -> Vec<usize> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, task)| {
+                task.as_ref()
+                    .map_or(false, |task| task.inner_exclusive_access().res.is_some())
+            })
+            .map(|(tid, _)| tid)
+            .collect()
+    }
+
+    /// Forget about every thread except `tid`, all the others having exited
+    pub fn forget_other_threads(&mut self, tid: usize) {
+        for (other_tid, slot) in self.tasks.iter_mut().enumerate() {
+            if other_tid != tid {
+                *slot = None;
+            }
+        }
     }
 
+    #[allow(unused)]
     pub fn get_task(&self, tid: usize) -> Arc<TaskControlBlock> {
         self.tasks[tid].as_ref().unwrap().clone()
     }
+
+    /// Wake up every thread of this process blocked in `sys_waitpid` or `sys_waittid`
+    ///
+    /// Woken threads re-check their condition, so this is always safe to call.
+    pub fn wakeup_waiters(&mut self) {
+        for task in self.wait_queue.drain(..) {
+            add_task(task);
+        }
+        for task in self.tasks.iter().flatten() {
+            for joiner in task.inner_exclusive_access().wait_queue.drain(..) {
+                add_task(joiner);
+            }
+        }
+    }
+
+    /// Make `signal` pending and wake up threads that may be waiting for it
+    ///
+    /// SIGCONT resumes a stopped process right away and cancels pending stop
+    /// signals, which in turn cancel a pending SIGCONT. A signal that kills the
+    /// process also ends every interruptible wait of its threads, so they
+    /// reach the kernel boundary and die there.
+    pub fn send_signal(&mut self, signal: SignalFlags) {
+        let stop_signals = SignalFlags::SIGSTOP
+            | SignalFlags::SIGTSTP
+            | SignalFlags::SIGTTIN
+            | SignalFlags::SIGTTOU;
+        if signal.contains(SignalFlags::SIGCONT) {
+            self.signals.remove(stop_signals);
+            self.frozen = false;
+            self.stop_report = None;
+        } else if signal.intersects(stop_signals) {
+            self.signals.remove(SignalFlags::SIGCONT);
+        }
+        self.signals |= signal;
+        // blocked waiters must notice if they have just been killed
+        self.wakeup_waiters();
+        if self.fatal_signal().is_some() {
+            for task in self.tasks.iter().flatten() {
+                interrupt_task(Arc::clone(task));
+            }
+        }
+    }
+
+    /// Pending signals that are not blocked
+    pub fn deliverable_signals(&self) -> SignalFlags {
+        self.signals - (self.signal_mask - SignalFlags::unmaskable())
+    }
+
+    /// A deliverable signal that terminates the process, if any
+    pub fn fatal_signal(&self) -> Option<SignalFlags> {
+        let mut pending = self.deliverable_signals();
+        while let Some(signum) = pending.first_signum() {
+            let signal = SignalFlags::from_signum(signum).unwrap();
+            let handler = self.signal_actions.table[signum].handler;
+            if signal.default_action() == DefaultAction::Terminate
+                && (handler == SIG_DFL || signal.contains(SignalFlags::SIGKILL))
+            {
+                return Some(signal);
+            }
+            pending.remove(signal);
+        }
+        None
+    }
 }
 
 impl ProcessControlBlock {
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, ProcessControlBlockInner> {
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn inner_exclusive_access(&self) -> UPRefMut<'_, ProcessControlBlockInner> {
         self.inner.exclusive_access()
     }
 
@@ -77,6 +197,7 @@ impl ProcessControlBlock {
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
         // allocate a pid
         let pid_handle = pid_alloc();
+        let pid = pid_handle.0;
         let process = Arc::new(Self {
             pid: pid_handle,
             inner: unsafe {
@@ -90,17 +211,30 @@ impl ProcessControlBlock {
                     exit_code: 0,
                     fd_table: vec![
                         // 0 -> stdin
-                        Some(Arc::new(Stdin)),
+                        Some(Arc::new(Stdin::new())),
                         // 1 -> stdout
                         Some(Arc::new(Stdout)),
                         // 2 -> stderr
                         Some(Arc::new(Stdout)),
                     ],
+                    cloexec: BTreeSet::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
+                    mailbox: Arc::new(Mailbox::new()),
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    frozen: false,
+                    wait_queue: VecDeque::new(),
+                    // the first process leads its own session
+                    pgid: pid,
+                    sid: pid,
+                    stop_report: None,
                 })
             },
         });
@@ -127,6 +261,7 @@ impl ProcessControlBlock {
         let mut process_inner = process.inner_exclusive_access();
         process_inner.tasks.push(Some(Arc::clone(&task)));
         drop(process_inner);
+        insert_into_pid2process(process.getpid(), Arc::clone(&process));
         // add main thread to scheduler
         add_task(task);
         process
@@ -134,21 +269,54 @@ impl ProcessControlBlock {
 
     // LAB5 HINT: How to initialize deadlock data structures?
     /// Load a new elf to replace the original application address space and start execution
-    /// Only support processes with a single thread.
+    ///
+    /// All other threads are terminated, and the calling thread continues as
+    /// the main thread (tid 0) of the new image. Nothing happens if it is
+    /// killed meanwhile by another thread exiting or calling exec, it exits
+    /// on its way back to user mode instead.
     pub fn exec(self: &Arc<Self>, elf_data: &[u8], args: Vec<String>) {
-        assert_eq!(self.inner_exclusive_access().thread_count(), 1);
+        let task = current_task().unwrap();
+        // the other threads go while their stacks are still in the old image
+        if !kill_other_threads() {
+            return;
+        }
+        // nothing waited on or held by the old image outlives it
+        forget_process(self.getpid(), false);
         // memory_set with elf program headers/trampoline/trap context/user stack
         let (memory_set, ustack_base, entry_point) = MemorySet::from_elf(elf_data);
         let new_token = memory_set.token();
         // substitute memory_set
-        self.inner_exclusive_access().memory_set = memory_set;
+        let mut inner = self.inner_exclusive_access();
+        inner.memory_set = memory_set;
+        // handlers live in the old image, so caught signals go back to default
+        for action in inner.signal_actions.table.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+        // so do the synchronization primitives
+        inner.mutex_list.clear();
+        inner.semaphore_list.clear();
+        inner.condvar_list.clear();
+        inner.rwlock_list.clear();
+        inner.barrier_list.clear();
+        inner.detector = Detector::new();
+        for fd in core::mem::take(&mut inner.cloexec) {
+            inner.fd_table[fd] = None;
+        }
+        // the caller becomes the main thread, its old tid went away with the old image
+        inner.task_res_allocator = RecycleAllocator::new();
+        let main_tid = inner.alloc_tid();
+        inner.tasks = vec![Some(Arc::clone(&task))];
+        drop(inner);
         // then we alloc user resource for main thread again
         // since memory_set has been changed
-        let task = self.inner_exclusive_access().get_task(0);
         let mut task_inner = task.inner_exclusive_access();
+        task_inner.res.as_mut().unwrap().tid = main_tid;
         task_inner.res.as_mut().unwrap().ustack_base = ustack_base;
         task_inner.res.as_mut().unwrap().alloc_user_res();
         task_inner.trap_cx_ppn = task_inner.res.as_mut().unwrap().trap_cx_ppn();
+        task_inner.signal_frame = None;
         // push arguments on user stack
         let mut user_sp = task_inner.res.as_mut().unwrap().ustack_top();
         user_sp -= (args.len() + 1) * core::mem::size_of::<usize>();
@@ -189,12 +357,33 @@ impl ProcessControlBlock {
 
     // LAB5 HINT: How to initialize deadlock data structures?
     /// Fork from parent to child
-    /// Only support processes with a single thread.
+    ///
+    /// Only the calling thread is duplicated, it becomes the main thread of the child.
     pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+        let current_task = current_task().unwrap();
+        let current_res = current_task.inner_exclusive_access();
+        let current_tid = current_res.res.as_ref().unwrap().tid;
+        let ustack_base = current_res.res.as_ref().unwrap().ustack_base();
+        drop(current_res);
         let mut parent = self.inner_exclusive_access();
-        assert_eq!(parent.thread_count(), 1);
         // clone parent's memory_set completely including trampoline/ustacks/trap_cxs
-        let memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        let mut memory_set = MemorySet::from_existed_user(&parent.memory_set);
+        // then drop what belongs to the threads that are not duplicated
+        for (tid, task) in parent.tasks.iter().enumerate() {
+            let alive = task
+                .as_ref()
+                .map_or(false, |task| task.inner_exclusive_access().res.is_some());
+            if tid == 0 || !alive {
+                continue;
+            }
+            if tid != current_tid {
+                let ustack_bottom: VirtAddr = ustack_bottom_from_tid(ustack_base, tid).into();
+                memory_set.remove_area_with_start_vpn(ustack_bottom.into());
+            }
+            // the child thread has tid 0 and uses its trap context page
+            let trap_cx_bottom: VirtAddr = trap_cx_bottom_from_tid(tid).into();
+            memory_set.remove_area_with_start_vpn(trap_cx_bottom.into());
+        }
         // alloc a pid
         let pid = pid_alloc();
         // copy fd table
@@ -219,26 +408,34 @@ impl ProcessControlBlock {
                     children: Vec::new(),
                     exit_code: 0,
                     fd_table: new_fd_table,
+                    cloexec: parent.cloexec.clone(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
+                    mailbox: Arc::new(Mailbox::new()),
+                    // pending signals are not inherited, handlers and mask are
+                    signals: SignalFlags::empty(),
+                    signal_mask: parent.signal_mask,
+                    signal_actions: parent.signal_actions.clone(),
+                    frozen: false,
+                    wait_queue: VecDeque::new(),
+                    pgid: parent.pgid,
+                    sid: parent.sid,
+                    stop_report: None,
                 })
             },
         });
         // add child
         parent.children.push(Arc::clone(&child));
+        drop(parent);
         // create main thread of child process
         let task = Arc::new(TaskControlBlock::new(
             Arc::clone(&child),
-            parent
-                .get_task(0)
-                .inner_exclusive_access()
-                .res
-                .as_ref()
-                .unwrap()
-                .ustack_base(),
+            ustack_base,
             // here we do not allocate trap_cx or ustack again
             // but mention that we allocate a new kernel_stack here
             false,
@@ -246,12 +443,24 @@ impl ProcessControlBlock {
         // attach task to child process
         let mut child_inner = child.inner_exclusive_access();
         child_inner.tasks.push(Some(Arc::clone(&task)));
+        if current_tid != 0 {
+            // the child keeps running on the stack of `current_tid`, so that tid is never reused
+            while child_inner.alloc_tid() < current_tid {}
+            for tid in 1..current_tid {
+                child_inner.dealloc_tid(tid);
+            }
+        }
         drop(child_inner);
         // modify kernel_stack_top in trap_cx of this thread
         let task_inner = task.inner_exclusive_access();
         let trap_cx = task_inner.get_trap_cx();
+        if current_tid != 0 {
+            *trap_cx = *current_task.inner_exclusive_access().get_trap_cx();
+        }
         trap_cx.kernel_sp = task.kernel_stack.get_top();
         drop(task_inner);
+        insert_into_pid2process(child.getpid(), Arc::clone(&child));
+        inherit_deadlock_policy(self.getpid(), child.getpid());
         // add this thread to scheduler
         add_task(task);
         child
@@ -263,8 +472,10 @@ impl ProcessControlBlock {
 
     pub fn kernel_process() -> Arc<Self> {
         let memory_set = MemorySet::kernel_copy();
+        let pid_handle = super::pid_alloc();
+        let pid = pid_handle.0;
         let process = Arc::new(ProcessControlBlock {
-            pid: super::pid_alloc(),
+            pid: pid_handle,
             inner: unsafe {
                 UPSafeCell::new(ProcessControlBlockInner {
                     detection: false,
@@ -275,11 +486,23 @@ impl ProcessControlBlock {
                     children: Vec::new(),
                     exit_code: 0,
                     fd_table: Vec::new(),
+                    cloexec: BTreeSet::new(),
                     tasks: Vec::new(),
                     task_res_allocator: RecycleAllocator::new(),
                     mutex_list: Vec::new(),
                     semaphore_list: Vec::new(),
                     condvar_list: Vec::new(),
+                    rwlock_list: Vec::new(),
+                    barrier_list: Vec::new(),
+                    mailbox: Arc::new(Mailbox::new()),
+                    signals: SignalFlags::empty(),
+                    signal_mask: SignalFlags::empty(),
+                    signal_actions: SignalActions::default(),
+                    frozen: false,
+                    wait_queue: VecDeque::new(),
+                    pgid: pid,
+                    sid: pid,
+                    stop_report: None,
                 })
             },
         });
@@ -8,7 +8,9 @@ use super::__switch;
 use super::process::ProcessControlBlock;
 use super::{fetch_task, TaskStatus};
 use super::{TaskContext, TaskControlBlock};
+use crate::drivers::irq_handler;
 use crate::sync::UPSafeCell;
+use crate::timer::check_timer;
 use crate::trap::TrapContext;
 use alloc::sync::Arc;
 use lazy_static::*;
@@ -19,6 +21,8 @@ pub struct Processor {
     current: Option<Arc<TaskControlBlock>>,
     /// The basic control flow of each core, helping to select and switch process
     idle_task_cx: TaskContext,
+    /// An exited task still running on its kernel stack, dropped once we have switched away
+    exited: Option<Arc<TaskControlBlock>>,
 }
 
 impl Processor {
@@ -26,6 +30,7 @@ impl Processor {
         Self {
             current: None,
             idle_task_cx: TaskContext::zero_init(),
+            exited: None,
         }
     }
     fn get_idle_task_cx_ptr(&mut self) -> *mut TaskContext {
@@ -50,15 +55,33 @@ lazy_static! {
 /// and switch the process through __switch
 pub fn run_tasks() {
     loop {
+        // with every task blocked, the PLIC is only heard from here: the disk
+        // completing a request, or a key typed for a reader of the console,
+        // which would otherwise sleep for ever in `tty_read`
+        irq_handler();
+        // timer interrupts are only taken in user mode, which is never
+        // entered while every task is blocked, e.g. in waitpid
+        check_timer();
+        #[cfg(debug_assertions)]
+        crate::sync::lockdep::switch_to(0);
         let mut processor = PROCESSOR.exclusive_access();
+        // we are on the idle stack now, so its kernel stack can go
+        processor.exited = None;
         if let Some(task) = fetch_task() {
             // println!("task get!");
             let idle_task_cx_ptr = processor.get_idle_task_cx_ptr();
             // access coming task TCB exclusively
             let mut task_inner = task.inner_exclusive_access();
+            if task_inner.task_status == TaskStatus::Exited {
+                // woken up once more by a stale wait queue entry just before it exited
+                continue;
+            }
+            task_inner.stride += task_inner.pass();
             let next_task_cx_ptr = &task_inner.task_cx as *const TaskContext;
             task_inner.task_status = TaskStatus::Running;
             drop(task_inner);
+            #[cfg(debug_assertions)]
+            crate::sync::lockdep::switch_to(Arc::as_ptr(&task) as usize);
             // release coming task TCB manually
             processor.current = Some(task);
             // release processor manually
@@ -66,12 +89,17 @@ pub fn run_tasks() {
             unsafe {
                 __switch(idle_task_cx_ptr, next_task_cx_ptr);
             }
-        } else {
-            println!("no tasks available in run_tasks");
         }
     }
 }
 
+/// Keep `task` alive until the next switch to the idle control flow
+///
+/// Used by an exiting task to free its own kernel stack.
+pub fn release_after_switch(task: Arc<TaskControlBlock>) {
+    PROCESSOR.exclusive_access().exited = Some(task);
+}
+
 /// Get current task through take, leaving a None in its place
 pub fn take_current_task() -> Option<Arc<TaskControlBlock>> {
     PROCESSOR.exclusive_access().take_current()
@@ -0,0 +1,158 @@
+//! Signal numbers and per-signal default behaviour
+
+use crate::trap::TrapContext;
+
+pub const MAX_SIG: usize = 31;
+
+bitflags! {
+    /// A set of signals, bit `n` standing for signal number `n`
+    pub struct SignalFlags: u32 {
+        const SIGDEF = 1; // Default signal handling
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+/// What the kernel does with a signal whose handler is `SIG_DFL`
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum DefaultAction {
+    Terminate,
+    Ignore,
+    Stop,
+    Continue,
+}
+
+impl SignalFlags {
+    /// Signal set of a single signal number, `None` if out of range
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        if signum == 0 || signum > MAX_SIG {
+            None
+        } else {
+            Self::from_bits(1 << signum)
+        }
+    }
+
+    /// Signals that can be neither caught, ignored nor blocked
+    pub fn unmaskable() -> Self {
+        Self::SIGKILL | Self::SIGSTOP
+    }
+
+    /// Lowest signal number in the set
+    pub fn first_signum(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.bits.trailing_zeros() as usize)
+        }
+    }
+
+    pub fn default_action(&self) -> DefaultAction {
+        if self.intersects(Self::SIGCHLD | Self::SIGURG | Self::SIGWINCH) {
+            DefaultAction::Ignore
+        } else if self.intersects(Self::SIGSTOP | Self::SIGTSTP | Self::SIGTTIN | Self::SIGTTOU) {
+            DefaultAction::Stop
+        } else if self.contains(Self::SIGCONT) {
+            DefaultAction::Continue
+        } else {
+            DefaultAction::Terminate
+        }
+    }
+
+    /// Exit code and message of a process terminated by this signal
+    pub fn check_error(&self) -> Option<(i32, &'static str)> {
+        let signum = self.first_signum()?;
+        let msg = match *self {
+            Self::SIGINT => "Killed, SIGINT=2",
+            Self::SIGILL => "Illegal Instruction, SIGILL=4",
+            Self::SIGABRT => "Aborted, SIGABRT=6",
+            Self::SIGFPE => "Erroneous Arithmetic Operation, SIGFPE=8",
+            Self::SIGKILL => "Killed, SIGKILL=9",
+            Self::SIGSEGV => "Segmentation Fault, SIGSEGV=11",
+            Self::SIGPIPE => "Broken Pipe, SIGPIPE=13",
+            Self::SIGTERM => "Terminated, SIGTERM=15",
+            _ => "Terminated by signal",
+        };
+        Some((-(signum as i32), msg))
+    }
+}
+
+/// Handler value asking for the default action
+pub const SIG_DFL: usize = 0;
+/// Handler value asking for the signal to be discarded
+pub const SIG_IGN: usize = 1;
+
+/// Action for a signal, as registered through `sys_sigaction`
+#[derive(Clone, Copy)]
+#[repr(C, align(16))]
+pub struct SignalAction {
+    pub handler: usize,
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
+    }
+}
+
+/// Handler table of a process, indexed by signal number
+#[derive(Clone)]
+pub struct SignalActions {
+    pub table: [SignalAction; MAX_SIG + 1],
+}
+
+impl Default for SignalActions {
+    fn default() -> Self {
+        Self {
+            table: [SignalAction::default(); MAX_SIG + 1],
+        }
+    }
+}
+
+/// Saved state pushed on the user stack before entering a signal handler
+///
+/// `sys_sigreturn` reads it back to resume the interrupted context.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct SignalFrame {
+    /// Interrupted user context
+    pub trap_cx: TrapContext,
+    /// Blocked signals before the handler was entered
+    pub mask: SignalFlags,
+    /// Signal being handled
+    pub signum: usize,
+    /// Frame of the handler this one interrupted, 0 if none
+    pub prev: usize,
+}
@@ -2,10 +2,19 @@
 
 use super::id::TaskUserRes;
 use super::{kstack_alloc, KernelStack, ProcessControlBlock, TaskContext};
+use crate::config::BIG_STRIDE;
+use crate::sync::MutexBlocking;
 use crate::trap::TrapContext;
-use crate::{mm::PhysPageNum, sync::UPSafeCell};
+use crate::{
+    mm::PhysPageNum,
+    sync::{UPRefMut, UPSafeCell},
+};
+use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
-use core::cell::RefMut;
+use alloc::vec::Vec;
+
+/// Priority of a thread until it calls `sys_set_priority`
+pub const DEFAULT_PRIORITY: isize = 16;
 
 /// Task control block structure
 ///
@@ -34,6 +43,32 @@ pub struct TaskControlBlockInner {
     pub exit_code: Option<i32>,
     /// Tid and ustack will be deallocated when this goes None
     pub res: Option<TaskUserRes>,
+    /// User address of the innermost signal frame while running a handler
+    pub signal_frame: Option<usize>,
+    /// Threads blocked in `sys_waittid` until this one exits
+    pub wait_queue: VecDeque<Arc<TaskControlBlock>>,
+    /// Reclaimed as soon as it exits instead of by `sys_waittid`
+    pub detached: bool,
+    /// Set by `sys_thread_cancel`, the thread exits at its next kernel entry
+    pub cancelled: bool,
+    /// Set when another thread exits the process or calls exec, the thread
+    /// leaves any interruptible wait and exits at the kernel boundary
+    pub killed: bool,
+    /// Priority set by `sys_set_priority`
+    pub priority: isize,
+    /// Priority it is scheduled with, raised above `priority` by the
+    /// waiters of the mutexes it holds
+    pub effective_priority: isize,
+    /// Stride scheduling: the thread with the lowest stride runs next
+    pub stride: usize,
+    /// Mutex it is blocked on, to pass priority boosts along chains of owners
+    pub blocked_on: Option<Weak<MutexBlocking>>,
+    /// Mutexes it holds
+    pub held_mutexes: Vec<Weak<MutexBlocking>>,
+    /// Blocked with a timer pending in `TIMERS`, see [`crate::timer::wakeup_task`]
+    pub timed_wait: bool,
+    /// Blocked in a wait that [`crate::task::interrupt_task`] may end early
+    pub interruptible: bool,
 }
 
 /// Simple access to its internal fields
@@ -51,6 +86,11 @@ impl TaskControlBlockInner {
     fn get_status(&self) -> TaskStatus {
         self.task_status
     }
+
+    /// How far `stride` advances each time the thread is scheduled
+    pub fn pass(&self) -> usize {
+        (BIG_STRIDE / self.effective_priority as usize).max(1)
+    }
 }
 
 impl TaskControlBlock {
@@ -71,15 +111,28 @@ impl TaskControlBlock {
                     res: Some(res),
                     trap_cx_ppn,
                     task_cx: TaskContext::goto_trap_return(kstack_top),
-                    task_status: TaskStatus::Ready,
+                    task_status: TaskStatus::UnInit,
                     exit_code: None,
+                    signal_frame: None,
+                    wait_queue: VecDeque::new(),
+                    detached: false,
+                    cancelled: false,
+                    killed: false,
+                    priority: DEFAULT_PRIORITY,
+                    effective_priority: DEFAULT_PRIORITY,
+                    stride: 0,
+                    blocked_on: None,
+                    held_mutexes: Vec::new(),
+                    timed_wait: false,
+                    interruptible: false,
                 })
             },
         }
     }
 
     /// Get the mutex to get the RefMut TaskControlBlockInner
-    pub fn inner_exclusive_access(&self) -> RefMut<'_, TaskControlBlockInner> {
+    #[cfg_attr(debug_assertions, track_caller)]
+    pub fn inner_exclusive_access(&self) -> UPRefMut<'_, TaskControlBlockInner> {
         let inner = self.inner.exclusive_access();
         // if self.process.upgrade().unwrap().pid.0 > 1 {
         //     if let Some(res) = inner.res.as_ref() {
@@ -122,8 +175,20 @@ impl TaskControlBlock {
                     res: None,
                     trap_cx_ppn: context_ppn,
                     task_cx: context,
-                    task_status: TaskStatus::Ready,
+                    task_status: TaskStatus::UnInit,
                     exit_code: None,
+                    signal_frame: None,
+                    wait_queue: VecDeque::new(),
+                    detached: false,
+                    cancelled: false,
+                    killed: false,
+                    priority: DEFAULT_PRIORITY,
+                    effective_priority: DEFAULT_PRIORITY,
+                    stride: 0,
+                    blocked_on: None,
+                    held_mutexes: Vec::new(),
+                    timed_wait: false,
+                    interruptible: false,
                 })
             },
         }
@@ -133,8 +198,12 @@ impl TaskControlBlock {
 #[derive(Copy, Clone, PartialEq)]
 /// task status: UnInit, Ready, Running, Exited
 pub enum TaskStatus {
+    /// Never scheduled yet
     UnInit,
+    /// In the ready queue
     Ready,
     Running,
     Blocking,
+    /// Never scheduled again, even if it is still queued somewhere
+    Exited,
 }
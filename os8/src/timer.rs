@@ -3,9 +3,12 @@
 use crate::config::CLOCK_FREQ;
 use crate::sbi::set_timer;
 use crate::sync::UPSafeCell;
-use crate::task::{add_task, TaskControlBlock};
+use crate::task::{
+    add_task, block_current_interruptible, current_interrupted, current_task, TaskControlBlock,
+};
 use alloc::collections::BinaryHeap;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::cmp::Ordering;
 use lazy_static::*;
 use riscv::register::time;
@@ -69,6 +72,48 @@ pub fn add_timer(expire_ms: usize, task: Arc<TaskControlBlock>) {
     timers.push(TimerCondVar { expire_ms, task });
 }
 
+/// Remove the pending timer of `task`, returning whether there was one
+pub fn cancel_timer(task: &Arc<TaskControlBlock>) -> bool {
+    let mut timers = TIMERS.exclusive_access();
+    let pending = timers.len();
+    let kept: Vec<TimerCondVar> = core::mem::take(&mut *timers)
+        .into_iter()
+        .filter(|timer| !Arc::ptr_eq(&timer.task, task))
+        .collect();
+    *timers = BinaryHeap::from(kept);
+    timers.len() != pending
+}
+
+/// Block the current task until woken up, or until `expire_ms` if given
+///
+/// Wakers must use [`wakeup_task`]. The caller finds out which came first
+/// by looking whether the task is still in the wait queue it put it in, it
+/// may also have been interrupted as in [`block_current_interruptible`].
+pub fn block_current_until(expire_ms: Option<usize>) {
+    if current_interrupted() {
+        return;
+    }
+    if let Some(expire_ms) = expire_ms {
+        let task = current_task().unwrap();
+        task.inner_exclusive_access().timed_wait = true;
+        add_timer(expire_ms, task);
+    }
+    block_current_interruptible();
+    if expire_ms.is_some() {
+        current_task().unwrap().inner_exclusive_access().timed_wait = false;
+    }
+}
+
+/// Make `task`, just taken off a wait queue, ready to run
+///
+/// Its timer is cancelled, unless it already fired and made it ready.
+pub fn wakeup_task(task: Arc<TaskControlBlock>) {
+    let timed_wait = task.inner_exclusive_access().timed_wait;
+    if !timed_wait || cancel_timer(&task) {
+        add_task(task);
+    }
+}
+
 pub fn check_timer() {
     let current_ms = get_time_ms();
     let mut timers = TIMERS.exclusive_access();
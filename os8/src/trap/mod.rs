@@ -15,10 +15,13 @@
 mod context;
 
 use crate::config::TRAMPOLINE;
+use crate::drivers::irq_handler;
 use crate::syscall::syscall;
 use crate::task::{
-    current_trap_cx, current_trap_cx_user_va, current_user_token, exit_current_and_run_next,
-    suspend_current_and_run_next,
+    check_signals_error_of_current, current_cancelled, current_force_signal, current_trap_cx,
+    current_trap_cx_user_va, current_user_token, exit_current_and_run_next,
+    exit_group_and_run_next, handle_signals, suspend_current_and_run_next, SignalFlags,
+    THREAD_CANCELED,
 };
 use crate::timer::{check_timer, set_next_trigger};
 use riscv::register::{
@@ -54,6 +57,9 @@ pub fn enable_timer_interrupt() {
 #[no_mangle]
 pub fn trap_handler() -> ! {
     set_kernel_trap_entry();
+    if current_cancelled() {
+        exit_current_and_run_next(THREAD_CANCELED);
+    }
     let scause = scause::read();
     let stval = stval::read();
     match scause.cause() {
@@ -62,7 +68,10 @@ pub fn trap_handler() -> ! {
             let mut cx = current_trap_cx();
             cx.sepc += 4;
             // get system call return value
-            let result = syscall(cx.x[17], [cx.x[10], cx.x[11], cx.x[12], cx.x[13]]);
+            let result = syscall(
+                cx.x[17],
+                [cx.x[10], cx.x[11], cx.x[12], cx.x[13], cx.x[14], cx.x[15]],
+            );
             // cx is changed during sys_exec, so we have to call it again
             cx = current_trap_cx();
             cx.x[10] = result as usize;
@@ -73,25 +82,29 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::InstructionPageFault)
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
-            println!(
-                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}, core dumped.",
+            debug!(
+                "[kernel] {:?} in application, bad addr = {:#x}, bad instruction = {:#x}",
                 scause.cause(),
                 stval,
                 current_trap_cx().sepc,
             );
-            // page fault exit code
-            exit_current_and_run_next(-2);
+            current_force_signal(SignalFlags::SIGSEGV);
         }
         Trap::Exception(Exception::IllegalInstruction) => {
-            println!("[kernel] IllegalInstruction in application, core dumped.");
-            // illegal instruction exit code
-            exit_current_and_run_next(-3);
+            debug!(
+                "[kernel] IllegalInstruction in application, bad instruction = {:#x}",
+                current_trap_cx().sepc
+            );
+            current_force_signal(SignalFlags::SIGILL);
         }
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             set_next_trigger();
             check_timer();
             suspend_current_and_run_next();
         }
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            irq_handler();
+        }
         _ => {
             panic!(
                 "Unsupported trap {:?}, stval = {:#x}!",
@@ -100,6 +113,16 @@ pub fn trap_handler() -> ! {
             );
         }
     }
+    // killed while in the kernel, by another thread exiting or calling exec
+    if current_cancelled() {
+        exit_current_and_run_next(THREAD_CANCELED);
+    }
+    // deliver pending signals on the way back to user mode
+    handle_signals();
+    if let Some((errno, msg)) = check_signals_error_of_current() {
+        println!("[kernel] {}", msg);
+        exit_group_and_run_next(errno);
+    }
     trap_return();
 }
 
@@ -27,7 +27,7 @@ static TESTS: &[&str] = &[
 ];
 static STEST: &str = "ch5_stride\0";
 
-use user_lib::{spawn, waitpid};
+use user_lib::{spawn, waitpid, wexitstatus};
 
 /// 辅助测例，运行所有其他测例。
 
@@ -44,7 +44,9 @@ pub fn main() -> i32 {
         assert_eq!(pid[i], wait_pid);
         println!(
             "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
-            test, pid[i], xstate
+            test,
+            pid[i],
+            wexitstatus(xstate)
         );
     }
     println!("Usertests: Running {}", STEST);
@@ -54,7 +56,9 @@ pub fn main() -> i32 {
     assert_eq!(spid, wait_pid);
     println!(
         "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
-        STEST, spid, xstate
+        STEST,
+        spid,
+        wexitstatus(xstate)
     );
     println!("ch5 Usertests passed!");
     0
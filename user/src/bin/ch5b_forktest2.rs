@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exit, fork, get_time, getpid, sleep, wait};
+use user_lib::{exit, fork, get_time, getpid, sleep, wait, wexitstatus};
 
 static NUM: usize = 30;
 
@@ -26,7 +26,7 @@ pub fn main() -> i32 {
     let mut exit_code: i32 = 0;
     for _ in 0..NUM {
         assert!(wait(&mut exit_code) > 0);
-        assert_eq!(exit_code, 0);
+        assert_eq!(wexitstatus(exit_code), 0);
     }
     assert!(wait(&mut exit_code) < 0);
     println!("forktest2 test passed!");
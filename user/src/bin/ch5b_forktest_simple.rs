@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{fork, getpid, wait};
+use user_lib::{fork, getpid, wait, wexitstatus};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -21,8 +21,12 @@ pub fn main() -> i32 {
         let mut exit_code: i32 = 0;
         println!("ready waiting on parent process!");
         assert_eq!(pid, wait(&mut exit_code));
-        assert_eq!(exit_code, 100);
-        println!("child process pid = {}, exit code = {}", pid, exit_code);
+        assert_eq!(wexitstatus(exit_code), 100);
+        println!(
+            "child process pid = {}, exit code = {}",
+            pid,
+            wexitstatus(exit_code)
+        );
         0
     }
 }
@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{exec, fork, wait, wexitstatus, yield_};
 
 #[no_mangle]
 fn main() -> i32 {
@@ -20,7 +20,8 @@ fn main() -> i32 {
             }
             println!(
                 "[initproc] Released a zombie process, pid={}, exit_code={}",
-                pid, exit_code,
+                pid,
+                wexitstatus(exit_code),
             );
         }
     }
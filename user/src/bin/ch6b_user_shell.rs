@@ -13,7 +13,7 @@ const BS: u8 = 0x08u8;
 
 use alloc::string::String;
 use user_lib::console::getchar;
-use user_lib::{exec, flush, fork, waitpid};
+use user_lib::{exec, flush, fork, waitpid, wexitstatus};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -40,7 +40,11 @@ pub fn main() -> i32 {
                         let mut exit_code: i32 = 0;
                         let exit_pid = waitpid(pid as usize, &mut exit_code);
                         assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        println!(
+                            "Shell: Process {} exited with code {}",
+                            pid,
+                            wexitstatus(exit_code)
+                        );
                     }
                     line.clear();
                 }
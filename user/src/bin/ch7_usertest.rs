@@ -7,7 +7,7 @@ extern crate user_lib;
 static TESTS: &[&str] = &[
 ];
 
-use user_lib::{spawn, waitpid};
+use user_lib::{spawn, waitpid, wexitstatus};
 
 /// 辅助测例，运行所有其他测例。
 
@@ -21,7 +21,9 @@ pub fn main() -> i32 {
         assert_eq!(pid, wait_pid);
         println!(
             "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
-            test, pid, xstate
+            test,
+            pid,
+            wexitstatus(xstate)
         );
     }
     println!("ch7 Usertests passed!");
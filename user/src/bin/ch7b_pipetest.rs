@@ -4,7 +4,7 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{close, fork, pipe, read, wait, write};
+use user_lib::{close, fork, pipe, read, wait, wexitstatus, write};
 
 static STR: &str = "Hello, world!";
 
@@ -37,7 +37,7 @@ pub fn main() -> i32 {
         close(pipe_fd[1]);
         let mut child_exit_code: i32 = 0;
         wait(&mut child_exit_code);
-        assert_eq!(child_exit_code, 0);
+        assert_eq!(wexitstatus(child_exit_code), 0);
         println!("pipetest passed!");
         0
     }
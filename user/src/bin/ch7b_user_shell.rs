@@ -14,7 +14,7 @@ const BS: u8 = 0x08u8;
 use alloc::string::String;
 use alloc::vec::Vec;
 use user_lib::console::getchar;
-use user_lib::{close, dup, exec, flush, fork, open, waitpid, OpenFlags};
+use user_lib::{close, dup, exec, flush, fork, open, waitpid, wexitstatus, OpenFlags};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -104,7 +104,11 @@ pub fn main() -> i32 {
                         let mut exit_code: i32 = 0;
                         let exit_pid = waitpid(pid as usize, &mut exit_code);
                         assert_eq!(pid, exit_pid);
-                        println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        println!(
+                            "Shell: Process {} exited with code {}",
+                            pid,
+                            wexitstatus(exit_code)
+                        );
                     }
                     line.clear();
                 }
@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+#![allow(clippy::println_empty_string)]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait, enable_deadlock_detect, exit,
+    mutex_blocking_create, mutex_lock, mutex_unlock, semaphore_create, semaphore_down,
+    semaphore_up, sleep, thread_create, waittid,
+};
+
+// mutex 0 and semaphore 0: a cycle through a mutex and a semaphore
+// mutex 1, mutex 2 and condvar 0: a cycle through a condvar wait
+
+// 理想结果：两次检测到死锁，子线程返回值均为 0
+
+const MUTEX: usize = 0;
+const SEM: usize = 0;
+const MUTEX_HELD: usize = 1;
+const MUTEX_WAIT: usize = 2;
+const CONDVAR: usize = 0;
+
+fn hold_mutex_wait_sem() {
+    assert_eq!(mutex_lock(MUTEX), 0);
+    assert_eq!(semaphore_down(SEM), 0);
+    semaphore_up(SEM);
+    mutex_unlock(MUTEX);
+    exit(0);
+}
+
+fn hold_mutex_wait_condvar() {
+    assert_eq!(mutex_lock(MUTEX_HELD), 0);
+    assert_eq!(mutex_lock(MUTEX_WAIT), 0);
+    assert_eq!(condvar_wait(CONDVAR, MUTEX_WAIT), 0);
+    mutex_unlock(MUTEX_WAIT);
+    mutex_unlock(MUTEX_HELD);
+    exit(0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    enable_deadlock_detect(true);
+    assert_eq!(mutex_blocking_create() as usize, MUTEX);
+    assert_eq!(semaphore_create(1) as usize, SEM);
+    assert_eq!(semaphore_down(SEM), 0);
+    let tid = thread_create(hold_mutex_wait_sem as usize, 0) as usize;
+    sleep(100);
+    // the thread holds the mutex and waits for the unit we hold
+    assert_eq!(mutex_lock(MUTEX), -0xdead);
+    semaphore_up(SEM);
+    assert_eq!(waittid(tid), 0);
+
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_HELD);
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_WAIT);
+    assert_eq!(condvar_create() as usize, CONDVAR);
+    let tid = thread_create(hold_mutex_wait_condvar as usize, 0) as usize;
+    sleep(100);
+    // nobody could signal the thread once we block on the mutex it keeps
+    assert_eq!(mutex_lock(MUTEX_HELD), -0xdead);
+    condvar_signal(CONDVAR);
+    assert_eq!(waittid(tid), 0);
+    println!("deadlock test mixed OK!");
+    0
+}
@@ -4,6 +4,8 @@
 #[macro_use]
 extern crate user_lib;
 
+/// Run all at once, which leaves out `ch8b_tty`: it switches the console
+/// they all share to raw mode for a while, so it is to be run on its own
 const TESTS: &[&str] = &[
     "ch2b_hello_world\0",
     "ch2b_power_3\0",
@@ -20,18 +22,41 @@ const TESTS: &[&str] = &[
     "ch8_deadlock_mutex1\0",
     "ch8_deadlock_sem1\0",
     "ch8_deadlock_sem2\0",
+    "ch8_deadlock_mixed\0",
+    "ch8b_condvar_broadcast\0",
+    "ch8b_deadlock_processes\0",
+    "ch8b_devfs\0",
+    "ch8b_disk_io\0",
+    "ch8b_dup_cloexec\0",
+    "ch8b_exit_blocked\0",
+    "ch8b_fifo\0",
+    "ch8b_futex\0",
+    "ch8b_mailbox\0",
     "ch8b_mpsc_sem\0",
+    "ch8b_mutex_priority\0",
     "ch8b_phil_din_mutex\0",
+    "ch8b_pipe_blocking\0",
+    "ch8b_poll\0",
+    "ch8b_race_adder_futex\0",
     "ch8b_race_adder_mutex_spin\0",
+    "ch8b_rwlock_barrier\0",
+    "ch8b_shm\0",
+    "ch8b_sig_tests\0",
     "ch8b_sync_sem\0",
     "ch8b_test_condvar\0",
     "ch8b_threads\0",
     "ch8b_threads_arg\0",
+    "ch8b_threads_detach\0",
+    "ch8b_threads_fork_exec\0",
+    "ch8b_timed_waits\0",
+    "ch8b_tmpfs\0",
+    "ch8b_vfs\0",
+    "ch8b_wait_tests\0",
 ];
 
 const TEST_NUM: usize = TESTS.len();
 
-use user_lib::{exec, fork, waitpid};
+use user_lib::{exec, fork, waitpid, wexitstatus, wifsignaled, wtermsig};
 
 #[no_mangle]
 pub fn main() -> i32 {
@@ -50,10 +75,21 @@ pub fn main() -> i32 {
     for (i, &test) in TESTS.iter().enumerate() {
         let wait_pid = waitpid(pids[i] as usize, &mut xstate);
         assert_eq!(pids[i], wait_pid);
-        println!(
-            "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
-            test, pids[i], xstate
-        );
+        if wifsignaled(xstate) {
+            println!(
+                "\x1b[31mUsertests: Test {} in Process {} killed by signal {}\x1b[0m",
+                test,
+                pids[i],
+                wtermsig(xstate)
+            );
+        } else {
+            println!(
+                "\x1b[32mUsertests: Test {} in Process {} exited with code {}\x1b[0m",
+                test,
+                pids[i],
+                wexitstatus(xstate)
+            );
+        }
     }
     println!("ch8 Usertests passed!");
     0
@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    condvar_broadcast, condvar_create, condvar_destroy, condvar_wait, exit, mutex_blocking_create,
+    mutex_destroy, mutex_lock, mutex_unlock, semaphore_create, semaphore_destroy, semaphore_down,
+    semaphore_up, sleep, thread_create, waittid, EBUSY,
+};
+
+const THREAD_COUNT: usize = 5;
+const MUTEX_ID: usize = 0;
+const CONDVAR_ID: usize = 0;
+
+static mut READY: bool = false;
+static mut WOKEN: usize = 0;
+
+fn waiter() -> ! {
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    // Mesa semantics, the condition has to be checked again once woken up
+    while unsafe { !READY } {
+        assert_eq!(condvar_wait(CONDVAR_ID, MUTEX_ID), 0);
+    }
+    unsafe { WOKEN += 1 };
+    assert_eq!(mutex_unlock(MUTEX_ID), 0);
+    exit(0)
+}
+
+fn semaphore_waiter(sem_id: usize) -> ! {
+    assert_eq!(semaphore_down(sem_id), 0);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_ID);
+    assert_eq!(condvar_create() as usize, CONDVAR_ID);
+    let mut tids = [0; THREAD_COUNT];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(waiter as usize, 0) as usize;
+    }
+    sleep(50);
+    assert_eq!(condvar_destroy(CONDVAR_ID), EBUSY);
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    assert_eq!(mutex_destroy(MUTEX_ID), EBUSY);
+    // a broadcast before the condition holds only makes them wait again
+    condvar_broadcast(CONDVAR_ID);
+    unsafe { READY = true };
+    condvar_broadcast(CONDVAR_ID);
+    assert_eq!(mutex_unlock(MUTEX_ID), 0);
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(unsafe { WOKEN }, THREAD_COUNT);
+
+    assert_eq!(condvar_destroy(CONDVAR_ID), 0);
+    assert_eq!(mutex_destroy(MUTEX_ID), 0);
+    // freed slots are handed out again
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_ID);
+    assert_eq!(condvar_create() as usize, CONDVAR_ID);
+
+    let sem_id = semaphore_create(0) as usize;
+    let tid = thread_create(semaphore_waiter as usize, sem_id) as usize;
+    sleep(20);
+    assert_eq!(semaphore_destroy(sem_id), EBUSY);
+    semaphore_up(sem_id);
+    assert_eq!(waittid(tid), 0);
+    assert_eq!(semaphore_destroy(sem_id), 0);
+    println!("condvar broadcast test passed!");
+    0
+}
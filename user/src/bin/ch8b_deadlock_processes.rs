@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::{
+    close, exit, fork, futex_wait, futex_wake, mutex_blocking_create, mutex_lock, pipe, read,
+    set_deadlock_policy, shmat, shmget, shmrm, sleep, waitpid, wexitstatus, wifexited, wifsignaled,
+    wtermsig, DEADLOCK_IGNORE, DEADLOCK_KILL_VICTIM, DEADLOCK_RETURN_ERROR, EDEADLK, IPC_CREAT,
+    IPC_PRIVATE, SIGKILL,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+/// The parent waits for a child reading a pipe only the parent can write to
+fn pipe_waitpid_cycle() {
+    let mut pipe_fd = [0usize; 2];
+    pipe(&mut pipe_fd);
+    let pid = fork();
+    if pid == 0 {
+        close(pipe_fd[1]);
+        let mut buffer = [0u8; 8];
+        // returns once the parent closes its write end
+        assert_eq!(read(pipe_fd[0], &mut buffer), 0);
+        exit(0);
+    }
+    close(pipe_fd[0]);
+    sleep(100);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), EDEADLK);
+    close(pipe_fd[1]);
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 0);
+}
+
+/// Parent and child each wait on a futex in shared memory that only the
+/// other one would wake
+fn futex_shm_cycle() {
+    let id = shmget(IPC_PRIVATE, PAGE_SIZE, IPC_CREAT);
+    assert!(id >= 0);
+    let addr = shmat(id as usize, 0, 0);
+    assert!(addr > 0);
+    let words = unsafe { &*(addr as *const [AtomicU32; 2]) };
+    let pid = fork();
+    if pid == 0 {
+        // returns once the parent sets the word
+        assert_eq!(futex_wait(&words[1], 0, 0), 0);
+        exit(0);
+    }
+    sleep(100);
+    assert_eq!(futex_wait(&words[0], 0, 0), EDEADLK);
+    words[1].store(1, Ordering::Release);
+    futex_wake(&words[1], 1);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 0);
+    assert_eq!(shmrm(id as usize), 0);
+}
+
+/// A child locking a mutex twice is killed
+fn kill_victim() {
+    let pid = fork();
+    if pid == 0 {
+        let mutex_id = mutex_blocking_create() as usize;
+        assert_eq!(mutex_lock(mutex_id), 0);
+        mutex_lock(mutex_id);
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifsignaled(exit_code));
+    assert_eq!(wtermsig(exit_code), SIGKILL);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(set_deadlock_policy(DEADLOCK_RETURN_ERROR), 0);
+    pipe_waitpid_cycle();
+    futex_shm_cycle();
+    assert_eq!(set_deadlock_policy(DEADLOCK_KILL_VICTIM), 0);
+    kill_victim();
+    assert_eq!(set_deadlock_policy(DEADLOCK_IGNORE), 0);
+    println!("deadlock processes test passed!");
+    0
+}
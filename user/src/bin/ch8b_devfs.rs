@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup2, exit, fork, open, read, tcgetattr, unlink, waitpid, wexitstatus, wifexited, write,
+    OpenFlags, Termios, EPERM,
+};
+
+/// Magic number at the start of the superblock of easy-fs
+const EFS_MAGIC: u32 = 0x3b800001;
+
+fn open_dev(path: &str, flags: OpenFlags) -> usize {
+    let fd = open(path, flags);
+    assert!(fd >= 0);
+    fd as usize
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let null = open_dev("/dev/null\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert_eq!(write(null, b"gone"), 4);
+    close(null);
+    let null = open_dev("/dev/null\0", OpenFlags::RDONLY);
+    let mut buf = [0xffu8; 64];
+    assert_eq!(read(null, &mut buf), 0);
+    close(null);
+
+    let zero = open_dev("/dev/zero\0", OpenFlags::RDWR);
+    assert_eq!(read(zero, &mut buf), 64);
+    assert!(buf.iter().all(|&byte| byte == 0));
+    assert_eq!(write(zero, b"gone"), 4);
+    close(zero);
+
+    let urandom = open_dev("/dev/urandom\0", OpenFlags::RDONLY);
+    let mut other = [0u8; 64];
+    assert_eq!(read(urandom, &mut buf), 64);
+    assert_eq!(read(urandom, &mut other), 64);
+    assert_ne!(buf, other);
+    assert!(buf.iter().any(|&byte| byte != 0));
+    close(urandom);
+
+    // the console the standard streams are on
+    let tty = open_dev("/dev/tty\0", OpenFlags::WRONLY);
+    let mut termios = Termios::default();
+    assert_eq!(tcgetattr(tty, &mut termios), 0);
+    assert_eq!(write(tty, b"devfs: hello from /dev/tty\n"), 27);
+    close(tty);
+
+    // the disk, which starts with the superblock of easy-fs
+    let vda = open_dev("/dev/vda\0", OpenFlags::RDONLY);
+    let mut block = [0u8; 512];
+    assert_eq!(read(vda, &mut block), 512);
+    let magic = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+    assert_eq!(magic, EFS_MAGIC);
+    close(vda);
+
+    // the nodes are fixed
+    assert!(open("/dev/new\0", OpenFlags::CREATE | OpenFlags::WRONLY) < 0);
+    assert_eq!(unlink("/dev/null\0"), EPERM);
+
+    // like `> /dev/null` in the shell
+    let pid = fork();
+    if pid == 0 {
+        let null = open_dev("/dev/null\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+        dup2(null, 1);
+        close(null);
+        println!("devfs: this line is not to be seen");
+        exit(7);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code) && wexitstatus(exit_code) == 7);
+    println!("devfs test passed!");
+    0
+}
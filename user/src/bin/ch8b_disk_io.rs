@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, open, read, sleep, thread_create, waitpid, wexitstatus, wifexited, write,
+    OpenFlags,
+};
+
+const WRITERS: usize = 3;
+/// More blocks than the block cache holds, so that they go to the disk
+const FILE_BLOCKS: usize = 48;
+
+fn file_name(i: usize) -> &'static str {
+    ["disk_io_0\0", "disk_io_1\0", "disk_io_2\0"][i]
+}
+
+fn fill(i: usize, block: usize, buf: &mut [u8]) {
+    for (j, byte) in buf.iter_mut().enumerate() {
+        *byte = (i * 31 + block * 7 + j) as u8;
+    }
+}
+
+/// Write a file and read it back while the other writers do the same
+fn writer(i: usize) -> i32 {
+    let mut buf = [0u8; 512];
+    let fd = open(file_name(i), OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    for block in 0..FILE_BLOCKS {
+        fill(i, block, &mut buf);
+        assert_eq!(write(fd as usize, &buf), 512);
+    }
+    close(fd as usize);
+    let fd = open(file_name(i), OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let mut expected = [0u8; 512];
+    for block in 0..FILE_BLOCKS {
+        fill(i, block, &mut expected);
+        assert_eq!(read(fd as usize, &mut buf), 512);
+        assert_eq!(buf, expected);
+    }
+    assert_eq!(read(fd as usize, &mut buf), 0);
+    close(fd as usize);
+    0
+}
+
+fn reader_thread() -> ! {
+    let mut buf = [0u8; 512];
+    loop {
+        let fd = open(file_name(0), OpenFlags::RDONLY) as usize;
+        while read(fd, &mut buf) > 0 {}
+        close(fd);
+    }
+}
+
+/// The process exits while another of its threads is busy with the disk
+fn exit_while_reading() -> i32 {
+    thread_create(reader_thread as usize, 0);
+    sleep(20);
+    exit(7)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut pids = [0usize; WRITERS];
+    for (i, pid) in pids.iter_mut().enumerate() {
+        let ret = fork();
+        if ret == 0 {
+            exit(writer(i));
+        }
+        *pid = ret as usize;
+    }
+    // computing meanwhile
+    let mut sum = 0usize;
+    for i in 0..100000usize {
+        sum = sum.wrapping_add(i * i);
+    }
+    assert_ne!(sum, 0);
+    for pid in pids {
+        let mut exit_code = 0;
+        assert_eq!(waitpid(pid, &mut exit_code), pid as isize);
+        assert!(wifexited(exit_code));
+        assert_eq!(wexitstatus(exit_code), 0);
+    }
+
+    let pid = fork();
+    if pid == 0 {
+        exit(exit_while_reading());
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert_eq!(wexitstatus(exit_code), 7);
+    println!("disk io test passed!");
+    0
+}
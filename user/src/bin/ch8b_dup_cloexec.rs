@@ -0,0 +1,127 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, dup, dup2, dup3, exec, exit, fcntl, fork, open, pipe, read, waitpid, wexitstatus,
+    wifexited, write, OpenFlags, EBADF, EINVAL, EMFILE, FD_CLOEXEC, F_DUPFD, F_DUPFD_CLOEXEC,
+    F_GETFD, F_SETFD,
+};
+
+/// Kept open across exec
+const KEPT_FD: usize = 10;
+/// Closed by exec
+const CLOSED_FD: usize = 11;
+
+/// Run by the exec'd image: only the descriptor without `FD_CLOEXEC` is left
+fn probe() -> i32 {
+    assert_eq!(fcntl(CLOSED_FD, F_GETFD, 0), EBADF);
+    assert_eq!(fcntl(KEPT_FD, F_GETFD, 0), 0);
+    assert_eq!(write(KEPT_FD, b"kept"), 4);
+    0
+}
+
+fn redirect() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    // a far away descriptor, then over an open one
+    assert_eq!(dup2(fds[1], 20), 20);
+    assert_eq!(write(20, b"a"), 1);
+    assert_eq!(dup2(fds[0], 20), 20);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(20, &mut buf), 1);
+    assert_eq!(buf[0], b'a');
+    assert_eq!(dup2(20, 20), 20);
+    close(20);
+    assert_eq!(dup2(20, 20), EBADF);
+    assert_eq!(dup2(20, 21), EBADF);
+    assert_eq!(dup3(fds[0], fds[0], OpenFlags::empty()), EINVAL);
+    assert_eq!(dup3(fds[0], 21, OpenFlags::RDWR), EINVAL);
+    assert_eq!(dup2(fds[0], 1 << 20), EBADF);
+
+    assert_eq!(fcntl(fds[0], F_DUPFD, 30), 30);
+    assert_eq!(fcntl(fds[0], F_DUPFD, 30), 31);
+    assert_eq!(fcntl(30, F_GETFD, 0), 0);
+    assert_eq!(fcntl(fds[0], F_DUPFD_CLOEXEC, 30), 32);
+    assert_eq!(fcntl(32, F_GETFD, 0), FD_CLOEXEC as isize);
+    // a reused descriptor starts without the flag
+    close(32);
+    assert_eq!(dup2(fds[0], 32), 32);
+    assert_eq!(fcntl(32, F_GETFD, 0), 0);
+    assert_eq!(dup3(fds[0], 32, OpenFlags::CLOEXEC), 32);
+    assert_eq!(fcntl(32, F_GETFD, 0), FD_CLOEXEC as isize);
+    for fd in [30, 31, 32, fds[0], fds[1]] {
+        close(fd);
+    }
+}
+
+fn fd_limit() {
+    let mut count = 0;
+    let last = loop {
+        let fd = dup(0);
+        if fd < 0 {
+            assert_eq!(fd, EMFILE);
+            break count;
+        }
+        count += 1;
+    };
+    assert!(last > 0);
+    for fd in 3..3 + last {
+        close(fd);
+    }
+    assert_eq!(dup(0), 3);
+    close(3);
+}
+
+fn close_on_exec() {
+    let fd = open(
+        "cloexec\0",
+        OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::CLOEXEC,
+    );
+    assert!(fd > 0);
+    assert_eq!(fcntl(fd as usize, F_GETFD, 0), FD_CLOEXEC as isize);
+    close(fd as usize);
+
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(dup2(fds[1], KEPT_FD), KEPT_FD as isize);
+    assert_eq!(dup2(fds[1], CLOSED_FD), CLOSED_FD as isize);
+    assert_eq!(fcntl(CLOSED_FD, F_SETFD, FD_CLOEXEC), 0);
+    close(fds[1]);
+    let pid = fork();
+    if pid == 0 {
+        // fork keeps the flags
+        assert_eq!(fcntl(CLOSED_FD, F_GETFD, 0), FD_CLOEXEC as isize);
+        close(fds[0]);
+        let args = [
+            "ch8b_dup_cloexec\0".as_ptr(),
+            "probe\0".as_ptr(),
+            core::ptr::null::<u8>(),
+        ];
+        exec("ch8b_dup_cloexec\0", &args);
+        exit(-1);
+    }
+    close(KEPT_FD);
+    close(CLOSED_FD);
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fds[0], &mut buf[..4]), 4);
+    assert_eq!(&buf[..4], b"kept");
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code) && wexitstatus(exit_code) == 0);
+    close(fds[0]);
+}
+
+#[no_mangle]
+pub fn main(argc: usize, argv: &[&str]) -> i32 {
+    if argc > 1 && argv[1] == "probe" {
+        return probe();
+    }
+    redirect();
+    fd_limit();
+    close_on_exec();
+    println!("dup and cloexec test passed!");
+    0
+}
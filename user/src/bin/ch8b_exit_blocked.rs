@@ -0,0 +1,83 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, exit_group, fork, mutex_blocking_create, mutex_lock, pipe, read, semaphore_create,
+    semaphore_down, sleep, thread_create, waitpid, waittid, wexitstatus, wifexited, write,
+};
+
+const PAGE_SIZE: usize = 4096;
+
+fn mutex_waiter(mutex_id: usize) -> ! {
+    mutex_lock(mutex_id);
+    panic!("took a mutex the main thread holds");
+}
+
+fn semaphore_waiter(sem_id: usize) -> ! {
+    semaphore_down(sem_id);
+    panic!("took a unit nobody gave");
+}
+
+/// Blocks on the pipe, which is full already
+fn pipe_writer(fd: usize) -> ! {
+    write(fd, b"x");
+    panic!("wrote to a full pipe nobody reads");
+}
+
+fn group_exiter() -> ! {
+    sleep(50);
+    exit_group(9)
+}
+
+/// Have a child process exit while its threads are blocked in the kernel on
+/// a mutex, a semaphore and the write end of a pipe, from the main thread or
+/// another one
+///
+/// The pipe must be closed once the child is reaped, so nothing the blocked
+/// threads held in the kernel may be left behind.
+fn exit_with_blocked_threads(from_main: bool) {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[0]);
+        let data = [0u8; PAGE_SIZE];
+        assert_eq!(write(fds[1], &data), PAGE_SIZE as isize);
+        let mutex_id = mutex_blocking_create() as usize;
+        assert_eq!(mutex_lock(mutex_id), 0);
+        let sem_id = semaphore_create(0) as usize;
+        thread_create(mutex_waiter as usize, mutex_id);
+        thread_create(semaphore_waiter as usize, sem_id);
+        let writer = thread_create(pipe_writer as usize, fds[1]);
+        close(fds[1]);
+        if from_main {
+            sleep(50);
+            exit(7);
+        }
+        thread_create(group_exiter as usize, 0);
+        // blocked in turn until the whole process goes
+        waittid(writer as usize);
+        panic!("the writer thread exited on its own");
+    }
+    close(fds[1]);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), if from_main { 7 } else { 9 });
+    let mut buf = [0u8; PAGE_SIZE];
+    assert_eq!(read(fds[0], &mut buf), PAGE_SIZE as isize);
+    // end of file, rather than waiting for ever
+    assert_eq!(read(fds[0], &mut buf), 0);
+    close(fds[0]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    exit_with_blocked_threads(true);
+    exit_with_blocked_threads(false);
+    println!("exit with blocked threads test passed!");
+    0
+}
@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl, fork, get_time, mkfifo, open, read, sleep, waitpid, wexitstatus, wifexited,
+    write, OpenFlags, EEXIST, ENXIO, F_GETFL,
+};
+
+const FIFO: &str = "ch8b_fifo_pipe\0";
+const MESSAGE: &[u8] = b"hello through a named pipe";
+
+fn writer() -> ! {
+    // the reader is waiting in open
+    sleep(50);
+    let fd = open(FIFO, OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, MESSAGE), MESSAGE.len() as isize);
+    close(fd as usize);
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // it survives in the file system if the tests ran before
+    let ret = mkfifo(FIFO);
+    assert!(ret == 0 || ret == EEXIST);
+    assert_eq!(mkfifo(FIFO), EEXIST);
+
+    // without waiting, there is no reader to write to
+    assert_eq!(open(FIFO, OpenFlags::WRONLY | OpenFlags::NONBLOCK), ENXIO);
+    // while a reader does not need a writer, and sees an end of file
+    let fd = open(FIFO, OpenFlags::RDONLY | OpenFlags::NONBLOCK);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 64];
+    assert_eq!(read(fd as usize, &mut buf), 0);
+    let wfd = open(FIFO, OpenFlags::WRONLY | OpenFlags::NONBLOCK);
+    assert!(wfd >= 0);
+    assert_eq!(write(wfd as usize, b"x"), 1);
+    assert_eq!(read(fd as usize, &mut buf), 1);
+    // O_NONBLOCK belongs to each open, not to the end they share
+    let nonblock = OpenFlags::NONBLOCK.bits() as isize;
+    let rfd = open(FIFO, OpenFlags::RDONLY);
+    assert!(rfd >= 0);
+    assert_eq!(fcntl(rfd as usize, F_GETFL, 0) & nonblock, 0);
+    assert_eq!(fcntl(fd as usize, F_GETFL, 0) & nonblock, nonblock);
+    close(rfd as usize);
+    close(wfd as usize);
+    close(fd as usize);
+
+    // opened for both, it is a writer of its own and waits for nobody
+    let fd = open(FIFO, OpenFlags::RDWR);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"rw"), 2);
+    assert_eq!(read(fd as usize, &mut buf), 2);
+    assert_eq!(&buf[..2], b"rw");
+    close(fd as usize);
+
+    let pid = fork();
+    if pid == 0 {
+        writer();
+    }
+    let start = get_time();
+    // waits for the writer to show up
+    let fd = open(FIFO, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    assert!(get_time() - start >= 50);
+    let mut total = 0;
+    loop {
+        let len = read(fd as usize, &mut buf[total..]);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        total += len as usize;
+    }
+    assert_eq!(&buf[..total], MESSAGE);
+    close(fd as usize);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code) && wexitstatus(exit_code) == 0);
+    println!("fifo test passed!");
+    0
+}
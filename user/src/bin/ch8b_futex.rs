@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+extern crate alloc;
+
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU32, Ordering};
+use user_lib::{exit, futex_wait, futex_wake, get_time, sleep, thread_create, waittid};
+use user_lib::{Condvar, Mutex, RwLock, EAGAIN, ETIMEDOUT};
+
+const THREAD_COUNT: usize = 4;
+
+static WORD: AtomicU32 = AtomicU32::new(0);
+
+fn word_waiter() -> ! {
+    while WORD.load(Ordering::Acquire) == 0 {
+        futex_wait(&WORD, 0, 0);
+    }
+    exit(0)
+}
+
+/// WAIT checks the word, times out, and WAKE reports who it woke up
+fn futex_basics() {
+    assert_eq!(futex_wait(&WORD, 1, 0), EAGAIN);
+    let start = get_time();
+    assert_eq!(futex_wait(&WORD, 0, 50), ETIMEDOUT);
+    assert!(get_time() - start >= 50);
+    assert_eq!(futex_wake(&WORD, 1), 0);
+
+    let tid = thread_create(word_waiter as usize, 0) as usize;
+    sleep(20);
+    WORD.store(1, Ordering::Release);
+    assert_eq!(futex_wake(&WORD, 1), 1);
+    assert_eq!(waittid(tid), 0);
+}
+
+/// Items passed from a producer to consumers through a queue
+static QUEUE: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+static NOT_EMPTY: Condvar = Condvar::new();
+static CONSUMED: Mutex<usize> = Mutex::new(0);
+const ITEMS: usize = 64;
+/// Tells a consumer to stop
+const DONE: usize = usize::MAX;
+
+fn consumer() -> ! {
+    loop {
+        let mut queue = QUEUE.lock();
+        while queue.is_empty() {
+            queue = NOT_EMPTY.wait(queue);
+        }
+        let item = queue.remove(0);
+        drop(queue);
+        if item == DONE {
+            exit(0)
+        }
+        *CONSUMED.lock() += item;
+    }
+}
+
+fn condvar_queue() {
+    let (guard, timed_out) = NOT_EMPTY.wait_timeout(QUEUE.lock(), 20);
+    assert!(timed_out && guard.is_empty());
+    drop(guard);
+
+    let tids: Vec<usize> = (0..THREAD_COUNT)
+        .map(|_| thread_create(consumer as usize, 0) as usize)
+        .collect();
+    for item in 1..=ITEMS {
+        QUEUE.lock().push(item);
+        NOT_EMPTY.notify_one();
+    }
+    // let the consumers drain the queue and all go to sleep
+    sleep(50);
+    {
+        let mut queue = QUEUE.lock();
+        for _ in 0..THREAD_COUNT {
+            queue.push(DONE);
+        }
+        NOT_EMPTY.notify_all();
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(*CONSUMED.lock(), ITEMS * (ITEMS + 1) / 2);
+}
+
+/// Writers keep both halves equal, readers must never see them differ
+static PAIR: RwLock<(usize, usize)> = RwLock::new((0, 0));
+const ROUNDS: usize = 200;
+
+fn reader() -> ! {
+    for _ in 0..ROUNDS {
+        let pair = PAIR.read();
+        assert_eq!(pair.0, pair.1);
+    }
+    exit(0)
+}
+
+fn writer() -> ! {
+    for _ in 0..ROUNDS {
+        let mut pair = PAIR.write();
+        pair.0 += 1;
+        // give readers a chance to look in the middle
+        for _ in 0..100 {
+            core::hint::spin_loop();
+        }
+        pair.1 += 1;
+    }
+    exit(0)
+}
+
+fn rwlock_pair() {
+    let mut tids = Vec::new();
+    for i in 0..THREAD_COUNT {
+        let entry = if i % 2 == 0 { writer } else { reader };
+        tids.push(thread_create(entry as usize, 0) as usize);
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    let pair = PAIR.read();
+    assert_eq!(
+        *pair,
+        (ROUNDS * THREAD_COUNT / 2, ROUNDS * THREAD_COUNT / 2)
+    );
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    futex_basics();
+    condvar_queue();
+    rwlock_pair();
+    println!("futex test passed!");
+    0
+}
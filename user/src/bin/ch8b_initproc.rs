@@ -4,12 +4,12 @@
 #[macro_use]
 extern crate user_lib;
 
-use user_lib::{exec, fork, wait, yield_};
+use user_lib::{exec, fork, wait, wexitstatus, yield_};
 
 #[no_mangle]
 fn main() -> i32 {
     if fork() == 0 {
-        exec("ch7b_user_shell\0", &[core::ptr::null::<u8>()]);
+        exec("ch8b_user_shell\0", &[core::ptr::null::<u8>()]);
     } else {
         loop {
             let mut exit_code: i32 = 0;
@@ -20,7 +20,8 @@ fn main() -> i32 {
             }
             println!(
                 "[initproc] Released a zombie process, pid={}, exit_code={}",
-                pid, exit_code,
+                pid,
+                wexitstatus(exit_code),
             );
         }
     }
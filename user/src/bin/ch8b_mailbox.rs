@@ -0,0 +1,94 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getpid, mail_read, mail_read_wait, mail_write, mail_write_wait, mailfd,
+    poll, read, sleep, waitpid, wexitstatus, wifexited, PollFd, EAGAIN, ESRCH, MAIL_MAX_LEN,
+    MAIL_SLOTS, POLLIN,
+};
+
+/// Messages the child sends back, more than fit in a mailbox at once
+const REPLIES: usize = MAIL_SLOTS + 4;
+
+fn child(parent: usize) -> ! {
+    let mut buf = [0u8; 16];
+    // blocks until the parent writes
+    assert_eq!(mail_read_wait(&mut buf), 5);
+    assert_eq!(&buf[..5], b"hello");
+    for i in 0..REPLIES {
+        // blocks while the parent's mailbox is full
+        assert_eq!(mail_write_wait(parent, &[i as u8]), 1);
+    }
+    exit(0)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let pid = getpid() as usize;
+    let mut buf = [0u8; MAIL_MAX_LEN + 8];
+
+    // an empty buffer only checks for a message
+    assert_eq!(mail_read(&mut []), EAGAIN);
+    assert_eq!(mail_read(&mut buf), EAGAIN);
+    assert_eq!(mail_write(pid, b"to myself"), 9);
+    assert_eq!(mail_read(&mut []), 0);
+    assert_eq!(mail_read(&mut buf), 9);
+    assert_eq!(&buf[..9], b"to myself");
+
+    // messages are bounded in number and in length
+    for i in 0..MAIL_SLOTS {
+        assert_eq!(mail_write(pid, &[i as u8; 2]), 2);
+    }
+    assert_eq!(mail_write(pid, b"one too many"), EAGAIN);
+    assert_eq!(mail_write(pid, &[]), EAGAIN);
+    for i in 0..MAIL_SLOTS {
+        // a short buffer gets the beginning of the message
+        assert_eq!(mail_read(&mut buf[..1]), 1);
+        assert_eq!(buf[0], i as u8);
+    }
+    assert_eq!(mail_write(pid, &[]), 0);
+    assert_eq!(
+        mail_write(pid, &[7; MAIL_MAX_LEN + 8]),
+        MAIL_MAX_LEN as isize
+    );
+    assert_eq!(mail_read(&mut buf), MAIL_MAX_LEN as isize);
+
+    // the mailbox as a file, to wait for it along with other files
+    let fd = mailfd(true);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut pollfds = [PollFd::new(fd, POLLIN)];
+    assert_eq!(poll(&mut pollfds, Some(0)), 0);
+    assert_eq!(read(fd, &mut buf), EAGAIN);
+    assert_eq!(mail_write(pid, b"polled"), 6);
+    assert_eq!(poll(&mut pollfds, Some(0)), 1);
+    assert_eq!(pollfds[0].revents, POLLIN);
+    assert_eq!(read(fd, &mut buf), 6);
+    assert_eq!(&buf[..6], b"polled");
+
+    let child_pid = fork();
+    if child_pid == 0 {
+        child(pid);
+    }
+    sleep(50);
+    assert_eq!(mail_write(child_pid as usize, b"hello"), 5);
+    // woken up by the first reply
+    assert_eq!(poll(&mut pollfds, None), 1);
+    close(fd);
+    // let the child fill our mailbox and block on it
+    sleep(100);
+    for i in 0..REPLIES {
+        assert_eq!(mail_read_wait(&mut buf), 1);
+        assert_eq!(buf[0], i as u8);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child_pid as usize, &mut exit_code), child_pid);
+    assert!(wifexited(exit_code) && wexitstatus(exit_code) == 0);
+    assert_eq!(mail_write(child_pid as usize, b"gone"), ESRCH);
+
+    println!("mailbox test passed!");
+    0
+}
@@ -0,0 +1,163 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use user_lib::{
+    exit, get_time, mutex_blocking_create, mutex_lock, mutex_timedlock, mutex_unlock, set_priority,
+    sleep, thread_create, waittid, ETIMEDOUT,
+};
+
+const MUTEX_ID: usize = 0;
+/// Priorities of the waiters, in the order they block on the mutex
+const PRIORITIES: [isize; 4] = [4, 32, 8, 32];
+/// Order they must get the mutex in: by priority, then first come first served
+const EXPECTED: [usize; 4] = [1, 3, 2, 0];
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+static mut ORDER: [usize; 4] = [0; 4];
+
+fn waiter(idx: usize) -> ! {
+    assert_eq!(set_priority(PRIORITIES[idx]), PRIORITIES[idx]);
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    unsafe {
+        ORDER[NEXT.fetch_add(1, Ordering::Relaxed)] = idx;
+    }
+    mutex_unlock(MUTEX_ID);
+    exit(0)
+}
+
+/// Priorities of the holders, of a spinner preempting them, and of the
+/// waiters raising them above it
+const LOW: isize = 2;
+const MEDIUM: isize = 16;
+const HIGH: isize = 128;
+/// How long a holder races the spinner for, many time slices
+const RACE_MS: isize = 200;
+
+static STOP: AtomicBool = AtomicBool::new(false);
+static SPUN: AtomicUsize = AtomicUsize::new(0);
+
+fn spinner() -> ! {
+    assert_eq!(set_priority(MEDIUM), MEDIUM);
+    while !STOP.load(Ordering::Relaxed) {
+        SPUN.fetch_add(1, Ordering::Relaxed);
+    }
+    exit(0)
+}
+
+/// Spin alongside the spinner for a while, returning how many rounds each did
+fn race_spinner() -> (usize, usize) {
+    let counter = AtomicUsize::new(0);
+    let spun = SPUN.load(Ordering::Relaxed);
+    let start = get_time();
+    loop {
+        let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        if count % 1024 == 0 && get_time() - start >= RACE_MS {
+            return (count, SPUN.load(Ordering::Relaxed) - spun);
+        }
+    }
+}
+
+/// Runs with the priority of a waiter, then with its own once it unlocks
+fn boosted_holder(mutex_id: usize) -> ! {
+    assert_eq!(set_priority(LOW), LOW);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    // the waiters block meanwhile
+    sleep(50);
+    let (count, spun) = race_spinner();
+    assert!(count > spun);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    let (count, spun) = race_spinner();
+    assert!(count < spun);
+    exit(0)
+}
+
+/// Holds the mutex while its waiter gives up, and must lose the priority
+/// that waiter lent it
+fn abandoned_holder(mutex_id: usize) -> ! {
+    assert_eq!(set_priority(LOW), LOW);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    // the waiter times out meanwhile
+    sleep(100);
+    let (count, spun) = race_spinner();
+    assert!(count < spun);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    exit(0)
+}
+
+/// Takes the first mutex, then blocks on the second one
+fn chained(ids: usize) -> ! {
+    let (first, second) = (ids >> 16, ids & 0xffff);
+    assert_eq!(set_priority(LOW), LOW);
+    assert_eq!(mutex_lock(first), 0);
+    assert_eq!(mutex_lock(second), 0);
+    assert_eq!(mutex_unlock(second), 0);
+    assert_eq!(mutex_unlock(first), 0);
+    exit(0)
+}
+
+fn high_waiter(mutex_id: usize) -> ! {
+    assert_eq!(set_priority(HIGH), HIGH);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    exit(0)
+}
+
+fn impatient_waiter(mutex_id: usize) -> ! {
+    assert_eq!(set_priority(HIGH), HIGH);
+    assert_eq!(mutex_timedlock(mutex_id, 50), ETIMEDOUT);
+    exit(0)
+}
+
+/// Have `holder` take a mutex and race a spinner of medium priority while
+/// `waiter` of high priority waits for it, directly or through a thread of
+/// low priority holding another mutex
+fn inheritance(holder: fn(usize) -> !, waiter: fn(usize) -> !, chain: bool) {
+    STOP.store(false, Ordering::Relaxed);
+    let held = mutex_blocking_create() as usize;
+    let holder = thread_create(holder as usize, held) as usize;
+    sleep(10);
+    let mut threads = [0; 3];
+    let awaited = if chain {
+        let first = mutex_blocking_create() as usize;
+        threads[2] = thread_create(chained as usize, first << 16 | held) as usize;
+        sleep(10);
+        first
+    } else {
+        held
+    };
+    threads[0] = thread_create(waiter as usize, awaited) as usize;
+    threads[1] = thread_create(spinner as usize, 0) as usize;
+    assert_eq!(waittid(holder), 0);
+    STOP.store(true, Ordering::Relaxed);
+    for tid in threads {
+        if tid != 0 {
+            assert_eq!(waittid(tid), 0);
+        }
+    }
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    assert_eq!(mutex_blocking_create() as usize, MUTEX_ID);
+    assert_eq!(mutex_lock(MUTEX_ID), 0);
+    let mut tids = [0; PRIORITIES.len()];
+    for (idx, tid) in tids.iter_mut().enumerate() {
+        *tid = thread_create(waiter as usize, idx) as usize;
+        // let it block before the next one comes
+        sleep(20);
+    }
+    mutex_unlock(MUTEX_ID);
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(unsafe { ORDER }, EXPECTED);
+    inheritance(boosted_holder, high_waiter, false);
+    inheritance(boosted_holder, high_waiter, true);
+    inheritance(abandoned_holder, impatient_waiter, true);
+    println!("mutex priority test passed!");
+    0
+}
@@ -0,0 +1,113 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl, fork, pipe, read, sigaction, waitpid, wexitstatus, wifexited, wifsignaled,
+    write, wtermsig, SignalAction, SignalFlags, EBUSY, EPERM, EPIPE, F_GETPIPE_SZ, F_SETPIPE_SZ,
+    SIGPIPE, SIG_IGN,
+};
+
+const PAGE_SIZE: usize = 4096;
+/// Several times what the pipe holds, so that the writer has to wait
+const LENGTH: usize = 5 * PAGE_SIZE;
+
+/// Too large for the user stack
+static mut DATA: [u8; LENGTH] = [0; LENGTH];
+
+fn byte(i: usize) -> u8 {
+    (i * 7 % 251) as u8
+}
+
+fn capacity() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(fcntl(fds[0], F_GETPIPE_SZ, 0), PAGE_SIZE as isize);
+    assert_eq!(
+        fcntl(fds[1], F_SETPIPE_SZ, PAGE_SIZE + 1),
+        2 * PAGE_SIZE as isize
+    );
+    // a full pipe takes a write without waiting
+    let data = unsafe { &mut DATA[..2 * PAGE_SIZE] };
+    assert_eq!(write(fds[1], data), data.len() as isize);
+    assert_eq!(fcntl(fds[1], F_SETPIPE_SZ, PAGE_SIZE), EBUSY);
+    assert_eq!(read(fds[0], data), data.len() as isize);
+    assert_eq!(fcntl(fds[1], F_SETPIPE_SZ, PAGE_SIZE), PAGE_SIZE as isize);
+    // the buffers are kernel memory
+    assert_eq!(fcntl(fds[1], F_SETPIPE_SZ, 1 << 20), EPERM);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+fn blocking_transfer() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(fds[0]);
+        let data = unsafe { &mut DATA };
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = byte(i);
+        }
+        assert_eq!(write(fds[1], data), LENGTH as isize);
+        close(fds[1]);
+        exit(0);
+    }
+    close(fds[1]);
+    let mut buf = [0u8; PAGE_SIZE / 2];
+    let mut total = 0;
+    loop {
+        let len = read(fds[0], &mut buf);
+        assert!(len >= 0);
+        if len == 0 {
+            break;
+        }
+        for &b in &buf[..len as usize] {
+            assert_eq!(b, byte(total));
+            total += 1;
+        }
+        // reads fill the buffer unless the writer is gone
+        if total < LENGTH {
+            assert_eq!(len as usize, buf.len());
+        }
+    }
+    assert_eq!(total, LENGTH);
+    close(fds[0]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code) && wexitstatus(exit_code) == 0);
+}
+
+fn broken_pipe() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    close(fds[0]);
+    let pid = fork();
+    if pid == 0 {
+        // killed by SIGPIPE
+        write(fds[1], b"nobody reads this");
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifsignaled(exit_code) && wtermsig(exit_code) == SIGPIPE);
+
+    let ignore = SignalAction {
+        handler: SIG_IGN,
+        mask: SignalFlags::empty(),
+    };
+    assert_eq!(sigaction(SIGPIPE, Some(&ignore), None), 0);
+    assert_eq!(write(fds[1], b"nobody reads this"), EPIPE);
+    close(fds[1]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    capacity();
+    blocking_transfer();
+    broken_pipe();
+    println!("pipe blocking test passed!");
+    0
+}
@@ -0,0 +1,154 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fcntl, fork, get_time, pipe, poll, read, sleep, waitpid, write, OpenFlags, PollFd,
+    EAGAIN, F_GETFL, F_SETFL, POLLERR, POLLHUP, POLLIN, POLLNVAL, POLLOUT,
+};
+
+fn readiness() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut pollfds = [PollFd::new(fds[0], POLLIN), PollFd::new(fds[1], POLLOUT)];
+    // only the write end is ready
+    assert_eq!(poll(&mut pollfds, Some(0)), 1);
+    assert_eq!(pollfds[0].revents, 0);
+    assert_eq!(pollfds[1].revents, POLLOUT);
+    assert_eq!(write(fds[1], b"x"), 1);
+    assert_eq!(poll(&mut pollfds, Some(0)), 2);
+    assert_eq!(pollfds[0].revents, POLLIN);
+
+    // closed descriptors are reported, negative ones skipped
+    let mut bad = [
+        PollFd::new(100, POLLIN),
+        PollFd {
+            fd: -1,
+            events: POLLIN,
+            revents: 0,
+        },
+    ];
+    assert_eq!(poll(&mut bad, Some(0)), 1);
+    assert_eq!(bad[0].revents, POLLNVAL);
+    assert_eq!(bad[1].revents, 0);
+
+    // hang-ups are reported whatever was asked for
+    close(fds[1]);
+    let mut pollfds = [PollFd::new(fds[0], POLLIN)];
+    assert_eq!(poll(&mut pollfds, Some(0)), 1);
+    assert_eq!(pollfds[0].revents, POLLIN | POLLHUP);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(fds[0], &mut buf), 1);
+    assert_eq!(poll(&mut pollfds, Some(0)), 1);
+    assert_eq!(pollfds[0].revents, POLLHUP);
+    close(fds[0]);
+
+    assert_eq!(pipe(&mut fds), 0);
+    close(fds[0]);
+    let mut pollfds = [PollFd::new(fds[1], POLLOUT)];
+    assert_eq!(poll(&mut pollfds, Some(0)), 1);
+    assert_eq!(pollfds[0].revents, POLLERR);
+    close(fds[1]);
+}
+
+fn nonblocking() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(fcntl(fds[0], F_GETFL, 0), OpenFlags::RDONLY.bits() as isize);
+    assert_eq!(fcntl(fds[1], F_GETFL, 0), OpenFlags::WRONLY.bits() as isize);
+    assert_eq!(
+        fcntl(fds[0], F_SETFL, OpenFlags::NONBLOCK.bits() as usize),
+        0
+    );
+    assert_eq!(
+        fcntl(fds[0], F_GETFL, 0),
+        OpenFlags::NONBLOCK.bits() as isize
+    );
+    let mut buf = [0u8; 8];
+    assert_eq!(read(fds[0], &mut buf), EAGAIN);
+    // a non-blocking read takes what is there
+    assert_eq!(write(fds[1], b"abc"), 3);
+    assert_eq!(read(fds[0], &mut buf), 3);
+    assert_eq!(&buf[..3], b"abc");
+    assert_eq!(read(fds[0], &mut buf), EAGAIN);
+
+    // a non-blocking write fills the pipe and stops there
+    assert_eq!(
+        fcntl(fds[1], F_SETFL, OpenFlags::NONBLOCK.bits() as usize),
+        0
+    );
+    let chunk = [0u8; 512];
+    let mut total = 0;
+    loop {
+        let len = write(fds[1], &chunk);
+        if len == EAGAIN {
+            break;
+        }
+        assert!(len > 0);
+        total += len;
+    }
+    assert_eq!(total, 4096);
+    let mut pollfds = [PollFd::new(fds[1], POLLOUT)];
+    assert_eq!(poll(&mut pollfds, Some(0)), 0);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+fn timeout() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut pollfds = [PollFd::new(fds[0], POLLIN)];
+    let start = get_time();
+    assert_eq!(poll(&mut pollfds, Some(100)), 0);
+    assert!(get_time() - start >= 100);
+    assert_eq!(pollfds[0].revents, 0);
+    close(fds[0]);
+    close(fds[1]);
+}
+
+fn wakeup() {
+    // nobody writes to this one, so the other one has to wake us up
+    let mut idle = [0usize; 2];
+    let mut to_parent = [0usize; 2];
+    assert_eq!(pipe(&mut idle), 0);
+    assert_eq!(pipe(&mut to_parent), 0);
+    let pid = fork();
+    if pid == 0 {
+        close(to_parent[0]);
+        sleep(50);
+        assert_eq!(write(to_parent[1], b"y"), 1);
+        exit(0);
+    }
+    close(to_parent[1]);
+    let mut pollfds = [
+        PollFd::new(idle[0], POLLIN),
+        PollFd::new(to_parent[0], POLLIN),
+    ];
+    assert_eq!(poll(&mut pollfds, None), 1);
+    assert_eq!(pollfds[0].revents, 0);
+    assert_eq!(pollfds[1].revents & POLLIN, POLLIN);
+    let mut buf = [0u8; 1];
+    assert_eq!(read(to_parent[0], &mut buf), 1);
+    assert_eq!(&buf, b"y");
+    // then the child goes away
+    pollfds[1].revents = 0;
+    assert_eq!(poll(&mut pollfds[1..], None), 1);
+    assert_eq!(pollfds[1].revents, POLLHUP);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    close(idle[0]);
+    close(idle[1]);
+    close(to_parent[0]);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    readiness();
+    nonblocking();
+    timeout();
+    wakeup();
+    println!("poll test passed!");
+    0
+}
@@ -0,0 +1,135 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+use user_lib::{
+    barrier_create, barrier_wait, exit, mutex_blocking_create, mutex_lock, mutex_recursive_create,
+    mutex_unlock, rwlock_create, rwlock_read, rwlock_unlock, rwlock_write, set_deadlock_policy,
+    sleep, thread_create, waittid, DEADLOCK_RETURN_ERROR, EDEADLK, EPERM,
+};
+
+/// Recursive mutexes nest, and only the holder may unlock a mutex
+fn recursive_mutex() {
+    let mutex_id = mutex_recursive_create() as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    let tid = thread_create(foreign_unlock as usize, mutex_id) as usize;
+    assert_eq!(waittid(tid), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), EPERM);
+
+    let mutex_id = mutex_blocking_create() as usize;
+    assert_eq!(mutex_unlock(mutex_id), EPERM);
+}
+
+fn foreign_unlock(mutex_id: usize) -> ! {
+    assert_eq!(mutex_unlock(mutex_id), EPERM);
+    exit(0)
+}
+
+static ORDER: AtomicUsize = AtomicUsize::new(0);
+static mut WRITER_TURN: usize = 0;
+static mut READER_TURN: usize = 0;
+
+fn writer(rwlock_id: usize) -> ! {
+    assert_eq!(rwlock_write(rwlock_id), 0);
+    unsafe { WRITER_TURN = ORDER.fetch_add(1, Ordering::Relaxed) };
+    assert_eq!(rwlock_unlock(rwlock_id), 0);
+    exit(0)
+}
+
+fn reader(rwlock_id: usize) -> ! {
+    assert_eq!(rwlock_read(rwlock_id), 0);
+    unsafe { READER_TURN = ORDER.fetch_add(1, Ordering::Relaxed) };
+    assert_eq!(rwlock_unlock(rwlock_id), 0);
+    exit(0)
+}
+
+/// A reader coming after a waiting writer does not overtake it
+fn writer_preference() {
+    let rwlock_id = rwlock_create() as usize;
+    assert_eq!(rwlock_read(rwlock_id), 0);
+    // other readers are welcome as long as no writer waits
+    assert_eq!(rwlock_read(rwlock_id), 0);
+    assert_eq!(rwlock_unlock(rwlock_id), 0);
+    let writer_tid = thread_create(writer as usize, rwlock_id) as usize;
+    sleep(20);
+    let reader_tid = thread_create(reader as usize, rwlock_id) as usize;
+    sleep(20);
+    assert_eq!(rwlock_unlock(rwlock_id), 0);
+    assert_eq!(rwlock_unlock(rwlock_id), EPERM);
+    assert_eq!(waittid(writer_tid), 0);
+    assert_eq!(waittid(reader_tid), 0);
+    unsafe {
+        assert_eq!((WRITER_TURN, READER_TURN), (0, 1));
+    }
+}
+
+const THREAD_COUNT: usize = 4;
+const ROUNDS: usize = 5;
+static ARRIVED: AtomicUsize = AtomicUsize::new(0);
+static LAST: AtomicUsize = AtomicUsize::new(0);
+
+fn round_worker(barrier_id: usize) -> ! {
+    for round in 0..ROUNDS {
+        ARRIVED.fetch_add(1, Ordering::Relaxed);
+        let last = barrier_wait(barrier_id);
+        assert!(last == 0 || last == 1);
+        LAST.fetch_add(last as usize, Ordering::Relaxed);
+        // nobody leaves a round before everyone arrived
+        assert!(ARRIVED.load(Ordering::Relaxed) >= THREAD_COUNT * (round + 1));
+        barrier_wait(barrier_id);
+    }
+    exit(0)
+}
+
+/// A barrier lets threads through in rounds, one of them being told it came last
+fn barrier_rounds() {
+    let barrier_id = barrier_create(THREAD_COUNT) as usize;
+    let mut tids = [0; THREAD_COUNT];
+    for tid in tids.iter_mut() {
+        *tid = thread_create(round_worker as usize, barrier_id) as usize;
+    }
+    for tid in tids {
+        assert_eq!(waittid(tid), 0);
+    }
+    assert_eq!(ARRIVED.load(Ordering::Relaxed), THREAD_COUNT * ROUNDS);
+    assert_eq!(LAST.load(Ordering::Relaxed), ROUNDS);
+}
+
+fn lock_and_exit(mutex_id: usize) -> ! {
+    assert_eq!(mutex_lock(mutex_id), 0);
+    mutex_unlock(mutex_id);
+    exit(0)
+}
+
+/// Waiting at a barrier only threads blocked on us could reach is refused
+fn barrier_deadlock() {
+    let mutex_id = mutex_blocking_create() as usize;
+    let barrier_id = barrier_create(2) as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    let tid = thread_create(lock_and_exit as usize, mutex_id) as usize;
+    sleep(20);
+    assert_eq!(set_deadlock_policy(DEADLOCK_RETURN_ERROR), 0);
+    assert_eq!(barrier_wait(barrier_id), EDEADLK);
+    let rwlock_id = rwlock_create() as usize;
+    assert_eq!(rwlock_write(rwlock_id), 0);
+    assert_eq!(rwlock_read(rwlock_id), EDEADLK);
+    assert_eq!(rwlock_unlock(rwlock_id), 0);
+    mutex_unlock(mutex_id);
+    assert_eq!(waittid(tid), 0);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    recursive_mutex();
+    writer_preference();
+    barrier_rounds();
+    barrier_deadlock();
+    println!("rwlock and barrier test passed!");
+    0
+}
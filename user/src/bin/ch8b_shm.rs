@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use user_lib::{
+    exit, fork, futex_wait, futex_wake, shmat, shmdt, shmget, shmrm, waitpid, wexitstatus,
+    wifexited, EEXIST, EINVAL, ENOENT, IPC_CREAT, IPC_EXCL, IPC_PRIVATE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const KEY: usize = 0x5eed;
+const ITEMS: usize = 100;
+
+/// A one-slot channel living in shared memory
+#[repr(C)]
+struct Slot {
+    /// 1 while holding an item, doubles as the futex word
+    full: AtomicU32,
+    item: AtomicUsize,
+}
+
+fn consumer(slot: &Slot) -> ! {
+    let mut sum = 0;
+    for _ in 0..ITEMS {
+        while slot.full.load(Ordering::Acquire) == 0 {
+            futex_wait(&slot.full, 0, 0);
+        }
+        sum += slot.item.load(Ordering::Relaxed);
+        slot.full.store(0, Ordering::Release);
+        futex_wake(&slot.full, 1);
+    }
+    exit((sum == ITEMS * (ITEMS + 1) / 2) as i32)
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // keyed segments are found again, unless asked for exclusively
+    let id = shmget(KEY, 2 * PAGE_SIZE, IPC_CREAT | IPC_EXCL);
+    assert!(id >= 0);
+    assert_eq!(shmget(KEY, PAGE_SIZE, IPC_CREAT | IPC_EXCL), EEXIST);
+    assert_eq!(shmget(KEY, PAGE_SIZE, 0), id);
+    assert_eq!(shmget(KEY, 3 * PAGE_SIZE, 0), EINVAL);
+    assert_eq!(shmget(KEY + 1, PAGE_SIZE, 0), ENOENT);
+    let private = shmget(IPC_PRIVATE, PAGE_SIZE, 0);
+    assert!(private >= 0 && private != id);
+    assert_eq!(shmrm(private as usize), 0);
+
+    // two attachments alias the same frames
+    let first = shmat(id as usize, 0, 0);
+    let second = shmat(id as usize, 0, 0);
+    assert!(first > 0 && second > 0 && first != second);
+    let words = unsafe { core::slice::from_raw_parts_mut(first as *mut usize, 2 * PAGE_SIZE / 8) };
+    assert!(words.iter().all(|&word| word == 0));
+    words[PAGE_SIZE / 8] = 42;
+    assert_eq!(
+        unsafe { *((second as usize + PAGE_SIZE) as *const usize) },
+        42
+    );
+    assert_eq!(shmdt(second as usize), 0);
+    assert_eq!(shmdt(second as usize), EINVAL);
+    assert_eq!(shmat(id as usize, first as usize, 0), EINVAL);
+    // out of the user half, the last page being the trampoline
+    assert_eq!(shmat(id as usize, 1 << 38, 0), EINVAL);
+    assert_eq!(shmat(id as usize, (1 << 38) - PAGE_SIZE, 0), EINVAL);
+    assert_eq!(shmat(id as usize, usize::MAX - PAGE_SIZE + 1, 0), EINVAL);
+
+    // removing it only forgets the key, the frames stay while attached
+    assert_eq!(shmrm(id as usize), 0);
+    assert_eq!(shmget(KEY, PAGE_SIZE, 0), ENOENT);
+    assert_eq!(shmat(id as usize, 0, 0), EINVAL);
+
+    // fork keeps the segment shared rather than copying it
+    let slot = unsafe { &*(first as *const Slot) };
+    let pid = fork();
+    if pid == 0 {
+        consumer(slot);
+    }
+    for item in 1..=ITEMS {
+        while slot.full.load(Ordering::Acquire) == 1 {
+            futex_wait(&slot.full, 1, 0);
+        }
+        slot.item.store(item, Ordering::Relaxed);
+        slot.full.store(1, Ordering::Release);
+        futex_wake(&slot.full, 1);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code) && wexitstatus(exit_code) == 1);
+    assert_eq!(words[PAGE_SIZE / 8], 42);
+    assert_eq!(shmdt(first as usize), 0);
+    println!("shm test passed!");
+    0
+}
@@ -0,0 +1,131 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, exit, fork, getpid, kill, mutex_blocking_create, mutex_lock, pipe, read,
+    semaphore_create, semaphore_down, sigaction, sigprocmask, sigreturn, sleep, thread_create,
+    waitpid, wexitstatus, wifexited, wifsignaled, wtermsig, SignalAction, SignalFlags, SIGKILL,
+    SIGSEGV, SIGTERM, SIGUSR1,
+};
+
+static mut HANDLED: usize = 0;
+
+fn func() {
+    unsafe {
+        HANDLED += 1;
+    }
+    sigreturn();
+}
+
+fn segv_handler() {
+    println!("caught SIGSEGV");
+    exit(11);
+}
+
+fn user_sig_test_kill() {
+    let new = SignalAction {
+        handler: func as usize,
+        mask: SignalFlags::empty(),
+    };
+    let mut old = SignalAction::default();
+    if sigaction(SIGUSR1, Some(&new), Some(&mut old)) < 0 {
+        panic!("Sigaction failed!");
+    }
+    if kill(getpid() as usize, SIGUSR1) < 0 {
+        panic!("Kill failed!");
+    }
+    assert_eq!(unsafe { HANDLED }, 1);
+}
+
+fn user_sig_test_procmask() {
+    sigprocmask(SignalFlags::SIGUSR1.bits());
+    kill(getpid() as usize, SIGUSR1);
+    // blocked, so still pending
+    assert_eq!(unsafe { HANDLED }, 1);
+    sigprocmask(0);
+    // delivered as soon as it is unblocked
+    assert_eq!(unsafe { HANDLED }, 2);
+}
+
+fn user_sig_test_sigkill() {
+    let pid = fork();
+    if pid == 0 {
+        #[allow(clippy::empty_loop)]
+        loop {}
+    }
+    sleep(10);
+    assert_eq!(kill(pid as usize, SIGKILL), 0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifsignaled(exit_code));
+    assert_eq!(wtermsig(exit_code), SIGKILL);
+}
+
+fn mutex_waiter(mutex_id: usize) -> ! {
+    mutex_lock(mutex_id);
+    panic!("took a mutex the main thread holds");
+}
+
+fn semaphore_waiter(sem_id: usize) -> ! {
+    semaphore_down(sem_id);
+    panic!("took a unit nobody gave");
+}
+
+/// A signal that kills the process ends the waits of all its threads
+fn user_sig_test_kill_blocked() {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        let mutex_id = mutex_blocking_create() as usize;
+        assert_eq!(mutex_lock(mutex_id), 0);
+        thread_create(mutex_waiter as usize, mutex_id);
+        thread_create(semaphore_waiter as usize, semaphore_create(0) as usize);
+        let mut buf = [0u8; 1];
+        // blocks for ever, the write ends stay open and nobody writes
+        read(fds[0], &mut buf);
+        panic!("read from a pipe nobody writes");
+    }
+    close(fds[0]);
+    sleep(50);
+    assert_eq!(kill(pid as usize, SIGTERM), 0);
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifsignaled(exit_code));
+    assert_eq!(wtermsig(exit_code), SIGTERM);
+    close(fds[1]);
+}
+
+fn user_sig_test_segv() {
+    let pid = fork();
+    if pid == 0 {
+        let new = SignalAction {
+            handler: segv_handler as usize,
+            mask: SignalFlags::empty(),
+        };
+        sigaction(SIGSEGV, Some(&new), None);
+        unsafe {
+            #[allow(clippy::zero_ptr)]
+            (0x0 as *mut u8).write_volatile(0);
+        }
+        exit(0);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 11);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    user_sig_test_kill();
+    user_sig_test_procmask();
+    user_sig_test_sigkill();
+    user_sig_test_kill_blocked();
+    user_sig_test_segv();
+    println!("signal tests passed!");
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, exit_group, fork, sleep, thread_cancel, thread_create, thread_detach, waitpid, waittid,
+    wexitstatus, wifexited, THREAD_CANCELED,
+};
+
+const DETACHED_ROUNDS: usize = 200;
+
+fn quick() -> ! {
+    exit(1)
+}
+
+fn spin() -> ! {
+    #[allow(clippy::empty_loop)]
+    loop {}
+}
+
+fn group_exiter() -> ! {
+    sleep(10);
+    exit_group(7)
+}
+
+fn user_thread_test_detach() {
+    // far more threads than kernel stacks would survive if they leaked
+    for _ in 0..DETACHED_ROUNDS {
+        let tid = thread_create(quick as usize, 0) as usize;
+        assert_eq!(thread_detach(tid), 0);
+        // no longer joinable, whether it has exited already or not
+        assert_eq!(waittid(tid), -1);
+    }
+    assert_eq!(thread_detach(0), -1);
+}
+
+fn user_thread_test_cancel() {
+    let tid = thread_create(spin as usize, 0) as usize;
+    assert_eq!(thread_cancel(tid), 0);
+    assert_eq!(waittid(tid), THREAD_CANCELED);
+}
+
+fn user_thread_test_exit_group() {
+    let pid = fork();
+    if pid == 0 {
+        thread_create(spin as usize, 0);
+        thread_create(group_exiter as usize, 0);
+        spin();
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 7);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    user_thread_test_detach();
+    user_thread_test_cancel();
+    user_thread_test_exit_group();
+    println!("threads detach test passed!");
+    0
+}
@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exec, exit, fork, mutex_blocking_create, mutex_lock, sleep, thread_create, waitpid, waittid,
+    wexitstatus, wifexited,
+};
+
+fn sleeper() -> ! {
+    loop {
+        sleep(10);
+    }
+}
+
+/// Blocks on a mutex the main thread holds for good
+fn mutex_waiter(mutex_id: usize) -> ! {
+    mutex_lock(mutex_id);
+    panic!("took a mutex the main thread holds");
+}
+
+/// Forks from a thread other than the main one
+fn forker() -> ! {
+    let pid = fork();
+    if pid == 0 {
+        // only this thread exists in the child, and it is its main thread
+        exit(3);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 3);
+    exit(0)
+}
+
+/// Replaces the whole process while the main thread is still running
+fn execer() -> ! {
+    exec("ch2b_hello_world\0", &[core::ptr::null::<u8>()]);
+    panic!("exec failed!");
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    thread_create(sleeper as usize, 0);
+    let tid = thread_create(forker as usize, 0);
+    assert_eq!(waittid(tid as usize), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        let mutex_id = mutex_blocking_create() as usize;
+        assert_eq!(mutex_lock(mutex_id), 0);
+        thread_create(mutex_waiter as usize, mutex_id);
+        thread_create(sleeper as usize, 0);
+        thread_create(execer as usize, 0);
+        sleeper();
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 0);
+    println!("threads fork exec test passed!");
+    0
+}
@@ -0,0 +1,137 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    condvar_create, condvar_signal, condvar_wait_timeout, enable_deadlock_detect, exit, get_time,
+    mutex_blocking_create, mutex_lock, mutex_timedlock, mutex_trylock, mutex_unlock,
+    semaphore_create, semaphore_down, semaphore_down_timeout, semaphore_up, sleep, thread_create,
+    waittid, EBUSY, EDEADLK, ETIMEDOUT,
+};
+
+const SHORT: usize = 50;
+const LONG: usize = 1000;
+
+/// Run `f` in a thread, `delay` ms later run `then` in the caller, and
+/// wait for the thread
+fn in_thread(f: fn(usize) -> !, arg: usize, delay: usize, then: impl FnOnce()) {
+    let tid = thread_create(f as usize, arg) as usize;
+    sleep(delay);
+    then();
+    assert_eq!(waittid(tid), 0);
+}
+
+fn mutex_busy(mutex_id: usize) -> ! {
+    assert_eq!(mutex_trylock(mutex_id), EBUSY);
+    let start = get_time();
+    assert_eq!(mutex_timedlock(mutex_id, SHORT), ETIMEDOUT);
+    assert!(get_time() - start >= SHORT as isize);
+    exit(0)
+}
+
+fn mutex_patient(mutex_id: usize) -> ! {
+    assert_eq!(mutex_timedlock(mutex_id, LONG), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    exit(0)
+}
+
+fn timed_mutex() {
+    let mutex_id = mutex_blocking_create() as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    in_thread(mutex_busy, mutex_id, 0, || {});
+    in_thread(mutex_patient, mutex_id, 20, || {
+        assert_eq!(mutex_unlock(mutex_id), 0);
+    });
+    assert_eq!(mutex_trylock(mutex_id), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+}
+
+fn sem_patient(sem_id: usize) -> ! {
+    assert_eq!(semaphore_down_timeout(sem_id, LONG), 0);
+    exit(0)
+}
+
+fn timed_semaphore() {
+    let sem_id = semaphore_create(0) as usize;
+    let start = get_time();
+    assert_eq!(semaphore_down_timeout(sem_id, SHORT), ETIMEDOUT);
+    assert!(get_time() - start >= SHORT as isize);
+    // the timed out wait left the count as it was
+    semaphore_up(sem_id);
+    assert_eq!(semaphore_down_timeout(sem_id, SHORT), 0);
+    in_thread(sem_patient, sem_id, 20, || semaphore_up(sem_id));
+}
+
+/// Takes a unit of the first semaphore then waits on the second one,
+/// which only the main thread raises
+fn sem_timed_first(ids: usize) -> ! {
+    let (sem_id, gate_id) = (ids >> 16, ids & 0xffff);
+    assert_eq!(semaphore_down_timeout(sem_id, LONG), 0);
+    assert_eq!(semaphore_down(gate_id), 0);
+    semaphore_up(sem_id);
+    exit(0)
+}
+
+fn sem_untimed(sem_id: usize) -> ! {
+    assert_eq!(semaphore_down(sem_id), 0);
+    exit(0)
+}
+
+/// The unit of an `up` goes to the first waiter even when it is a timed
+/// one, and the deadlock detector must see it there
+fn timed_semaphore_detection() {
+    enable_deadlock_detect(true);
+    let sem_id = semaphore_create(0) as usize;
+    let gate_id = semaphore_create(0) as usize;
+    let timed = thread_create(sem_timed_first as usize, sem_id << 16 | gate_id) as usize;
+    sleep(20);
+    let untimed = thread_create(sem_untimed as usize, sem_id) as usize;
+    sleep(20);
+    semaphore_up(sem_id);
+    sleep(20);
+    // the timed waiter holds the unit and waits for the gate, the other one
+    // still waits for a unit, so nobody could ever raise the gate
+    assert_eq!(semaphore_down(sem_id), EDEADLK);
+    semaphore_up(gate_id);
+    assert_eq!(waittid(timed), 0);
+    assert_eq!(waittid(untimed), 0);
+    enable_deadlock_detect(false);
+}
+
+fn condvar_patient(ids: usize) -> ! {
+    let (condvar_id, mutex_id) = (ids >> 16, ids & 0xffff);
+    assert_eq!(mutex_lock(mutex_id), 0);
+    assert_eq!(condvar_wait_timeout(condvar_id, mutex_id, LONG), 0);
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    exit(0)
+}
+
+fn timed_condvar() {
+    let mutex_id = mutex_blocking_create() as usize;
+    let condvar_id = condvar_create() as usize;
+    assert_eq!(mutex_lock(mutex_id), 0);
+    let start = get_time();
+    assert_eq!(condvar_wait_timeout(condvar_id, mutex_id, SHORT), ETIMEDOUT);
+    assert!(get_time() - start >= SHORT as isize);
+    // the mutex is held again after a timeout too
+    assert_eq!(mutex_unlock(mutex_id), 0);
+    // nobody waits anymore, so this signal is lost
+    condvar_signal(condvar_id);
+    in_thread(condvar_patient, condvar_id << 16 | mutex_id, 20, || {
+        assert_eq!(mutex_lock(mutex_id), 0);
+        condvar_signal(condvar_id);
+        assert_eq!(mutex_unlock(mutex_id), 0);
+    });
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    timed_mutex();
+    timed_semaphore();
+    timed_semaphore_detection();
+    timed_condvar();
+    println!("timed waits test passed!");
+    0
+}
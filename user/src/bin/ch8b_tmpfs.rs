@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    close, fstat, link, mount, open, read, umount, unlink, write, OpenFlags, Stat, StatMode, EBUSY,
+    EEXIST, EINVAL, ENOENT, ENOSPC, EXDEV,
+};
+
+/// Mount point of the tmpfs instances of this test, `/tmp` being shared
+const MNT: &str = "/tmpfs_test\0";
+
+fn read_file(path: &str, buf: &mut [u8]) -> isize {
+    let fd = open(path, OpenFlags::RDONLY);
+    assert!(fd >= 0);
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    len
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // the tmpfs mounted at /tmp while booting
+    let fd = open("/tmp/tmpfs_a\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    assert_eq!(write(fd, b"hello tmpfs"), 11);
+    let stat = Stat::new();
+    assert_eq!(fstat(fd, &stat), 0);
+    assert_eq!(stat.mode, StatMode::FILE);
+    assert_eq!(stat.nlink, 1);
+
+    assert_eq!(link("/tmp/tmpfs_a\0", "/tmp/tmpfs_b\0"), 0);
+    assert_eq!(link("/tmp/tmpfs_a\0", "/tmp/tmpfs_b\0"), EEXIST);
+    assert_eq!(link("/tmp/tmpfs_a\0", "/tmpfs_a\0"), EXDEV);
+    let stat2 = Stat::new();
+    assert_eq!(fstat(fd, &stat2), 0);
+    assert_eq!((stat2.dev, stat2.ino, stat2.nlink), (stat.dev, stat.ino, 2));
+    assert_eq!(unlink("/tmp/tmpfs_a\0"), 0);
+    assert_eq!(unlink("/tmp/tmpfs_a\0"), ENOENT);
+    assert!(open("/tmp/tmpfs_a\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(fstat(fd, &stat2), 0);
+    assert_eq!(stat2.nlink, 1);
+    close(fd);
+    let mut buf = [0u8; 32];
+    assert_eq!(read_file("/tmp/tmpfs_b\0", &mut buf), 11);
+    assert_eq!(&buf[..11], b"hello tmpfs");
+    assert_eq!(unlink("/tmp/tmpfs_b\0"), 0);
+
+    // a small instance of its own, files are kept until it is unmounted
+    assert_eq!(mount("none\0", MNT, "tmpfs\0", "size=8x\0"), EINVAL);
+    assert_eq!(mount("none\0", MNT, "tmpfs\0", "size=8k\0"), 0);
+    assert_eq!(mount("none\0", MNT, "tmpfs\0", "\0"), EBUSY);
+    let fd = open("/tmpfs_test/big\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let data = [0x5au8; 3000];
+    assert_eq!(write(fd, &data), 3000);
+    assert_eq!(write(fd, &data), 3000);
+    // short once full, then nothing
+    assert_eq!(write(fd, &data), 8192 - 6000);
+    assert_eq!(write(fd, &data), ENOSPC);
+    // open files keep the filesystem
+    assert_eq!(umount(MNT), EBUSY);
+    close(fd);
+    // the space comes back with the last name
+    assert_eq!(unlink("/tmpfs_test/big\0"), 0);
+    let fd = open("/tmpfs_test/big2\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, &data), 3000);
+    close(fd as usize);
+
+    assert_eq!(umount(MNT), 0);
+    assert!(open("/tmpfs_test/big2\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(mount("none\0", MNT, "tmpfs\0", "\0"), 0);
+    assert!(open("/tmpfs_test/big2\0", OpenFlags::RDONLY) < 0);
+    assert_eq!(umount(MNT), 0);
+    println!("tmpfs test passed!");
+    0
+}
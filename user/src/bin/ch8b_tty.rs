@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::console::STDIN;
+use user_lib::{
+    poll, read, tcgetattr, tcsetattr, PollFd, Termios, ECHO, ICANON, ICRNL, ISIG, ONLCR, OPOST,
+    POLLIN, TCSETS, TCSETSF, VEOF, VERASE, VINTR, VMIN, VSUSP,
+};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    let mut saved = Termios::default();
+    assert_eq!(tcgetattr(STDIN, &mut saved), 0);
+    // the console starts in canonical mode
+    assert_eq!(saved.lflag & (ICANON | ECHO | ISIG), ICANON | ECHO | ISIG);
+    assert_eq!(saved.iflag & ICRNL, ICRNL);
+    assert_eq!(saved.oflag & (OPOST | ONLCR), OPOST | ONLCR);
+    assert_eq!(saved.cc[VINTR], 0x03);
+    assert_eq!(saved.cc[VERASE], 0x7f);
+    assert_eq!(saved.cc[VEOF], 0x04);
+    assert_eq!(saved.cc[VSUSP], 0x1a);
+
+    // raw mode with VMIN = 0 reads whatever is there without waiting
+    let mut raw = saved;
+    raw.make_raw();
+    raw.cc[VMIN] = 0;
+    assert_eq!(tcsetattr(STDIN, TCSETSF, &raw), 0);
+    let mut current = Termios::default();
+    assert_eq!(tcgetattr(STDIN, &mut current), 0);
+    assert_eq!(current, raw);
+    assert_eq!(current.lflag & (ICANON | ECHO | ISIG), 0);
+    let mut buf = [0u8; 16];
+    assert_eq!(read(STDIN, &mut buf), 0);
+    let mut pollfds = [PollFd::new(STDIN, POLLIN)];
+    assert_eq!(poll(&mut pollfds, Some(0)), 0);
+
+    // the attributes are shared by the standard streams
+    assert_eq!(tcsetattr(1, TCSETS, &saved), 0);
+    assert_eq!(tcgetattr(STDIN, &mut current), 0);
+    assert_eq!(current, saved);
+    println!("tty test passed!");
+    0
+}
@@ -1,22 +1,134 @@
 #![no_std]
 #![no_main]
-#![allow(clippy::println_empty_string)]
 
 extern crate alloc;
 
 #[macro_use]
 extern crate user_lib;
 
-const LF: u8 = 0x0au8;
-const CR: u8 = 0x0du8;
-const DL: u8 = 0x7fu8;
-const BS: u8 = 0x08u8;
 const LINE_START: &str = ">> ";
 
 use alloc::string::String;
 use alloc::vec::Vec;
-use user_lib::console::getchar;
-use user_lib::{close, dup, exec, fork, open, pipe, waitpid, OpenFlags};
+use user_lib::console::{flush, STDIN};
+use user_lib::{
+    close, dup2, exec, fork, getpid, killpg, open, pipe, read, setpgid, setsid, sigaction,
+    tcsetpgrp, waitpid_options, wifstopped, OpenFlags, SignalAction, SignalFlags, SIGCONT, SIGINT,
+    SIGTSTP, SIGTTOU, SIG_DFL, SIG_IGN, WNOHANG, WUNTRACED,
+};
+
+/// A pipeline started by the shell, running in a process group of its own
+struct Job {
+    id: usize,
+    pgid: usize,
+    /// Processes of the pipeline which have not been reaped yet
+    pids: Vec<usize>,
+    command: String,
+    stopped: bool,
+}
+
+/// Set the action of the signals generated by the terminal
+fn set_job_control_signals(handler: usize) {
+    let action = SignalAction {
+        handler,
+        mask: SignalFlags::empty(),
+    };
+    for signum in [SIGINT, SIGTSTP, SIGTTOU] {
+        sigaction(signum, Some(&action), None);
+    }
+}
+
+/// Give the terminal to `job` and wait until it exits or is stopped
+///
+/// Returns true if the whole job has exited.
+fn wait_foreground(job: &mut Job, shell_pgid: usize) -> bool {
+    tcsetpgrp(0, job.pgid);
+    while let Some(&pid) = job.pids.first() {
+        let mut exit_code: i32 = 0;
+        let exit_pid = waitpid_options(pid as isize, &mut exit_code, WUNTRACED);
+        if exit_pid == pid as isize && wifstopped(exit_code) {
+            job.stopped = true;
+            break;
+        }
+        //println!("Shell: Process {} exited with code {}", pid, wexitstatus(exit_code));
+        job.pids.remove(0);
+    }
+    tcsetpgrp(0, shell_pgid);
+    job.pids.is_empty()
+}
+
+/// Reap background processes that have exited and report finished jobs
+fn reap_background(jobs: &mut Vec<Job>) {
+    for job in jobs.iter_mut() {
+        let mut stopped = false;
+        job.pids.retain(|&pid| {
+            let mut exit_code: i32 = 0;
+            match waitpid_options(pid as isize, &mut exit_code, WNOHANG | WUNTRACED) {
+                -2 => true,
+                exit_pid if exit_pid == pid as isize && wifstopped(exit_code) => {
+                    stopped = true;
+                    true
+                }
+                _ => false,
+            }
+        });
+        job.stopped |= stopped;
+        if job.pids.is_empty() {
+            println!("[{}] Done\t{}", job.id, job.command);
+        }
+    }
+    jobs.retain(|job| !job.pids.is_empty());
+}
+
+/// Index in `jobs` of the job named by the argument of `fg`/`bg`, the latest one by default
+fn find_job(jobs: &[Job], arg: Option<&str>) -> Option<usize> {
+    match arg {
+        None => jobs.len().checked_sub(1),
+        Some(arg) => {
+            let id: usize = arg.trim_start_matches('%').parse().ok()?;
+            jobs.iter().position(|job| job.id == id)
+        }
+    }
+}
+
+/// Run the job control builtins, returns false if `line` is not one of them
+fn run_builtin(line: &str, jobs: &mut Vec<Job>, shell_pgid: usize) -> bool {
+    let mut words = line.split(' ').filter(|word| !word.is_empty());
+    let command = words.next();
+    let arg = words.next();
+    match command {
+        Some("jobs") => {
+            for job in jobs.iter() {
+                let state = if job.stopped { "Stopped" } else { "Running" };
+                println!("[{}] {}\t{}", job.id, state, job.command);
+            }
+        }
+        Some("fg") => match find_job(jobs, arg) {
+            Some(idx) => {
+                let mut job = jobs.remove(idx);
+                println!("{}", job.command);
+                job.stopped = false;
+                killpg(job.pgid, SIGCONT);
+                if !wait_foreground(&mut job, shell_pgid) {
+                    println!("\n[{}] Stopped\t{}", job.id, job.command);
+                    jobs.push(job);
+                }
+            }
+            None => println!("fg: no such job"),
+        },
+        Some("bg") => match find_job(jobs, arg) {
+            Some(idx) => {
+                let job = &mut jobs[idx];
+                job.stopped = false;
+                killpg(job.pgid, SIGCONT);
+                println!("[{}] {} &", job.id, job.command);
+            }
+            None => println!("bg: no such job"),
+        },
+        _ => return false,
+    }
+    true
+}
 
 #[derive(Debug)]
 struct ProcessArguments {
@@ -74,140 +186,165 @@ impl ProcessArguments {
     }
 }
 
+/// Read a line edited by the terminal, `false` at the end of the input
+fn read_line(line: &mut String) -> bool {
+    let mut buf = [0u8; 256];
+    loop {
+        let len = read(STDIN, &mut buf);
+        if len <= 0 {
+            // an unterminated line is still run
+            return !line.is_empty();
+        }
+        let bytes = &buf[..len as usize];
+        line.push_str(&String::from_utf8_lossy(bytes));
+        if bytes.ends_with(b"\n") {
+            return true;
+        }
+    }
+}
+
 #[no_mangle]
 pub fn main() -> i32 {
     println!("Rust user shell");
+    // lead a session of our own and keep the terminal signals for the jobs
+    let shell_pgid = match setsid() {
+        -1 => getpid() as usize,
+        sid => sid as usize,
+    };
+    tcsetpgrp(0, shell_pgid);
+    set_job_control_signals(SIG_IGN);
+    let mut jobs: Vec<Job> = Vec::new();
     let mut line: String = String::new();
-    print!("{}", LINE_START);
     loop {
-        let c = getchar();
-        match c {
-            LF | CR => {
-                println!("");
-                if !line.is_empty() {
-                    let splited: Vec<_> = line.as_str().split('|').collect();
-                    let process_arguments_list: Vec<_> = splited
-                        .iter()
-                        .map(|&cmd| ProcessArguments::new(cmd))
-                        .collect();
-                    let mut valid = true;
-                    for (i, process_args) in process_arguments_list.iter().enumerate() {
-                        if i == 0 {
-                            if !process_args.output.is_empty() {
-                                valid = false;
-                            }
-                        } else if i == process_arguments_list.len() - 1 {
-                            if !process_args.input.is_empty() {
-                                valid = false;
-                            }
-                        } else if !process_args.output.is_empty() || !process_args.input.is_empty()
-                        {
-                            valid = false;
-                        }
+        print!("{}", LINE_START);
+        flush();
+        line.clear();
+        if !read_line(&mut line) {
+            println!("exit");
+            return 0;
+        }
+        reap_background(&mut jobs);
+        let mut command = line.trim();
+        let background = command.ends_with('&');
+        if background {
+            command = command.trim_end_matches('&').trim_end();
+        }
+        if !command.is_empty() && !run_builtin(command, &mut jobs, shell_pgid) {
+            let splited: Vec<_> = command.split('|').collect();
+            let process_arguments_list: Vec<_> = splited
+                .iter()
+                .map(|&cmd| ProcessArguments::new(cmd))
+                .collect();
+            let mut valid = true;
+            for (i, process_args) in process_arguments_list.iter().enumerate() {
+                if i == 0 {
+                    if !process_args.output.is_empty() {
+                        valid = false;
                     }
-                    if process_arguments_list.len() == 1 {
-                        valid = true;
+                } else if i == process_arguments_list.len() - 1 {
+                    if !process_args.input.is_empty() {
+                        valid = false;
                     }
-                    if !valid {
-                        println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
-                    } else {
-                        // create pipes
-                        let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
-                        if !process_arguments_list.is_empty() {
-                            for _ in 0..process_arguments_list.len() - 1 {
-                                let mut pipe_fd = [0usize; 2];
-                                pipe(&mut pipe_fd);
-                                pipes_fd.push(pipe_fd);
+                } else if !process_args.output.is_empty() || !process_args.input.is_empty() {
+                    valid = false;
+                }
+            }
+            if process_arguments_list.len() == 1 {
+                valid = true;
+            }
+            if !valid {
+                println!("Invalid command: Inputs/Outputs cannot be correctly binded!");
+            } else {
+                // create pipes
+                let mut pipes_fd: Vec<[usize; 2]> = Vec::new();
+                if !process_arguments_list.is_empty() {
+                    for _ in 0..process_arguments_list.len() - 1 {
+                        let mut pipe_fd = [0usize; 2];
+                        pipe(&mut pipe_fd);
+                        pipes_fd.push(pipe_fd);
+                    }
+                }
+                let mut children: Vec<_> = Vec::new();
+                for (i, process_argument) in process_arguments_list.iter().enumerate() {
+                    let pid = fork();
+                    if pid == 0 {
+                        // the first process of the pipeline leads its group
+                        let pid = getpid() as usize;
+                        setpgid(pid, children.first().map_or(pid, |&leader| leader));
+                        set_job_control_signals(SIG_DFL);
+                        let input = &process_argument.input;
+                        let output = &process_argument.output;
+                        let args_copy = &process_argument.args_copy;
+                        let args_addr = &process_argument.args_addr;
+                        // redirect input
+                        if !input.is_empty() {
+                            let input_fd = open(input.as_str(), OpenFlags::RDONLY);
+                            if input_fd == -1 {
+                                println!("Error when opening file {}", input);
+                                return -4;
                             }
+                            let input_fd = input_fd as usize;
+                            assert_eq!(dup2(input_fd, 0), 0);
+                            close(input_fd);
                         }
-                        let mut children: Vec<_> = Vec::new();
-                        for (i, process_argument) in process_arguments_list.iter().enumerate() {
-                            let pid = fork();
-                            if pid == 0 {
-                                let input = &process_argument.input;
-                                let output = &process_argument.output;
-                                let args_copy = &process_argument.args_copy;
-                                let args_addr = &process_argument.args_addr;
-                                // redirect input
-                                if !input.is_empty() {
-                                    let input_fd = open(input.as_str(), OpenFlags::RDONLY);
-                                    if input_fd == -1 {
-                                        println!("Error when opening file {}", input);
-                                        return -4;
-                                    }
-                                    let input_fd = input_fd as usize;
-                                    close(0);
-                                    assert_eq!(dup(input_fd), 0);
-                                    close(input_fd);
-                                }
-                                // redirect output
-                                if !output.is_empty() {
-                                    let output_fd = open(
-                                        output.as_str(),
-                                        OpenFlags::CREATE | OpenFlags::WRONLY,
-                                    );
-                                    if output_fd == -1 {
-                                        println!("Error when opening file {}", output);
-                                        return -4;
-                                    }
-                                    let output_fd = output_fd as usize;
-                                    close(1);
-                                    assert_eq!(dup(output_fd), 1);
-                                    close(output_fd);
-                                }
-                                // receive input from the previous process
-                                if i > 0 {
-                                    close(0);
-                                    let read_end = pipes_fd.get(i - 1).unwrap()[0];
-                                    assert_eq!(dup(read_end), 0);
-                                }
-                                // send output to the next process
-                                if i < process_arguments_list.len() - 1 {
-                                    close(1);
-                                    let write_end = pipes_fd.get(i).unwrap()[1];
-                                    assert_eq!(dup(write_end), 1);
-                                }
-                                // close all pipe ends inherited from the parent process
-                                for pipe_fd in pipes_fd.iter() {
-                                    close(pipe_fd[0]);
-                                    close(pipe_fd[1]);
-                                }
-                                // execute new application
-                                if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
-                                    println!("Error when executing!");
-                                    return -4;
-                                }
-                                unreachable!();
-                            } else {
-                                children.push(pid);
+                        // redirect output
+                        if !output.is_empty() {
+                            let output_fd =
+                                open(output.as_str(), OpenFlags::CREATE | OpenFlags::WRONLY);
+                            if output_fd == -1 {
+                                println!("Error when opening file {}", output);
+                                return -4;
                             }
+                            let output_fd = output_fd as usize;
+                            assert_eq!(dup2(output_fd, 1), 1);
+                            close(output_fd);
                         }
+                        // receive input from the previous process
+                        if i > 0 {
+                            let read_end = pipes_fd.get(i - 1).unwrap()[0];
+                            assert_eq!(dup2(read_end, 0), 0);
+                        }
+                        // send output to the next process
+                        if i < process_arguments_list.len() - 1 {
+                            let write_end = pipes_fd.get(i).unwrap()[1];
+                            assert_eq!(dup2(write_end, 1), 1);
+                        }
+                        // close all pipe ends inherited from the parent process
                         for pipe_fd in pipes_fd.iter() {
                             close(pipe_fd[0]);
                             close(pipe_fd[1]);
                         }
-                        let mut exit_code: i32 = 0;
-                        for pid in children.into_iter() {
-                            let exit_pid = waitpid(pid as usize, &mut exit_code);
-                            assert_eq!(pid, exit_pid);
-                            //println!("Shell: Process {} exited with code {}", pid, exit_code);
+                        // execute new application
+                        if exec(args_copy[0].as_str(), args_addr.as_slice()) == -1 {
+                            println!("Error when executing!");
+                            return -4;
                         }
+                        unreachable!();
+                    } else {
+                        let pid = pid as usize;
+                        // also done here so that the group exists once fork returns
+                        setpgid(pid, children.first().map_or(pid, |&leader| leader));
+                        children.push(pid);
                     }
-                    line.clear();
                 }
-                print!("{}", LINE_START);
-            }
-            BS | DL => {
-                if !line.is_empty() {
-                    print!("{}", BS as char);
-                    print!(" ");
-                    print!("{}", BS as char);
-                    line.pop();
+                for pipe_fd in pipes_fd.iter() {
+                    close(pipe_fd[0]);
+                    close(pipe_fd[1]);
+                }
+                let mut job = Job {
+                    id: jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1,
+                    pgid: children[0],
+                    pids: children,
+                    command: String::from(command),
+                    stopped: false,
+                };
+                if background {
+                    println!("[{}] {}", job.id, job.pgid);
+                    jobs.push(job);
+                } else if !wait_foreground(&mut job, shell_pgid) {
+                    println!("\n[{}] Stopped\t{}", job.id, job.command);
+                    jobs.push(job);
                 }
-            }
-            _ => {
-                print!("{}", c as char);
-                line.push(c as char);
             }
         }
     }
@@ -0,0 +1,36 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{close, mount, open, read, umount, unlink, write, OpenFlags, EBUSY, EINVAL, ENODEV};
+
+#[no_mangle]
+pub fn main() -> i32 {
+    // paths are looked up from the root, whichever way they are spelled
+    let fd = open("/vfs_file\0", OpenFlags::CREATE | OpenFlags::WRONLY);
+    assert!(fd >= 0);
+    assert_eq!(write(fd as usize, b"vfs"), 3);
+    close(fd as usize);
+    for path in ["vfs_file\0", "//vfs_file\0", "/./nowhere/../vfs_file\0"] {
+        let fd = open(path, OpenFlags::RDONLY);
+        assert!(fd >= 0);
+        let mut buf = [0u8; 8];
+        assert_eq!(read(fd as usize, &mut buf), 3);
+        assert_eq!(&buf[..3], b"vfs");
+        close(fd as usize);
+    }
+    // a file is no directory
+    assert!(open("/vfs_file/inside\0", OpenFlags::RDONLY) < 0);
+    assert!(open("/vfs_file/inside\0", OpenFlags::CREATE | OpenFlags::WRONLY) < 0);
+    assert_eq!(unlink("/vfs_file\0"), 0);
+    assert!(open("/vfs_file\0", OpenFlags::RDONLY) < 0);
+
+    assert_eq!(mount("none\0", "/mnt\0", "nosuchfs\0", "\0"), ENODEV);
+    // the root filesystem stays
+    assert_eq!(umount("/\0"), EBUSY);
+    assert_eq!(umount("/mnt\0"), EINVAL);
+    println!("vfs test passed!");
+    0
+}
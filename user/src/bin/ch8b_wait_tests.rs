@@ -0,0 +1,63 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+extern crate user_lib;
+
+use user_lib::{
+    exit, fork, sleep, thread_create, waitpid, waitpid_options, waittid, waittid_options,
+    wexitstatus, wifexited, WNOHANG,
+};
+
+const MAGIC: i32 = 0x1234;
+
+fn user_wait_test_nohang() {
+    let pid = fork();
+    if pid == 0 {
+        sleep(50);
+        exit(7);
+    }
+    let mut exit_code: i32 = 0;
+    // still sleeping, so there is nothing to reap yet
+    assert_eq!(waitpid_options(pid, &mut exit_code, WNOHANG), -2);
+    // blocks until the child exits
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    assert_eq!(wexitstatus(exit_code), 7);
+    // already reaped
+    assert_eq!(waitpid_options(pid, &mut exit_code, WNOHANG), -1);
+}
+
+fn user_wait_test_status() {
+    let pid = fork();
+    if pid == 0 {
+        exit(MAGIC);
+    }
+    let mut exit_code: i32 = 0;
+    assert_eq!(waitpid(pid as usize, &mut exit_code), pid);
+    assert!(wifexited(exit_code));
+    // only the low 8 bits of the exit code are reported
+    assert_eq!(wexitstatus(exit_code), MAGIC & 0xff);
+}
+
+fn sleepy_thread() -> ! {
+    sleep(50);
+    exit(MAGIC)
+}
+
+fn user_wait_test_thread() {
+    let tid = thread_create(sleepy_thread as usize, 0) as usize;
+    assert_eq!(waittid_options(tid, WNOHANG), -2);
+    assert_eq!(waittid(tid), MAGIC as isize);
+    // reaped by the previous call
+    assert_eq!(waittid(tid), -1);
+}
+
+#[no_mangle]
+pub fn main() -> i32 {
+    user_wait_test_nohang();
+    user_wait_test_status();
+    user_wait_test_thread();
+    println!("wait tests passed!");
+    0
+}
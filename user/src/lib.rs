@@ -6,6 +6,7 @@
 #[macro_use]
 pub mod console;
 mod lang_items;
+mod sync;
 mod syscall;
 
 extern crate alloc;
@@ -16,6 +17,8 @@ extern crate bitflags;
 use alloc::vec::Vec;
 use buddy_system_allocator::LockedHeap;
 pub use console::{flush, STDIN, STDOUT};
+use core::sync::atomic::AtomicU32;
+pub use sync::{Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
 pub use syscall::*;
 
 const USER_HEAP_SIZE: usize = 16384;
@@ -82,9 +85,18 @@ bitflags! {
         const RDWR = 1 << 1;
         const CREATE = 1 << 9;
         const TRUNC = 1 << 10;
+        const NONBLOCK = 1 << 11;
+        const CLOEXEC = 1 << 19;
     }
 }
 
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct TimeSpec {
+    pub sec: usize,
+    pub nsec: usize,
+}
+
 #[repr(C)]
 #[derive(Debug, Default)]
 pub struct TimeVal {
@@ -171,6 +183,101 @@ bitflags! {
         const DIR   = 0o040000;
         /// ordinary regular file
         const FILE  = 0o100000;
+        /// named pipe
+        const FIFO  = 0o010000;
+    }
+}
+
+pub const SIGDEF: i32 = 0; // Default signal handling
+pub const SIGHUP: i32 = 1;
+pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
+pub const SIGILL: i32 = 4;
+pub const SIGTRAP: i32 = 5;
+pub const SIGABRT: i32 = 6;
+pub const SIGBUS: i32 = 7;
+pub const SIGFPE: i32 = 8;
+pub const SIGKILL: i32 = 9;
+pub const SIGUSR1: i32 = 10;
+pub const SIGSEGV: i32 = 11;
+pub const SIGUSR2: i32 = 12;
+pub const SIGPIPE: i32 = 13;
+pub const SIGALRM: i32 = 14;
+pub const SIGTERM: i32 = 15;
+pub const SIGSTKFLT: i32 = 16;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGSTOP: i32 = 19;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIGURG: i32 = 23;
+pub const SIGXCPU: i32 = 24;
+pub const SIGXFSZ: i32 = 25;
+pub const SIGVTALRM: i32 = 26;
+pub const SIGPROF: i32 = 27;
+pub const SIGWINCH: i32 = 28;
+pub const SIGIO: i32 = 29;
+pub const SIGPWR: i32 = 30;
+pub const SIGSYS: i32 = 31;
+
+/// Handler value asking for the default action
+pub const SIG_DFL: usize = 0;
+/// Handler value asking for the signal to be discarded
+pub const SIG_IGN: usize = 1;
+
+bitflags! {
+    pub struct SignalFlags: u32 {
+        const SIGDEF = 1; // Default signal handling
+        const SIGHUP = 1 << 1;
+        const SIGINT = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGILL = 1 << 4;
+        const SIGTRAP = 1 << 5;
+        const SIGABRT = 1 << 6;
+        const SIGBUS = 1 << 7;
+        const SIGFPE = 1 << 8;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGSTKFLT = 1 << 16;
+        const SIGCHLD = 1 << 17;
+        const SIGCONT = 1 << 18;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+        const SIGTTIN = 1 << 21;
+        const SIGTTOU = 1 << 22;
+        const SIGURG = 1 << 23;
+        const SIGXCPU = 1 << 24;
+        const SIGXFSZ = 1 << 25;
+        const SIGVTALRM = 1 << 26;
+        const SIGPROF = 1 << 27;
+        const SIGWINCH = 1 << 28;
+        const SIGIO = 1 << 29;
+        const SIGPWR = 1 << 30;
+        const SIGSYS = 1 << 31;
+    }
+}
+
+/// Action for a signal; `handler` is called with the signal number
+/// and must finish with [`sigreturn`]
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    pub handler: usize,
+    pub mask: SignalFlags,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+        }
     }
 }
 
@@ -195,6 +302,13 @@ pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
 
+/// [`link`] across filesystems
+pub const EXDEV: isize = -18;
+/// [`unlink`] of a directory
+pub const EISDIR: isize = -21;
+/// Writing nothing since the filesystem is full
+pub const ENOSPC: isize = -28;
+
 pub fn link(old_path: &str, new_path: &str) -> isize {
     sys_linkat(AT_FDCWD as usize, old_path, AT_FDCWD as usize, new_path, 0)
 }
@@ -203,16 +317,104 @@ pub fn unlink(path: &str) -> isize {
     sys_unlinkat(AT_FDCWD as usize, path, 0)
 }
 
+/// The mode of a named pipe
+const S_IFIFO: u32 = 0o010000;
+/// No reader on a named pipe opened for writing with [`OpenFlags::NONBLOCK`]
+pub const ENXIO: isize = -6;
+
+/// Create a named pipe, fails with [`EEXIST`] if `path` exists already
+pub fn mkfifo(path: &str) -> isize {
+    sys_mknodat(AT_FDCWD as usize, path, S_IFIFO | 0o666, 0)
+}
+
+/// No filesystem of the type given to [`mount`]
+pub const ENODEV: isize = -19;
+pub const ENOTDIR: isize = -20;
+
+/// Mount a filesystem of type `fstype` made from `source` at `target`, with
+/// the comma-separated options in `data` such as `size=64k` of tmpfs
+pub fn mount(source: &str, target: &str, fstype: &str, data: &str) -> isize {
+    sys_mount(source, target, fstype, 0, data)
+}
+
+/// Unmount the filesystem at `target`, fails with [`EBUSY`] while it is in use
+pub fn umount(target: &str) -> isize {
+    sys_umount2(target, 0)
+}
+
 pub fn fstat(fd: usize, st: &Stat) -> isize {
     sys_fstat(fd, st)
 }
 
+/// Messages a mailbox holds at most
+pub const MAIL_SLOTS: usize = 16;
+/// Longer messages are truncated to this many bytes
+pub const MAIL_MAX_LEN: usize = 256;
+/// Error returned when sending to a process that does not exist
+pub const ESRCH: isize = -3;
+
+const MAIL_NONBLOCK: usize = 1;
+
+/// Take the oldest message of this process, fails with [`EAGAIN`] if there is none
+///
+/// With an empty `buf`, only tells whether a message is waiting.
 pub fn mail_read(buf: &mut [u8]) -> isize {
-    sys_mail_read(buf)
+    sys_mail_read(buf, MAIL_NONBLOCK)
 }
 
+/// Send a message to process `pid`, fails with [`EAGAIN`] if its mailbox is full
 pub fn mail_write(pid: usize, buf: &[u8]) -> isize {
-    sys_mail_write(pid, buf)
+    sys_mail_write(pid, buf, MAIL_NONBLOCK)
+}
+
+/// Like [`mail_read`], but wait for a message to arrive
+pub fn mail_read_wait(buf: &mut [u8]) -> isize {
+    sys_mail_read(buf, 0)
+}
+
+/// Like [`mail_write`], but wait for a free slot
+pub fn mail_write_wait(pid: usize, buf: &[u8]) -> isize {
+    sys_mail_write(pid, buf, 0)
+}
+
+/// Open the mailbox of this process as a file descriptor to [`poll`]
+///
+/// Reading it takes a message, waiting for one unless `nonblock`.
+pub fn mailfd(nonblock: bool) -> isize {
+    sys_mailfd(if nonblock { MAIL_NONBLOCK } else { 0 })
+}
+
+/// Key of a new segment only found through its id
+pub const IPC_PRIVATE: usize = 0;
+/// Create the segment if there is none with the key
+pub const IPC_CREAT: usize = 0o1000;
+/// Fail with [`EEXIST`] if the segment exists already
+pub const IPC_EXCL: usize = 0o2000;
+/// Attach the segment read-only
+pub const SHM_RDONLY: usize = 0o10000;
+const IPC_RMID: usize = 0;
+/// Error returned when looking up a segment that does not exist
+pub const ENOENT: isize = -2;
+/// Error returned when creating a segment that exists already
+pub const EEXIST: isize = -17;
+/// Error returned for a bad segment id or address
+pub const EINVAL: isize = -22;
+
+/// Id of the shared memory segment of `key`, of at least `size` bytes
+pub fn shmget(key: usize, size: usize, flags: usize) -> isize {
+    sys_shmget(key, size, flags)
+}
+/// Remove a segment, which goes away once no process has it attached
+pub fn shmrm(id: usize) -> isize {
+    sys_shmctl(id, IPC_RMID)
+}
+/// Map a segment at `addr`, or wherever there is room if it is 0, returns the address
+pub fn shmat(id: usize, addr: usize, flags: usize) -> isize {
+    sys_shmat(id, addr, flags)
+}
+/// Unmap the segment attached at `addr`
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
 }
 
 pub fn exit(exit_code: i32) -> ! {
@@ -220,6 +422,12 @@ pub fn exit(exit_code: i32) -> ! {
     sys_exit(exit_code);
 }
 
+/// Exit the whole process, not only the calling thread
+pub fn exit_group(exit_code: i32) -> ! {
+    console::flush();
+    sys_exit_group(exit_code);
+}
+
 pub fn yield_() -> isize {
     sys_yield()
 }
@@ -248,23 +456,54 @@ pub fn set_priority(prio: isize) -> isize {
     sys_set_priority(prio)
 }
 
+/// Return -2 from `waitpid`/`waittid` instead of blocking if the target is still running
+pub const WNOHANG: usize = 1;
+/// Also return from `waitpid` when a child is stopped, see [`wifstopped`]
+pub const WUNTRACED: usize = 2;
+
+/// Whether the child exited normally, see [`wexitstatus`]
+pub fn wifexited(status: i32) -> bool {
+    status & 0x7f == 0
+}
+
+/// Exit code passed to `exit` by a child that exited normally
+pub fn wexitstatus(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
+/// Whether the child was terminated by a signal, see [`wtermsig`]
+pub fn wifsignaled(status: i32) -> bool {
+    status & 0x7f != 0 && !wifstopped(status)
+}
+
+/// Signal that terminated the child
+pub fn wtermsig(status: i32) -> i32 {
+    status & 0x7f
+}
+
+/// Whether the child is stopped, only reported with [`WUNTRACED`]
+pub fn wifstopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+
+/// Signal that stopped the child
+pub fn wstopsig(status: i32) -> i32 {
+    (status >> 8) & 0xff
+}
+
 pub fn wait(exit_code: &mut i32) -> isize {
-    loop {
-        match sys_waitpid(-1, exit_code as *mut _) {
-            -2 => {
-                sys_yield();
-            }
-            n => {
-                return n;
-            }
-        }
-    }
+    waitpid_options(-1, exit_code, 0)
 }
 
 pub fn waitpid(pid: usize, exit_code: &mut i32) -> isize {
+    waitpid_options(pid as isize, exit_code, 0)
+}
+
+pub fn waitpid_options(pid: isize, exit_code: &mut i32, options: usize) -> isize {
     loop {
-        match sys_waitpid(pid as isize, exit_code as *mut _) {
-            -2 => {
+        match sys_waitpid(pid, exit_code as *mut _, options) {
+            // kernels before ch8 never block and always report -2
+            -2 if options & WNOHANG == 0 => {
                 sys_yield();
             }
             n => {
@@ -284,6 +523,127 @@ pub fn sleep(period_ms: usize) {
         sys_yield();
     }
 }
+pub fn kill(pid: usize, signum: i32) -> isize {
+    sys_kill(pid as isize, signum)
+}
+
+/// Send `signum` to every process in group `pgid`
+pub fn killpg(pgid: usize, signum: i32) -> isize {
+    sys_kill(-(pgid as isize), signum)
+}
+
+/// Move process `pid` into group `pgid`, a new group is created if they are equal
+pub fn setpgid(pid: usize, pgid: usize) -> isize {
+    sys_setpgid(pid, pgid)
+}
+
+pub fn getpgid(pid: usize) -> isize {
+    sys_getpgid(pid)
+}
+
+pub fn setsid() -> isize {
+    sys_setsid()
+}
+
+pub const TIOCGPGRP: usize = 0x540f;
+pub const TIOCSPGRP: usize = 0x5410;
+
+/// Foreground process group of the terminal `fd`
+pub fn tcgetpgrp(fd: usize) -> isize {
+    let mut pgid: i32 = 0;
+    match sys_ioctl(fd, TIOCGPGRP, &mut pgid as *mut _ as usize) {
+        0 => pgid as isize,
+        err => err,
+    }
+}
+
+/// Make `pgid` the foreground process group of the terminal `fd`
+pub fn tcsetpgrp(fd: usize, pgid: usize) -> isize {
+    let pgid = pgid as i32;
+    sys_ioctl(fd, TIOCSPGRP, &pgid as *const _ as usize)
+}
+
+pub const TCGETS: usize = 0x5401;
+pub const TCSETS: usize = 0x5402;
+pub const TCSETSW: usize = 0x5403;
+pub const TCSETSF: usize = 0x5404;
+
+/// `Termios::iflag`: translate carriage return to newline
+pub const ICRNL: u32 = 0o400;
+/// `Termios::oflag`: process the output
+pub const OPOST: u32 = 0o1;
+/// `Termios::oflag`: translate newline to carriage return and newline
+pub const ONLCR: u32 = 0o4;
+/// `Termios::lflag`: turn ^C, ^\ and ^Z into signals
+pub const ISIG: u32 = 0o1;
+/// `Termios::lflag`: canonical mode, input is edited and read by lines
+pub const ICANON: u32 = 0o2;
+/// `Termios::lflag`: echo the input
+pub const ECHO: u32 = 0o10;
+pub const ECHOE: u32 = 0o20;
+pub const ECHOK: u32 = 0o40;
+
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSUSP: usize = 10;
+
+/// Terminal attributes
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Termios {
+    pub iflag: u32,
+    pub oflag: u32,
+    pub cflag: u32,
+    pub lflag: u32,
+    pub line: u8,
+    pub cc: [u8; 19],
+}
+
+impl Termios {
+    /// Turn off line editing, echo and signal characters, reads return each byte
+    pub fn make_raw(&mut self) {
+        self.iflag &= !ICRNL;
+        self.lflag &= !(ICANON | ECHO | ISIG);
+        self.cc[VMIN] = 1;
+        self.cc[VTIME] = 0;
+    }
+}
+
+/// Attributes of the terminal `fd`
+pub fn tcgetattr(fd: usize, termios: &mut Termios) -> isize {
+    sys_ioctl(fd, TCGETS, termios as *mut _ as usize)
+}
+
+/// Set the attributes of the terminal `fd`, `request` is one of the `TCSETS*`
+pub fn tcsetattr(fd: usize, request: usize, termios: &Termios) -> isize {
+    sys_ioctl(fd, request, termios as *const _ as usize)
+}
+
+pub fn sigaction(
+    signum: i32,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |a| a),
+        old_action.map_or(core::ptr::null_mut(), |a| a),
+    )
+}
+
+pub fn sigprocmask(mask: u32) -> isize {
+    sys_sigprocmask(mask)
+}
+
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
 pub fn mmap(start: usize, len: usize, prot: usize) -> isize {
     sys_mmap(start, len, prot)
 }
@@ -299,10 +659,93 @@ pub fn spawn(path: &str) -> isize {
 pub fn dup(fd: usize) -> isize {
     sys_dup(fd)
 }
+
+/// Make `new_fd` refer to the file of `old_fd`, closing it first if open
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    if old_fd == new_fd {
+        // nothing to do, as long as it is open
+        let ret = fcntl(old_fd, F_GETFD, 0);
+        return if ret < 0 { ret } else { new_fd as isize };
+    }
+    sys_dup3(old_fd, new_fd, 0)
+}
+
+/// Like [`dup2`], `flags` may be [`OpenFlags::CLOEXEC`]
+pub fn dup3(old_fd: usize, new_fd: usize, flags: OpenFlags) -> isize {
+    sys_dup3(old_fd, new_fd, flags.bits)
+}
+
 pub fn pipe(pipe_fd: &mut [usize]) -> isize {
     sys_pipe(pipe_fd)
 }
 
+/// Set the capacity of a pipe, rounded up to whole pages, returns the new one
+pub const F_SETPIPE_SZ: usize = 1031;
+/// Get the capacity of a pipe
+pub const F_GETPIPE_SZ: usize = 1032;
+/// Error returned when writing to a pipe whose read ends are all closed,
+/// if SIGPIPE does not kill the writer
+pub const EPIPE: isize = -32;
+
+pub fn fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    sys_fcntl(fd, cmd, arg)
+}
+
+/// Duplicate a file to the lowest free descriptor not below the argument
+pub const F_DUPFD: usize = 0;
+/// Get the descriptor flags
+pub const F_GETFD: usize = 1;
+/// Set the descriptor flags
+pub const F_SETFD: usize = 2;
+/// Like [`F_DUPFD`], with [`FD_CLOEXEC`] set on the new descriptor
+pub const F_DUPFD_CLOEXEC: usize = 1030;
+/// The descriptor is closed by exec
+pub const FD_CLOEXEC: usize = 1;
+/// Bad file descriptor
+pub const EBADF: isize = -9;
+/// Too many open files
+pub const EMFILE: isize = -24;
+
+/// Get the [`OpenFlags`] of a file
+pub const F_GETFL: usize = 3;
+/// Set the [`OpenFlags`] of a file, only `NONBLOCK` can change
+pub const F_SETFL: usize = 4;
+
+pub const POLLIN: i16 = 0x1;
+pub const POLLPRI: i16 = 0x2;
+pub const POLLOUT: i16 = 0x4;
+pub const POLLERR: i16 = 0x8;
+pub const POLLHUP: i16 = 0x10;
+pub const POLLNVAL: i16 = 0x20;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: i16) -> Self {
+        Self {
+            fd: fd as i32,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// Wait until one of `fds` is ready, for at most `timeout_ms` milliseconds
+/// unless it is `None`, returns how many are
+pub fn poll(fds: &mut [PollFd], timeout_ms: Option<usize>) -> isize {
+    let timeout = timeout_ms.map(|ms| TimeSpec {
+        sec: ms / 1000,
+        nsec: ms % 1000 * 1_000_000,
+    });
+    sys_ppoll(fds, timeout.as_ref())
+}
+
 pub fn task_info(info: &TaskInfo) -> isize {
     sys_task_info(info)
 }
@@ -314,27 +757,56 @@ pub fn gettid() -> isize {
     sys_gettid()
 }
 pub fn waittid(tid: usize) -> isize {
-    loop {
-        match sys_waittid(tid) {
-            -2 => {
-                yield_();
-            }
-            exit_code => return exit_code,
-        }
-    }
+    sys_waittid(tid, 0)
+}
+pub fn waittid_options(tid: usize, options: usize) -> isize {
+    sys_waittid(tid, options)
+}
+
+/// Exit code reported by `waittid` for a cancelled thread
+pub const THREAD_CANCELED: isize = -0xca;
+
+/// Free thread `tid` as soon as it exits, it can no longer be waited for
+pub fn thread_detach(tid: usize) -> isize {
+    sys_thread_detach(tid)
+}
+/// Make thread `tid` exit with [`THREAD_CANCELED`] the next time it enters the kernel
+pub fn thread_cancel(tid: usize) -> isize {
+    sys_thread_cancel(tid)
 }
 
+const MUTEX_SPIN: usize = 0;
+const MUTEX_BLOCKING: usize = 1;
+const MUTEX_RECURSIVE: usize = 2;
+
 pub fn mutex_create() -> isize {
-    sys_mutex_create(false)
+    sys_mutex_create(MUTEX_SPIN)
 }
 pub fn mutex_blocking_create() -> isize {
-    sys_mutex_create(true)
+    sys_mutex_create(MUTEX_BLOCKING)
+}
+/// Create a blocking mutex its holder can lock again, it has to unlock it as many times
+pub fn mutex_recursive_create() -> isize {
+    sys_mutex_create(MUTEX_RECURSIVE)
 }
 pub fn mutex_lock(mutex_id: usize) -> isize {
     sys_mutex_lock(mutex_id)
 }
-pub fn mutex_unlock(mutex_id: usize) {
-    sys_mutex_unlock(mutex_id);
+/// Take the mutex only if it is free, fails with [`EBUSY`] otherwise
+pub fn mutex_trylock(mutex_id: usize) -> isize {
+    sys_mutex_trylock(mutex_id, 0)
+}
+/// Wait for the mutex at most `timeout_ms` milliseconds, fails with [`ETIMEDOUT`] then
+pub fn mutex_timedlock(mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_mutex_trylock(mutex_id, timeout_ms.max(1))
+}
+/// Fails with [`EPERM`] if the calling thread does not hold the mutex
+pub fn mutex_unlock(mutex_id: usize) -> isize {
+    sys_mutex_unlock(mutex_id)
+}
+/// Free the mutex, fails with [`EBUSY`] while it is held
+pub fn mutex_destroy(mutex_id: usize) -> isize {
+    sys_mutex_destroy(mutex_id)
 }
 pub fn semaphore_create(res_count: usize) -> isize {
     sys_semaphore_create(res_count)
@@ -342,18 +814,123 @@ pub fn semaphore_create(res_count: usize) -> isize {
 pub fn semaphore_up(sem_id: usize) {
     sys_semaphore_up(sem_id);
 }
+/// Free the semaphore, fails with [`EBUSY`] while threads wait for it
+pub fn semaphore_destroy(sem_id: usize) -> isize {
+    sys_semaphore_destroy(sem_id)
+}
 pub fn enable_deadlock_detect(enabled: bool) -> isize {
     sys_enable_deadlock_detect(enabled as usize)
 }
+
+/// Error returned when releasing a lock the calling thread does not hold
+pub const EPERM: isize = -1;
+/// Error returned by `mutex_trylock` when the mutex is held, and when
+/// destroying an object still in use
+pub const EBUSY: isize = -16;
+/// Error returned by a blocking call that would never return
+pub const EDEADLK: isize = -0xdead;
+/// Let threads blocked across processes hang, the default
+pub const DEADLOCK_IGNORE: usize = 0;
+/// Fail the blocking call that closes a cycle with [`EDEADLK`]
+pub const DEADLOCK_RETURN_ERROR: usize = 1;
+/// Also kill the process making that call
+pub const DEADLOCK_KILL_VICTIM: usize = 2;
+
+/// Choose how the kernel-wide wait-for graph handles deadlocks of this
+/// process, which its children inherit
+pub fn set_deadlock_policy(policy: usize) -> isize {
+    sys_set_deadlock_policy(policy)
+}
 pub fn semaphore_down(sem_id: usize) -> isize {
     sys_semaphore_down(sem_id)
 }
+/// Wait for a unit at most `timeout_ms` milliseconds, fails with [`ETIMEDOUT`] then
+pub fn semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    sys_semaphore_down_timeout(sem_id, timeout_ms)
+}
 pub fn condvar_create() -> isize {
     sys_condvar_create(0)
 }
 pub fn condvar_signal(condvar_id: usize) {
     sys_condvar_signal(condvar_id);
 }
-pub fn condvar_wait(condvar_id: usize, mutex_id: usize) {
-    sys_condvar_wait(condvar_id, mutex_id);
+/// Wake up all threads waiting on the condvar
+pub fn condvar_broadcast(condvar_id: usize) {
+    sys_condvar_broadcast(condvar_id);
+}
+/// Free the condvar, fails with [`EBUSY`] while threads wait on it
+pub fn condvar_destroy(condvar_id: usize) -> isize {
+    sys_condvar_destroy(condvar_id)
+}
+pub fn condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
+    sys_condvar_wait(condvar_id, mutex_id)
+}
+
+/// Wait for a signal at most `timeout_ms` milliseconds, fails with [`ETIMEDOUT`]
+/// then, holding the mutex again either way
+pub fn condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    sys_condvar_wait_timeout(condvar_id, mutex_id, timeout_ms)
+}
+
+pub fn rwlock_create() -> isize {
+    sys_rwlock_create()
+}
+/// Take a reader-writer lock shared, waiting while a writer holds it or waits for it
+pub fn rwlock_read(rwlock_id: usize) -> isize {
+    sys_rwlock_read(rwlock_id)
+}
+pub fn rwlock_write(rwlock_id: usize) -> isize {
+    sys_rwlock_write(rwlock_id)
+}
+/// Release the read or write lock the calling thread holds
+pub fn rwlock_unlock(rwlock_id: usize) -> isize {
+    sys_rwlock_unlock(rwlock_id)
+}
+/// Create a barrier for `count` threads
+pub fn barrier_create(count: usize) -> isize {
+    sys_barrier_create(count)
+}
+/// Wait until `count` threads wait at the barrier, returns 1 in the last one and 0 in the others
+pub fn barrier_wait(barrier_id: usize) -> isize {
+    sys_barrier_wait(barrier_id)
+}
+
+/// Error returned by `futex_wait` when the word does not hold the value
+pub const EAGAIN: isize = -11;
+/// Error returned by timed waits when the timeout expires
+pub const ETIMEDOUT: isize = -110;
+
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+const FUTEX_REQUEUE: usize = 3;
+
+/// Sleep as long as `word` holds `val`, for at most `timeout_ms` milliseconds unless it is 0
+pub fn futex_wait(word: &AtomicU32, val: u32, timeout_ms: usize) -> isize {
+    sys_futex(
+        word as *const _ as *const u32,
+        FUTEX_WAIT,
+        val as usize,
+        timeout_ms,
+        core::ptr::null(),
+    )
+}
+/// Wake up to `count` threads sleeping on `word`, returning how many were
+pub fn futex_wake(word: &AtomicU32, count: usize) -> isize {
+    sys_futex(
+        word as *const _ as *const u32,
+        FUTEX_WAKE,
+        count,
+        0,
+        core::ptr::null(),
+    )
+}
+/// Wake up to `count` threads sleeping on `word` and move up to `requeue` others to `word2`
+pub fn futex_requeue(word: &AtomicU32, count: usize, word2: &AtomicU32, requeue: usize) -> isize {
+    sys_futex(
+        word as *const _ as *const u32,
+        FUTEX_REQUEUE,
+        count,
+        requeue,
+        word2 as *const _ as *const u32,
+    )
 }
@@ -0,0 +1,269 @@
+//! Locks living in user memory, built on atomics and [`futex_wait`]/[`futex_wake`]
+//!
+//! They only enter the kernel when a thread has to sleep or to wake up
+//! another one, so uncontended operations never trap.
+
+use super::{futex_requeue, futex_wait, futex_wake, ETIMEDOUT};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked, and some threads may be sleeping on the lock
+const CONTENDED: u32 = 2;
+
+/// Wake up all threads sleeping on a futex
+const ALL: usize = i32::MAX as usize;
+
+/// Mutual exclusion lock protecting a `T`
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_contended();
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    /// Take the lock assuming others are sleeping on it, so that the
+    /// unlock wakes one of them up
+    fn lock_contended(&self) {
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            futex_wait(&self.state, CONTENDED, 0);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Condition variable, to be used with a [`Mutex`]
+pub struct Condvar {
+    /// Bumped by every notification, so that a waiter cannot miss one made
+    /// between unlocking the mutex and going to sleep
+    seq: AtomicU32,
+    /// State of the mutex the waiters use, for `notify_all` to requeue them
+    mutex: AtomicPtr<AtomicU32>,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+            mutex: AtomicPtr::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// Unlock the mutex of `guard`, sleep until notified, then lock it again
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        self.wait_timeout(guard, 0).0
+    }
+
+    /// Like [`Self::wait`], but give up after `timeout_ms` milliseconds
+    /// unless it is 0
+    ///
+    /// Also returns whether the time ran out.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout_ms: usize,
+    ) -> (MutexGuard<'a, T>, bool) {
+        let mutex = guard.mutex;
+        self.mutex.store(
+            &mutex.state as *const AtomicU32 as *mut AtomicU32,
+            Ordering::Relaxed,
+        );
+        let seq = self.seq.load(Ordering::Relaxed);
+        drop(guard);
+        let timed_out = futex_wait(&self.seq, seq, timeout_ms) == ETIMEDOUT;
+        // notify_all may have moved other waiters onto the mutex
+        mutex.lock_contended();
+        (MutexGuard { mutex }, timed_out)
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.seq, 1);
+    }
+
+    /// Wake up all waiters
+    ///
+    /// Only one is woken up, the others are moved to the mutex and get it
+    /// one after the other instead of all racing for it.
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Relaxed);
+        let mutex = self.mutex.load(Ordering::Relaxed);
+        if mutex.is_null() {
+            futex_wake(&self.seq, ALL);
+        } else {
+            futex_requeue(&self.seq, 1, unsafe { &*mutex }, ALL);
+        }
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reader-writer lock protecting a `T`
+pub struct RwLock<T> {
+    /// Number of readers, or `WRITE_LOCKED`
+    state: AtomicU32,
+    /// Threads sleeping on `state`, so that unlocks only wake up when needed
+    waiters: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+const WRITE_LOCKED: u32 = u32::MAX;
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+pub struct RwLockReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+pub struct RwLockWriteGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<T> RwLock<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(0),
+            waiters: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, T> {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state == WRITE_LOCKED {
+                self.sleep(state);
+            } else if self
+                .state
+                .compare_exchange(state, state + 1, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                return RwLockReadGuard { lock: self };
+            }
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, T> {
+        loop {
+            match self
+                .state
+                .compare_exchange(0, WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            {
+                Ok(_) => return RwLockWriteGuard { lock: self },
+                Err(state) => self.sleep(state),
+            }
+        }
+    }
+
+    /// Sleep until `state` changes
+    fn sleep(&self, state: u32) {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        futex_wait(&self.state, state, 0);
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// `state` was just changed, wake up everyone waiting for it to
+    fn wake(&self) {
+        if self.waiters.load(Ordering::SeqCst) != 0 {
+            futex_wake(&self.state, ALL);
+        }
+    }
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T> {
+    fn drop(&mut self) {
+        // only writers wait for readers to leave
+        if self.lock.state.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.lock.wake();
+        }
+    }
+}
+
+impl<T> Deref for RwLockWriteGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.state.store(0, Ordering::SeqCst);
+        self.lock.wake();
+    }
+}
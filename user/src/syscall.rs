@@ -1,20 +1,38 @@
 use crate::TaskInfo;
 
-use super::{Stat, TimeVal};
+use super::{PollFd, SignalAction, Stat, TimeSpec, TimeVal};
 
 pub const SYSCALL_OPENAT: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_IOCTL: usize = 29;
+pub const SYSCALL_MKNODAT: usize = 33;
 pub const SYSCALL_UNLINKAT: usize = 35;
 pub const SYSCALL_LINKAT: usize = 37;
+pub const SYSCALL_UMOUNT2: usize = 39;
+pub const SYSCALL_MOUNT: usize = 40;
+pub const SYSCALL_PPOLL: usize = 73;
 pub const SYSCALL_FSTAT: usize = 80;
 pub const SYSCALL_EXIT: usize = 93;
+pub const SYSCALL_EXIT_GROUP: usize = 94;
+pub const SYSCALL_FUTEX: usize = 98;
 pub const SYSCALL_SLEEP: usize = 101;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGPROCMASK: usize = 135;
+pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETPGID: usize = 154;
+pub const SYSCALL_GETPGID: usize = 155;
+pub const SYSCALL_SETSID: usize = 157;
 pub const SYSCALL_GETTIMEOFDAY: usize = 169;
 pub const SYSCALL_GETPID: usize = 172;
 pub const SYSCALL_GETTID: usize = 178;
+pub const SYSCALL_SHMGET: usize = 194;
+pub const SYSCALL_SHMCTL: usize = 195;
+pub const SYSCALL_SHMAT: usize = 196;
+pub const SYSCALL_SHMDT: usize = 197;
 pub const SYSCALL_FORK: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
 pub const SYSCALL_WAITPID: usize = 260;
@@ -24,13 +42,17 @@ pub const SYSCALL_MMAP: usize = 222;
 pub const SYSCALL_SPAWN: usize = 400;
 pub const SYSCALL_MAIL_READ: usize = 401;
 pub const SYSCALL_MAIL_WRITE: usize = 402;
+pub const SYSCALL_MAILFD: usize = 404;
 pub const SYSCALL_DUP: usize = 24;
+pub const SYSCALL_DUP3: usize = 403;
+pub const SYSCALL_FCNTL: usize = 25;
 pub const SYSCALL_PIPE: usize = 59;
 pub const SYSCALL_TASK_INFO: usize = 410;
 pub const SYSCALL_THREAD_CREATE: usize = 460;
 pub const SYSCALL_WAITTID: usize = 462;
 pub const SYSCALL_MUTEX_CREATE: usize = 463;
 pub const SYSCALL_MUTEX_LOCK: usize = 464;
+pub const SYSCALL_MUTEX_TRYLOCK: usize = 465;
 pub const SYSCALL_MUTEX_UNLOCK: usize = 466;
 pub const SYSCALL_SEMAPHORE_CREATE: usize = 467;
 pub const SYSCALL_SEMAPHORE_UP: usize = 468;
@@ -39,6 +61,21 @@ pub const SYSCALL_SEMAPHORE_DOWN: usize = 470;
 pub const SYSCALL_CONDVAR_CREATE: usize = 471;
 pub const SYSCALL_CONDVAR_SIGNAL: usize = 472;
 pub const SYSCALL_CONDVAR_WAIT: usize = 473;
+pub const SYSCALL_THREAD_DETACH: usize = 474;
+pub const SYSCALL_THREAD_CANCEL: usize = 475;
+pub const SYSCALL_SET_DEADLOCK_POLICY: usize = 476;
+pub const SYSCALL_RWLOCK_CREATE: usize = 477;
+pub const SYSCALL_RWLOCK_READ: usize = 478;
+pub const SYSCALL_RWLOCK_WRITE: usize = 479;
+pub const SYSCALL_RWLOCK_UNLOCK: usize = 480;
+pub const SYSCALL_BARRIER_CREATE: usize = 481;
+pub const SYSCALL_BARRIER_WAIT: usize = 482;
+pub const SYSCALL_SEMAPHORE_DOWN_TIMEOUT: usize = 483;
+pub const SYSCALL_CONDVAR_WAIT_TIMEOUT: usize = 484;
+pub const SYSCALL_CONDVAR_BROADCAST: usize = 485;
+pub const SYSCALL_MUTEX_DESTROY: usize = 486;
+pub const SYSCALL_SEMAPHORE_DESTROY: usize = 487;
+pub const SYSCALL_CONDVAR_DESTROY: usize = 488;
 
 pub fn syscall(id: usize, args: [usize; 3]) -> isize {
     let mut ret: isize;
@@ -119,6 +156,31 @@ pub fn sys_linkat(
     )
 }
 
+pub fn sys_mknodat(dirfd: usize, path: &str, mode: u32, dev: usize) -> isize {
+    syscall6(
+        SYSCALL_MKNODAT,
+        [dirfd, path.as_ptr() as usize, mode as usize, dev, 0, 0],
+    )
+}
+
+pub fn sys_umount2(target: &str, flags: usize) -> isize {
+    syscall(SYSCALL_UMOUNT2, [target.as_ptr() as usize, flags, 0])
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: &str, flags: usize, data: &str) -> isize {
+    syscall6(
+        SYSCALL_MOUNT,
+        [
+            source.as_ptr() as usize,
+            target.as_ptr() as usize,
+            fstype.as_ptr() as usize,
+            flags,
+            data.as_ptr() as usize,
+            0,
+        ],
+    )
+}
+
 pub fn sys_unlinkat(dirfd: usize, path: &str, flags: usize) -> isize {
     syscall(SYSCALL_UNLINKAT, [dirfd, path.as_ptr() as usize, flags])
 }
@@ -127,25 +189,63 @@ pub fn sys_fstat(fd: usize, st: &Stat) -> isize {
     syscall(SYSCALL_FSTAT, [fd, st as *const _ as usize, 0])
 }
 
-pub fn sys_mail_read(buffer: &mut [u8]) -> isize {
+pub fn sys_mail_read(buffer: &mut [u8], flags: usize) -> isize {
     syscall(
         SYSCALL_MAIL_READ,
-        [buffer.as_ptr() as usize, buffer.len(), 0],
+        [buffer.as_ptr() as usize, buffer.len(), flags],
     )
 }
 
-pub fn sys_mail_write(pid: usize, buffer: &[u8]) -> isize {
-    syscall(
+pub fn sys_mail_write(pid: usize, buffer: &[u8], flags: usize) -> isize {
+    syscall6(
         SYSCALL_MAIL_WRITE,
-        [pid, buffer.as_ptr() as usize, buffer.len()],
+        [pid, buffer.as_ptr() as usize, buffer.len(), flags, 0, 0],
     )
 }
 
+pub fn sys_mailfd(flags: usize) -> isize {
+    syscall(SYSCALL_MAILFD, [flags, 0, 0])
+}
+
+pub fn sys_shmget(key: usize, size: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, flags])
+}
+
+pub fn sys_shmctl(id: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [id, cmd, 0])
+}
+
+pub fn sys_shmat(id: usize, addr: usize, flags: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [id, addr, flags])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0])
+}
+
 pub fn sys_exit(exit_code: i32) -> ! {
     syscall(SYSCALL_EXIT, [exit_code as usize, 0, 0]);
     panic!("sys_exit never returns!");
 }
 
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    syscall(SYSCALL_EXIT_GROUP, [exit_code as usize, 0, 0]);
+    panic!("sys_exit_group never returns!");
+}
+
+pub fn sys_futex(
+    uaddr: *const u32,
+    op: usize,
+    val: usize,
+    arg: usize,
+    uaddr2: *const u32,
+) -> isize {
+    syscall6(
+        SYSCALL_FUTEX,
+        [uaddr as usize, op, val, arg, uaddr2 as usize, 0],
+    )
+}
+
 pub fn sys_sleep(sleep_ms: usize) -> isize {
     syscall(SYSCALL_SLEEP, [sleep_ms, 0, 0])
 }
@@ -154,6 +254,45 @@ pub fn sys_yield() -> isize {
     syscall(SYSCALL_YIELD, [0, 0, 0])
 }
 
+pub fn sys_kill(pid: isize, signal: i32) -> isize {
+    syscall(SYSCALL_KILL, [pid as usize, signal as usize, 0])
+}
+
+pub fn sys_sigaction(
+    signum: i32,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    syscall(
+        SYSCALL_SIGACTION,
+        [signum as usize, action as usize, old_action as usize],
+    )
+}
+
+pub fn sys_sigprocmask(mask: u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [mask as usize, 0, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0, 0, 0])
+}
+
+pub fn sys_setpgid(pid: usize, pgid: usize) -> isize {
+    syscall(SYSCALL_SETPGID, [pid, pgid, 0])
+}
+
+pub fn sys_getpgid(pid: usize) -> isize {
+    syscall(SYSCALL_GETPGID, [pid, 0, 0])
+}
+
+pub fn sys_setsid() -> isize {
+    syscall(SYSCALL_SETSID, [0, 0, 0])
+}
+
+pub fn sys_ioctl(fd: usize, request: usize, arg: usize) -> isize {
+    syscall(SYSCALL_IOCTL, [fd, request, arg])
+}
+
 pub fn sys_get_time(time: &TimeVal, tz: usize) -> isize {
     syscall(SYSCALL_GETTIMEOFDAY, [time as *const _ as usize, tz, 0])
 }
@@ -173,8 +312,8 @@ pub fn sys_exec(path: &str, args: &[*const u8]) -> isize {
     )
 }
 
-pub fn sys_waitpid(pid: isize, xstatus: *mut i32) -> isize {
-    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, 0])
+pub fn sys_waitpid(pid: isize, xstatus: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, xstatus as usize, options])
 }
 
 pub fn sys_set_priority(prio: isize) -> isize {
@@ -197,6 +336,28 @@ pub fn sys_dup(fd: usize) -> isize {
     syscall(SYSCALL_DUP, [fd, 0, 0])
 }
 
+pub fn sys_dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYSCALL_DUP3, [old_fd, new_fd, flags as usize])
+}
+
+pub fn sys_fcntl(fd: usize, cmd: usize, arg: usize) -> isize {
+    syscall(SYSCALL_FCNTL, [fd, cmd, arg])
+}
+
+pub fn sys_ppoll(fds: &mut [PollFd], timeout: Option<&TimeSpec>) -> isize {
+    syscall6(
+        SYSCALL_PPOLL,
+        [
+            fds.as_mut_ptr() as usize,
+            fds.len(),
+            timeout.map_or(0, |timeout| timeout as *const _ as usize),
+            0,
+            0,
+            0,
+        ],
+    )
+}
+
 pub fn sys_pipe(pipe: &mut [usize]) -> isize {
     syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0])
 }
@@ -213,22 +374,38 @@ pub fn sys_gettid() -> isize {
     syscall(SYSCALL_GETTID, [0; 3])
 }
 
-pub fn sys_waittid(tid: usize) -> isize {
-    syscall(SYSCALL_WAITTID, [tid, 0, 0])
+pub fn sys_waittid(tid: usize, options: usize) -> isize {
+    syscall(SYSCALL_WAITTID, [tid, options, 0])
+}
+
+pub fn sys_thread_detach(tid: usize) -> isize {
+    syscall(SYSCALL_THREAD_DETACH, [tid, 0, 0])
+}
+
+pub fn sys_thread_cancel(tid: usize) -> isize {
+    syscall(SYSCALL_THREAD_CANCEL, [tid, 0, 0])
 }
 
-pub fn sys_mutex_create(blocking: bool) -> isize {
-    syscall(SYSCALL_MUTEX_CREATE, [blocking as usize, 0, 0])
+pub fn sys_mutex_create(kind: usize) -> isize {
+    syscall(SYSCALL_MUTEX_CREATE, [kind, 0, 0])
 }
 
 pub fn sys_mutex_lock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_LOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_trylock(id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_MUTEX_TRYLOCK, [id, timeout_ms, 0])
+}
+
 pub fn sys_mutex_unlock(id: usize) -> isize {
     syscall(SYSCALL_MUTEX_UNLOCK, [id, 0, 0])
 }
 
+pub fn sys_mutex_destroy(id: usize) -> isize {
+    syscall(SYSCALL_MUTEX_DESTROY, [id, 0, 0])
+}
+
 pub fn sys_semaphore_create(res_count: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_CREATE, [res_count, 0, 0])
 }
@@ -237,14 +414,26 @@ pub fn sys_semaphore_up(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_UP, [sem_id, 0, 0])
 }
 
+pub fn sys_semaphore_destroy(sem_id: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DESTROY, [sem_id, 0, 0])
+}
+
 pub fn sys_enable_deadlock_detect(enabled: usize) -> isize {
     syscall(SYSCALL_ENABLE_DEADLOCK_DETECT, [enabled, 0, 0])
 }
 
+pub fn sys_set_deadlock_policy(policy: usize) -> isize {
+    syscall(SYSCALL_SET_DEADLOCK_POLICY, [policy, 0, 0])
+}
+
 pub fn sys_semaphore_down(sem_id: usize) -> isize {
     syscall(SYSCALL_SEMAPHORE_DOWN, [sem_id, 0, 0])
 }
 
+pub fn sys_semaphore_down_timeout(sem_id: usize, timeout_ms: usize) -> isize {
+    syscall(SYSCALL_SEMAPHORE_DOWN_TIMEOUT, [sem_id, timeout_ms, 0])
+}
+
 pub fn sys_condvar_create(_arg: usize) -> isize {
     syscall(SYSCALL_CONDVAR_CREATE, [_arg, 0, 0])
 }
@@ -253,6 +442,45 @@ pub fn sys_condvar_signal(condvar_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_SIGNAL, [condvar_id, 0, 0])
 }
 
+pub fn sys_condvar_broadcast(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_BROADCAST, [condvar_id, 0, 0])
+}
+
+pub fn sys_condvar_destroy(condvar_id: usize) -> isize {
+    syscall(SYSCALL_CONDVAR_DESTROY, [condvar_id, 0, 0])
+}
+
 pub fn sys_condvar_wait(condvar_id: usize, mutex_id: usize) -> isize {
     syscall(SYSCALL_CONDVAR_WAIT, [condvar_id, mutex_id, 0])
 }
+
+pub fn sys_condvar_wait_timeout(condvar_id: usize, mutex_id: usize, timeout_ms: usize) -> isize {
+    syscall(
+        SYSCALL_CONDVAR_WAIT_TIMEOUT,
+        [condvar_id, mutex_id, timeout_ms],
+    )
+}
+
+pub fn sys_rwlock_create() -> isize {
+    syscall(SYSCALL_RWLOCK_CREATE, [0; 3])
+}
+
+pub fn sys_rwlock_read(id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_READ, [id, 0, 0])
+}
+
+pub fn sys_rwlock_write(id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_WRITE, [id, 0, 0])
+}
+
+pub fn sys_rwlock_unlock(id: usize) -> isize {
+    syscall(SYSCALL_RWLOCK_UNLOCK, [id, 0, 0])
+}
+
+pub fn sys_barrier_create(count: usize) -> isize {
+    syscall(SYSCALL_BARRIER_CREATE, [count, 0, 0])
+}
+
+pub fn sys_barrier_wait(id: usize) -> isize {
+    syscall(SYSCALL_BARRIER_WAIT, [id, 0, 0])
+}